@@ -35,7 +35,7 @@ impl<H: Hal> VirtIONet<'_, H> {
         debug!("Got MAC={:?}, status={:?}", mac, config.status.read());
 
         let queue_num = 2; // for simplicity
-        let recv_queue = VirtQueue::new(header, QUEUE_RECEIVE, queue_num)?;
+        let recv_queue = VirtQueue::new(header, QUEUE_RECEIVE, RECV_QUEUE_SIZE)?;
         let send_queue = VirtQueue::new(header, QUEUE_TRANSMIT, queue_num)?;
 
         header.finish_init();
@@ -83,6 +83,50 @@ impl<H: Hal> VirtIONet<'_, H> {
         Ok(len as usize - size_of::<Header>())
     }
 
+    /// Size of the virtio-net header in front of every received packet.
+    pub const fn header_size() -> usize {
+        size_of::<Header>()
+    }
+
+    /// Hand a receive buffer to the device without waiting for a packet.
+    ///
+    /// The first `header_size()` bytes of `buf` receive the virtio-net header
+    /// and the packet follows it.
+    ///
+    /// Up to `max_recv_buffers()` buffers can be outstanding at once. Returns
+    /// a token identifying the buffer, which `recv_poll` hands back.
+    ///
+    /// # Safety
+    ///
+    /// `buf` must stay valid and untouched until `recv_poll` returns its token.
+    pub unsafe fn recv_begin(&mut self, buf: &mut [u8]) -> Result<u16> {
+        if buf.len() <= size_of::<Header>() {
+            return Err(Error::BufferTooSmall);
+        }
+        let (header_buf, data) = buf.split_at_mut(size_of::<Header>());
+        let token = self.recv_queue.add(&[], &[header_buf, data])?;
+        self.header.notify(QUEUE_RECEIVE as u32);
+        Ok(token)
+    }
+
+    /// Take a packet received into one of the buffers given to `recv_begin`.
+    ///
+    /// Returns the buffer's token and the packet length, or `None` if nothing
+    /// has arrived yet.
+    pub fn recv_poll(&mut self) -> Result<Option<(u16, usize)>> {
+        if !self.recv_queue.can_pop() {
+            return Ok(None);
+        }
+        let (token, len) = self.recv_queue.pop_used()?;
+        Ok(Some((token, (len as usize).saturating_sub(size_of::<Header>()))))
+    }
+
+    /// Number of receive buffers that can be posted with `recv_begin`.
+    pub const fn max_recv_buffers() -> usize {
+        // every buffer takes a header and a data descriptor
+        RECV_QUEUE_SIZE as usize / 2
+    }
+
     /// Send a packet.
     pub fn send(&mut self, buf: &[u8]) -> Result {
         let header = unsafe { MaybeUninit::<Header>::zeroed().assume_init() };
@@ -214,4 +258,5 @@ enum GsoType {
 }
 
 const QUEUE_RECEIVE: usize = 0;
+const RECV_QUEUE_SIZE: u16 = 16;
 const QUEUE_TRANSMIT: usize = 1;
@@ -0,0 +1,413 @@
+//! FAT32 镜像一致性检查与修复
+//!
+//! 直接解析镜像中的 BPB、FAT 表与目录项, 不依赖 fatfs 库,
+//! 这样即使镜像已经损坏到无法挂载也能检查
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+const FAT_BAD: u32 = 0x0FFF_FFF7;
+const FAT_EOC: u32 = 0x0FFF_FFFF;
+const FAT32_CLEAN_SHUTDOWN_BIT: u32 = 0x0800_0000;
+const RESERVED_FAT_ENTRIES: u32 = 2;
+const BPB_STATUS_FLAGS_OFFSET: u64 = 0x41;
+const DIR_ENTRY_SIZE: usize = 32;
+const DIR_ENTRY_DELETED_FLAG: u8 = 0xE5;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_LFN: u8 = 0x0F;
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub lost_chains: u32,
+    pub lost_clusters: u32,
+    pub cross_links: u32,
+    pub bad_entries: u32,
+    pub free_clusters: u32,
+    pub was_dirty: bool,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.lost_clusters == 0 && self.cross_links == 0 && self.bad_entries == 0
+    }
+}
+
+struct Volume {
+    img: File,
+    cluster_size: u64,
+    fat_offset: u64,
+    fat_size: u64,
+    fats: u64,
+    data_offset: u64,
+    total_clusters: u32,
+    root_cluster: u32,
+    fs_info_offset: u64,
+    status_flags: u8,
+    fat: Vec<u32>,
+    fat_dirty: bool,
+}
+
+impl Volume {
+    fn open(path: &str, write: bool) -> io::Result<Self> {
+        let mut img = OpenOptions::new().read(true).write(write).open(path)?;
+        let mut boot = [0u8; 512];
+        img.read_exact(&mut boot)?;
+        let u16_at = |off: usize| u16::from_le_bytes([boot[off], boot[off + 1]]) as u64;
+        let u32_at = |off: usize| {
+            u32::from_le_bytes([boot[off], boot[off + 1], boot[off + 2], boot[off + 3]]) as u64
+        };
+        let bytes_per_sector = u16_at(11);
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved_sectors = u16_at(14);
+        let fats = boot[16] as u64;
+        let total_sectors = if u16_at(19) != 0 { u16_at(19) } else { u32_at(32) };
+        let sectors_per_fat = u32_at(36);
+        if bytes_per_sector == 0 || sectors_per_cluster == 0 || sectors_per_fat == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a FAT32 volume"));
+        }
+        let first_data_sector = reserved_sectors + fats * sectors_per_fat;
+        let total_clusters = ((total_sectors - first_data_sector) / sectors_per_cluster) as u32;
+        let mut volume = Self {
+            img,
+            cluster_size: bytes_per_sector * sectors_per_cluster,
+            fat_offset: reserved_sectors * bytes_per_sector,
+            fat_size: sectors_per_fat * bytes_per_sector,
+            fats,
+            data_offset: first_data_sector * bytes_per_sector,
+            total_clusters,
+            root_cluster: u32_at(44) as u32,
+            fs_info_offset: u16_at(48) * bytes_per_sector,
+            status_flags: boot[BPB_STATUS_FLAGS_OFFSET as usize],
+            fat: Vec::new(),
+            fat_dirty: false,
+        };
+        volume.load_fat()?;
+        Ok(volume)
+    }
+
+    fn load_fat(&mut self) -> io::Result<()> {
+        let entries = (self.total_clusters + RESERVED_FAT_ENTRIES) as usize;
+        let mut raw = vec![0u8; entries * 4];
+        self.img.seek(SeekFrom::Start(self.fat_offset))?;
+        self.img.read_exact(&mut raw)?;
+        self.fat = raw
+            .chunks(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        Ok(())
+    }
+
+    fn end_cluster(&self) -> u32 {
+        self.total_clusters + RESERVED_FAT_ENTRIES
+    }
+
+    fn valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= RESERVED_FAT_ENTRIES && cluster < self.end_cluster()
+    }
+
+    fn get(&self, cluster: u32) -> u32 {
+        self.fat[cluster as usize] & FAT_ENTRY_MASK
+    }
+
+    fn set(&mut self, cluster: u32, value: u32) {
+        let reserved = self.fat[cluster as usize] & !FAT_ENTRY_MASK;
+        self.fat[cluster as usize] = reserved | value;
+        self.fat_dirty = true;
+    }
+
+    fn next(&self, cluster: u32) -> Option<u32> {
+        match self.get(cluster) {
+            0 | FAT_BAD => None,
+            n if n >= 0x0FFF_FFF8 => None,
+            n => Some(n),
+        }
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_offset + (cluster - RESERVED_FAT_ENTRIES) as u64 * self.cluster_size
+    }
+
+    fn read_cluster(&mut self, cluster: u32, buf: &mut [u8]) -> io::Result<()> {
+        self.img.seek(SeekFrom::Start(self.cluster_offset(cluster)))?;
+        self.img.read_exact(buf)
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.img.seek(SeekFrom::Start(offset))?;
+        self.img.write_all(data)
+    }
+
+    fn flush(&mut self, free_clusters: u32) -> io::Result<()> {
+        if self.fat_dirty {
+            let raw: Vec<u8> = self.fat.iter().flat_map(|v| v.to_le_bytes()).collect();
+            for i in 0..self.fats {
+                let offset = self.fat_offset + i * self.fat_size;
+                self.write_at(offset, &raw)?;
+            }
+        }
+        // FsInfo: 空闲簇数在 488, 下一个空闲簇在 492
+        let next_free = (RESERVED_FAT_ENTRIES..self.end_cluster())
+            .find(|&c| self.get(c) == 0)
+            .unwrap_or(0xFFFF_FFFF);
+        let mut fs_info = free_clusters.to_le_bytes().to_vec();
+        fs_info.extend_from_slice(&next_free.to_le_bytes());
+        let offset = self.fs_info_offset + 488;
+        self.write_at(offset, &fs_info)?;
+        self.img.flush()
+    }
+}
+
+struct Checker {
+    volume: Volume,
+    owner: Vec<bool>,
+    repair: bool,
+    report: Report,
+}
+
+impl Checker {
+    /// 沿簇链标记已引用的簇, 遇到交叉链接、非法簇号或环时在修复模式下截断
+    /// 返回链上合法的簇
+    fn mark_chain(&mut self, first_cluster: u32, name: &str) -> Vec<u32> {
+        let mut chain = Vec::new();
+        let mut prev: Option<u32> = None;
+        let mut cluster = Some(first_cluster);
+        while let Some(n) = cluster {
+            let problem = if !self.volume.valid_cluster(n) {
+                self.report.bad_entries += 1;
+                Some("points to an invalid cluster")
+            } else if self.owner[n as usize] {
+                self.report.cross_links += 1;
+                Some("is cross-linked")
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                println!("{}: cluster chain {} at cluster {}", name, problem, n);
+                if self.repair {
+                    if let Some(p) = prev {
+                        self.volume.set(p, FAT_EOC);
+                    }
+                }
+                break;
+            }
+            self.owner[n as usize] = true;
+            chain.push(n);
+            prev = Some(n);
+            cluster = self.volume.next(n);
+        }
+        chain
+    }
+
+    /// 用显式的栈代替递归, 目录再深也不会栈溢出.
+    /// 已引用的簇即访问过的簇, 目录项指回祖先目录时不会重复进入
+    fn check_tree(&mut self, root_cluster: u32) -> io::Result<()> {
+        let mut pending = vec![(root_cluster, String::from("/"))];
+        while let Some((first, path)) = pending.pop() {
+            self.check_dir(first, &path, &mut pending)?;
+        }
+        Ok(())
+    }
+
+    /// 检查一个目录的目录项, 子目录放入 pending 等待检查
+    fn check_dir(
+        &mut self,
+        first_cluster: u32,
+        path: &str,
+        pending: &mut Vec<(u32, String)>,
+    ) -> io::Result<()> {
+        let chain = self.mark_chain(first_cluster, path);
+        let cluster_size = self.volume.cluster_size as usize;
+        let mut buf = vec![0u8; cluster_size];
+        'outer: for cluster in chain {
+            self.volume.read_cluster(cluster, &mut buf)?;
+            let mut dirty = false;
+            let mut lfn_name: Vec<u16> = Vec::new();
+            for i in 0..cluster_size / DIR_ENTRY_SIZE {
+                let raw = &mut buf[i * DIR_ENTRY_SIZE..(i + 1) * DIR_ENTRY_SIZE];
+                if raw[0] == 0 {
+                    if dirty {
+                        let offset = self.volume.cluster_offset(cluster);
+                        self.volume.write_at(offset, &buf)?;
+                    }
+                    break 'outer;
+                }
+                let attrs = raw[11];
+                if raw[0] == DIR_ENTRY_DELETED_FLAG || attrs & ATTR_VOLUME_ID != 0 && attrs & ATTR_LFN != ATTR_LFN {
+                    lfn_name.clear();
+                    continue;
+                }
+                if attrs & ATTR_LFN == ATTR_LFN {
+                    // 长文件名片段按逆序保存
+                    let mut part: Vec<u16> = Vec::new();
+                    for range in [(1, 11), (14, 26), (28, 32)] {
+                        for c in raw[range.0..range.1].chunks(2) {
+                            part.push(u16::from_le_bytes([c[0], c[1]]));
+                        }
+                    }
+                    part.retain(|&c| c != 0 && c != 0xFFFF);
+                    part.extend_from_slice(&lfn_name);
+                    lfn_name = part;
+                    continue;
+                }
+                if raw[0] == b'.' {
+                    lfn_name.clear();
+                    continue;
+                }
+                let entry_name = if lfn_name.is_empty() {
+                    String::from_utf8_lossy(&raw[0..11]).trim().to_string()
+                } else {
+                    String::from_utf16_lossy(&lfn_name)
+                };
+                lfn_name.clear();
+                let name = format!("{}/{}", path.trim_end_matches('/'), entry_name);
+                let hi = u16::from_le_bytes([raw[20], raw[21]]) as u32;
+                let lo = u16::from_le_bytes([raw[26], raw[27]]) as u32;
+                let first = (hi << 16) | lo;
+                let size = u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]) as u64;
+                let is_dir = attrs & ATTR_DIRECTORY != 0;
+
+                let bad_start = first != 0
+                    && (!self.volume.valid_cluster(first) || self.volume.get(first) == 0);
+                if bad_start || (first == 0 && (is_dir || size != 0)) {
+                    println!("{}: bad first cluster {} (size {})", name, first, size);
+                    self.report.bad_entries += 1;
+                    if self.repair {
+                        if is_dir {
+                            raw[0] = DIR_ENTRY_DELETED_FLAG;
+                        } else {
+                            raw[20..22].fill(0);
+                            raw[26..28].fill(0);
+                            raw[28..32].fill(0);
+                        }
+                        dirty = true;
+                    }
+                    continue;
+                }
+                if first == 0 {
+                    continue;
+                }
+                if is_dir {
+                    if self.owner[first as usize] {
+                        println!("{}: directory loops back to cluster {}", name, first);
+                        self.report.cross_links += 1;
+                        if self.repair {
+                            raw[0] = DIR_ENTRY_DELETED_FLAG;
+                            dirty = true;
+                        }
+                    } else {
+                        pending.push((first, name));
+                    }
+                    continue;
+                }
+                let chain = self.mark_chain(first, &name);
+                let len = chain.len() as u64;
+                let needed = (size + cluster_size as u64 - 1) / cluster_size as u64;
+                if len != needed {
+                    println!(
+                        "{}: size {} needs {} clusters but chain has {}",
+                        name, size, needed, len
+                    );
+                    self.report.bad_entries += 1;
+                    if self.repair {
+                        if needed == 0 {
+                            // 空文件不占簇, 整条链在丢失簇扫描中被回收
+                            for &c in &chain {
+                                self.owner[c as usize] = false;
+                            }
+                            raw[20..22].fill(0);
+                            raw[26..28].fill(0);
+                            dirty = true;
+                        } else if len > needed {
+                            // 释放多余的簇, 它们会在丢失簇扫描中被回收
+                            let keep = needed as usize;
+                            self.volume.set(chain[keep - 1], FAT_EOC);
+                            for &c in &chain[keep..] {
+                                self.owner[c as usize] = false;
+                            }
+                        } else {
+                            let new_size = (len * cluster_size as u64) as u32;
+                            raw[28..32].copy_from_slice(&new_size.to_le_bytes());
+                            dirty = true;
+                        }
+                    }
+                }
+            }
+            if dirty {
+                let offset = self.volume.cluster_offset(cluster);
+                self.volume.write_at(offset, &buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_lost_chains(&mut self) {
+        let lost: Vec<u32> = (RESERVED_FAT_ENTRIES..self.volume.end_cluster())
+            .filter(|&c| {
+                let val = self.volume.get(c);
+                val != 0 && val != FAT_BAD && !self.owner[c as usize]
+            })
+            .collect();
+        if lost.is_empty() {
+            return;
+        }
+        // 链头: 没有其他丢失的簇指向它
+        let mut pointed = vec![false; self.owner.len()];
+        for &c in &lost {
+            if let Some(n) = self.volume.next(c) {
+                if self.volume.valid_cluster(n) {
+                    pointed[n as usize] = true;
+                }
+            }
+        }
+        self.report.lost_clusters = lost.len() as u32;
+        self.report.lost_chains = lost.iter().filter(|&&c| !pointed[c as usize]).count() as u32;
+        println!(
+            "found {} lost clusters in {} chains",
+            self.report.lost_clusters, self.report.lost_chains
+        );
+        if self.repair {
+            for c in lost {
+                self.volume.set(c, 0);
+            }
+        }
+    }
+}
+
+/// 检查镜像, `repair` 为 true 时修复发现的问题并清除 dirty 标志
+pub fn fsck(path: &str, repair: bool) -> io::Result<Report> {
+    let volume = Volume::open(path, repair)?;
+    let entries = volume.end_cluster() as usize;
+    let was_dirty =
+        volume.status_flags & 1 != 0 || volume.fat[1] & FAT32_CLEAN_SHUTDOWN_BIT == 0;
+    let root = volume.root_cluster;
+    let mut checker = Checker {
+        volume,
+        owner: vec![false; entries],
+        repair,
+        report: Report {
+            was_dirty,
+            ..Report::default()
+        },
+    };
+    if was_dirty {
+        println!("volume was not cleanly unmounted");
+    }
+    checker.check_tree(root)?;
+    checker.check_lost_chains();
+    let free = (RESERVED_FAT_ENTRIES..checker.volume.end_cluster())
+        .filter(|&c| checker.volume.get(c) == 0)
+        .count() as u32;
+    checker.report.free_clusters = free;
+    if repair {
+        let volume = &mut checker.volume;
+        if volume.fat[1] & FAT32_CLEAN_SHUTDOWN_BIT == 0 {
+            volume.fat[1] |= FAT32_CLEAN_SHUTDOWN_BIT;
+            volume.fat_dirty = true;
+        }
+        volume.flush(free)?;
+        let flags = volume.status_flags & !1;
+        volume.write_at(BPB_STATUS_FLAGS_OFFSET, &[flags])?;
+    }
+    Ok(checker.report)
+}
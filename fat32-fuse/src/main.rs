@@ -4,6 +4,8 @@ use std::io::{self, Read};
 use fscommon::BufStream;
 use fatfs::{format_volume,FormatVolumeOptions,StdIoWrapper,FsOptions,FileSystem,Write, FatType};
 
+mod fsck;
+
 fn fat32_packup(){
     //cargo run --后面再使用这些参数
     let matches = App::new("Fat32 packer")
@@ -32,7 +34,27 @@ fn fat32_packup(){
             .short("b")
             .long("bin")
             .help("use when source is binary"))        
+        //检查已有镜像, 不打包
+        .arg(Arg::with_name("check")
+            .short("c")
+            .long("check")
+            .takes_value(true)
+            .help("check an existing img"))
+        //与check一起使用, 修复发现的问题
+        .arg(Arg::with_name("repair")
+            .short("r")
+            .long("repair")
+            .requires("check")
+            .help("repair the img checked by --check"))
         .get_matches();
+    if let Some(img) = matches.value_of("check") {
+        let report = fsck::fsck(img, matches.is_present("repair")).unwrap();
+        println!("{:#?}", report);
+        if !report.is_clean() && !matches.is_present("repair") {
+            std::process::exit(1);
+        }
+        return;
+    }
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
     let output = matches.value_of("output").unwrap();
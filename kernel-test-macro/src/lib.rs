@@ -0,0 +1,52 @@
+//! `#[kernel_test]`: 把无参数的函数注册为内核单元测试
+//!
+//! 函数与注册项只在启用内核的 `test` feature 或 `cargo test` 时编译.
+//! 注册项是放在 `.kernel_tests` 段中的 `crate::ktest::KernelTest`, 内核从链接脚本
+//! 给出的段边界读出所有测试; `cargo test` 时它同时是 custom_test_frameworks 的 `#[test_case]`
+use proc_macro::{TokenStream, TokenTree};
+
+#[proc_macro_attribute]
+pub fn kernel_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return compile_error("#[kernel_test] does not take arguments");
+    }
+    let name = match fn_name(&item) {
+        Some(name) => name,
+        None => return compile_error("#[kernel_test] can only be applied to a function"),
+    };
+    let registration = format!(
+        r#"
+        #[cfg(any(test, feature = "test"))]
+        #[cfg_attr(test, test_case)]
+        #[used]
+        #[link_section = ".kernel_tests"]
+        #[allow(non_upper_case_globals)]
+        static __kernel_test_{name}: crate::ktest::KernelTest = crate::ktest::KernelTest {{
+            name: concat!(module_path!(), "::", "{name}"),
+            func: {name},
+        }};
+        "#
+    );
+    let mut output: TokenStream = r#"#[cfg(any(test, feature = "test"))]"#.parse().unwrap();
+    output.extend(item);
+    output.extend(registration.parse::<TokenStream>().unwrap());
+    output
+}
+
+/// `fn` 之后的第一个标识符
+fn fn_name(item: &TokenStream) -> Option<String> {
+    let mut tokens = item.clone().into_iter();
+    while let Some(token) = tokens.next() {
+        if matches!(&token, TokenTree::Ident(ident) if ident.to_string() == "fn") {
+            return match tokens.next() {
+                Some(TokenTree::Ident(name)) => Some(name.to_string()),
+                _ => None,
+            };
+        }
+    }
+    None
+}
+
+fn compile_error(message: &str) -> TokenStream {
+    format!("compile_error!({:?});", message).parse().unwrap()
+}
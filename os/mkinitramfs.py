@@ -0,0 +1,35 @@
+import os
+import sys
+
+# 生成 cpio newc 格式的 initramfs, 内核找不到根文件系统中的 init 时使用
+# 用法: python3 mkinitramfs.py 输出文件 程序...
+# 程序都放在归档的 bin 目录下, 文件名不变
+BIN_DIR = "bin"
+
+
+def align4(data):
+    return data + b"\0" * (-len(data) % 4)
+
+
+def entry(ino, name, mode, data):
+    name = name.encode() + b"\0"
+    fields = [ino, mode, 0, 0, 1, 0, len(data), 0, 0, 0, 0, len(name), 0]
+    header = b"070701" + b"".join(b"%08X" % field for field in fields)
+    return align4(header + name) + align4(data)
+
+
+if __name__ == "__main__":
+    if len(sys.argv) < 3:
+        print("用法: python3 mkinitramfs.py 输出文件 程序...")
+        sys.exit(1)
+
+    archive = entry(1, BIN_DIR, 0o040755, b"")
+    for ino, path in enumerate(sys.argv[2:], start=2):
+        with open(path, "rb") as f:
+            name = f"{BIN_DIR}/{os.path.basename(path)}"
+            archive += entry(ino, name, 0o100755, f.read())
+    archive += entry(0, "TRAILER!!!", 0, b"")
+
+    with open(sys.argv[1], "wb") as f:
+        f.write(archive)
+    print(f"{len(sys.argv) - 2} files packed into {sys.argv[1]}")
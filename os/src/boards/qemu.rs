@@ -1,9 +1,8 @@
-pub const CLOCK_FREQ: usize = 12500000;
-pub const MEMORY_END: usize = 0x8800_0000;
-
-pub const MMIO: &[(usize, usize)] = &[
-    (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
-    (0x1000_1000, 0x00_1000), // Virtio Block in virt machine
-];
-
+// 内存、时钟频率和外设地址都在启动时从设备树中读出, 见 fdt 模块
 pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
+pub type RtcDeviceImpl = crate::drivers::rtc::GoldfishRtc;
+pub type CharDeviceImpl = crate::drivers::chardev::NS16550a;
+pub type NetDeviceImpl = crate::drivers::net::VirtIONetDevice;
+pub type InputDeviceImpl = crate::drivers::input::VirtIOInputDevice;
+#[cfg(feature = "graphics")]
+pub type GpuDeviceImpl = crate::drivers::gpu::VirtIOGpuDevice;
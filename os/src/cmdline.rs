@@ -0,0 +1,49 @@
+//! 内核命令行, 来自设备树 /chosen/bootargs, 在 QEMU 中由 `make run BOOTARGS=...` 指定
+//!
+//! - `root=` 根文件系统所在的卷, 例如 vda1、LABEL=name
+//! - `init=` 第一个用户程序的路径, 默认 /bin/initproc
+//! - `loglevel=` 日志过滤规则, 格式与 /proc/sys/kernel/loglevel 相同, 覆盖编译时的 LOG
+//! - `sched=rr|fifo` 调度策略, 默认 rr
+//! - `testsuite=auto|目录` 与 `testsuite_timeout=秒数` 由 initproc 读取 /proc/cmdline 处理,
+//!   运行目录 (auto 为根目录) 中的所有测试程序后关机
+use crate::fdt::MACHINE;
+use crate::logging;
+use log::warn;
+
+const KNOWN_PARAMS: &[&str] = &[
+    "root",
+    "init",
+    "loglevel",
+    "sched",
+    "testsuite",
+    "testsuite_timeout",
+];
+
+pub fn cmdline() -> &'static str {
+    MACHINE.bootargs.trim()
+}
+
+/// `key=value` 中的 value, 同一个参数出现多次时以最后一次为准
+pub fn param(key: &str) -> Option<&'static str> {
+    cmdline()
+        .split_whitespace()
+        .filter_map(|arg| arg.split_once('='))
+        .filter(|(name, _)| *name == key)
+        .map(|(_, value)| value)
+        .last()
+}
+
+/// 在日志初始化之后调用, 不认识的参数只给出警告
+pub fn init() {
+    for arg in cmdline().split_whitespace() {
+        let name = arg.split_once('=').map_or(arg, |(name, _)| name);
+        if !KNOWN_PARAMS.contains(&name) {
+            warn!("unknown kernel parameter {}", arg);
+        }
+    }
+    if let Some(spec) = param("loglevel") {
+        if !logging::set_filter_spec(spec) {
+            warn!("invalid loglevel={}", spec);
+        }
+    }
+}
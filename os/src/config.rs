@@ -2,11 +2,17 @@
 
 pub const USER_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+/// 最多使用的 hart 数, 每个 hart 有自己的启动栈
+pub const MAX_HARTS: usize = 8;
 pub const KERNEL_HEAP_SIZE: usize = 0x20_0000;
+/// 每个进程的文件描述符上限, 即 RLIMIT_NOFILE
+pub const MAX_FD: usize = 128;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
-
-pub use crate::board::{CLOCK_FREQ, MEMORY_END, MMIO};
+/// mmap 没有指定地址时从这里开始分配
+pub const MMAP_BASE: usize = 0x10_0000_0000;
+/// 用户可以 mmap 的地址上界. Sv39 的高半部分放着跳板和 TrapContext
+pub const USER_SPACE_END: usize = 0x40_0000_0000;
@@ -1,13 +1,15 @@
-use crate::sbi::console_putchar;
+use crate::drivers::chardev::{CharDevice, UART};
 use core::fmt::{self, Write};
 
 struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
+        for c in s.bytes() {
+            UART.putchar_sync(c);
         }
+        #[cfg(feature = "graphics")]
+        crate::drivers::gpu::console_write(s.as_bytes());
         Ok(())
     }
 }
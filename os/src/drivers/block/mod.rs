@@ -1,29 +1,147 @@
-mod virtio_blk;
 mod block_device;
+mod partition;
+#[cfg(any(test, feature = "test"))]
+mod ram_disk;
+mod virtio_blk;
 
-pub use virtio_blk::VirtIOBlock;
+pub use virtio_blk::{VirtIOBlock, VirtioHal};
 pub use block_device::BlockDevice; //这里从easyfs替换为同一目录下的Blockevice,也要给其他文件用
+use super::virtio_slots;
 use crate::board::BlockDeviceImpl;
+use crate::cmdline;
+use crate::ktest::kernel_test;
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use partition::{is_fat_boot_sector, parse_partition_table, Partition};
+use virtio_drivers::DeviceType;
 
 use lazy_static::*;
 
+/// 没有 root= 启动参数时, 优先使用带有这个标签的分区作为根文件系统
+const ROOT_LABEL: &str = "rootfs";
+
+/// 一块磁盘或其上的一个分区
+pub struct BlockVolume {
+    /// 与 Linux 相同, 磁盘为 vda, vdb, ..., 分区为 vda1, vda2, ...
+    pub name: String,
+    /// GPT 分区名, 没有时为 FAT 卷标
+    pub label: String,
+    pub is_partition: bool,
+    pub device: Arc<dyn BlockDevice>,
+}
+
 lazy_static! {
-    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+    /// 按槽位地址排列的所有磁盘, 每块磁盘后面紧跟它的分区
+    pub static ref BLOCK_VOLUMES: Vec<BlockVolume> = probe_volumes();
+    /// 根文件系统所在的卷
+    static ref ROOT_VOLUME: &'static BlockVolume = root_volume();
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = ROOT_VOLUME.device.clone();
 }
 
-#[allow(unused)]
-pub fn block_device_test() {
-    let block_device = BLOCK_DEVICE.clone();
-    let mut write_buffer = [0u8; 512];
-    let mut read_buffer = [0u8; 512];
-    for i in 0..512 {
-        for byte in write_buffer.iter_mut() {
-            *byte = i as u8;
+fn probe_volumes() -> Vec<BlockVolume> {
+    let mut volumes = Vec::new();
+    let disks = virtio_slots(DeviceType::Block)
+        .iter()
+        .filter_map(|slot| BlockDeviceImpl::probe(slot.base))
+        .collect::<Vec<_>>();
+    for (i, disk) in disks.into_iter().enumerate() {
+        let disk: Arc<dyn BlockDevice> = Arc::new(disk);
+        let disk_name = format!("vd{}", (b'a' + i as u8) as char);
+        let partitions = parse_partition_table(&disk);
+        volumes.push(BlockVolume {
+            name: disk_name.clone(),
+            label: fat_label(&disk).unwrap_or_default(),
+            is_partition: false,
+            device: disk.clone(),
+        });
+        for (j, entry) in partitions.iter().enumerate() {
+            let device: Arc<dyn BlockDevice> = Arc::new(Partition::new(disk.clone(), entry));
+            let label = if entry.label.is_empty() {
+                fat_label(&device).unwrap_or_default()
+            } else {
+                entry.label.clone()
+            };
+            volumes.push(BlockVolume {
+                name: format!("{}{}", disk_name, j + 1),
+                label,
+                is_partition: true,
+                device,
+            });
         }
-        block_device.write_block(i as usize, &write_buffer);
-        block_device.read_block(i as usize, &mut read_buffer);
-        assert_eq!(write_buffer, read_buffer);
     }
-    println!("block device test passed!");
+    volumes
+}
+
+/// FAT 引导扇区中的卷标, 不是 FAT 卷时返回 None
+fn fat_label(device: &Arc<dyn BlockDevice>) -> Option<String> {
+    let mut sector = [0u8; 512];
+    device.read_block(0, &mut sector);
+    if !is_fat_boot_sector(&sector) {
+        return None;
+    }
+    // FAT32 的 BPB 中每 FAT 扇区数 (16 位) 为 0, 扩展 BPB 的位置不同
+    let offset = if sector[22] == 0 && sector[23] == 0 { 71 } else { 43 };
+    let label = String::from_utf8_lossy(&sector[offset..offset + 11]);
+    Some(String::from(label.trim_end()))
+}
+
+fn is_fat(volume: &BlockVolume) -> bool {
+    fat_label(&volume.device).is_some()
+}
+
+/// root= 可以是 vda1 或 /dev/vda1, 也可以是 LABEL=name 或 PARTLABEL=name
+fn root_volume() -> &'static BlockVolume {
+    let root_arg = cmdline::param("root");
+    let found = match root_arg {
+        Some(arg) => {
+            let found = if let Some(label) = arg.strip_prefix("PARTLABEL=") {
+                BLOCK_VOLUMES
+                    .iter()
+                    .find(|volume| volume.is_partition && volume.label == label)
+            } else if let Some(label) = arg.strip_prefix("LABEL=") {
+                BLOCK_VOLUMES.iter().find(|volume| volume.label == label)
+            } else {
+                let name = arg.strip_prefix("/dev/").unwrap_or(arg);
+                BLOCK_VOLUMES.iter().find(|volume| volume.name == name)
+            };
+            if found.is_none() {
+                println!("[kernel] root={} not found, falling back", arg);
+            }
+            found
+        }
+        None => None,
+    };
+    // 依次尝试: 带 ROOT_LABEL 标签的卷, 第一个 FAT 分区, 整块是 FAT 的磁盘
+    found
+        .or_else(|| BLOCK_VOLUMES.iter().find(|volume| volume.label == ROOT_LABEL))
+        .or_else(|| {
+            BLOCK_VOLUMES
+                .iter()
+                .find(|volume| volume.is_partition && is_fat(volume))
+        })
+        .or_else(|| BLOCK_VOLUMES.iter().find(|volume| is_fat(volume)))
+        .or_else(|| BLOCK_VOLUMES.first())
+        .expect("no virtio-blk device found")
+}
+
+/// 打印所有磁盘和分区, 以及选中的根文件系统
+pub fn init() {
+    for volume in BLOCK_VOLUMES.iter() {
+        if volume.label.is_empty() {
+            println!("[kernel] block device {}", volume.name);
+        } else {
+            println!("[kernel] block device {} ({})", volume.name, volume.label);
+        }
+    }
+    println!("[kernel] root volume: {}", ROOT_VOLUME.name);
+}
+
+/// 根卷已经挂载, 绕过块缓存时只能读它
+#[kernel_test]
+fn root_volume_read_test() {
+    let mut boot_sector = [0u8; 512];
+    BLOCK_DEVICE.read_block(0, &mut boot_sector);
+    assert!(is_fat_boot_sector(&boot_sector));
 }
@@ -0,0 +1,264 @@
+//! MBR 与 GPT 分区表, 每个分区作为一个独立的块设备
+use super::BlockDevice;
+use crate::ktest::kernel_test;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use log::error;
+
+const BLOCK_SIZE: usize = 512;
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_ENTRIES_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// 保护性 MBR, 真正的分区表是 GPT
+const MBR_TYPE_GPT: u8 = 0xee;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_NAME_OFFSET: usize = 56;
+const GPT_NAME_LEN: usize = 36;
+/// 分区表项数的上限, 损坏的 GPT 头不会让这里读上亿个扇区
+const GPT_MAX_ENTRIES: usize = 128;
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn le64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// 分区表中的一项, 位置以扇区为单位
+pub struct PartitionEntry {
+    pub start: usize,
+    pub blocks: usize,
+    /// GPT 分区名, MBR 分区没有名字
+    pub label: String,
+}
+
+/// 把块号加上分区起始扇区后转发给整块磁盘
+pub struct Partition {
+    disk: Arc<dyn BlockDevice>,
+    start: usize,
+    blocks: usize,
+}
+
+impl Partition {
+    pub fn new(disk: Arc<dyn BlockDevice>, entry: &PartitionEntry) -> Self {
+        Self {
+            disk,
+            start: entry.start,
+            blocks: entry.blocks,
+        }
+    }
+}
+
+/// 越过分区末尾的访问不会到达磁盘上的其他分区: 读到全 0, 写入被丢弃
+impl BlockDevice for Partition {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        if block_id >= self.blocks {
+            error!(
+                "[partition] read block {} beyond the end ({} blocks)",
+                block_id, self.blocks
+            );
+            buf.fill(0);
+            return;
+        }
+        self.disk.read_block(self.start + block_id, buf);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        if block_id >= self.blocks {
+            error!(
+                "[partition] write block {} beyond the end ({} blocks)",
+                block_id, self.blocks
+            );
+            return;
+        }
+        self.disk.write_block(self.start + block_id, buf);
+    }
+}
+
+/// 扇区 0 是 FAT 引导扇区时, 整块磁盘就是一个卷, 0x55aa 并不代表 MBR
+pub fn is_fat_boot_sector(sector: &[u8]) -> bool {
+    let jump = sector[0] == 0xeb || sector[0] == 0xe9;
+    let bytes_per_sector = u16::from_le_bytes([sector[11], sector[12]]);
+    jump && bytes_per_sector == BLOCK_SIZE as u16 && sector[510..512] == MBR_SIGNATURE
+}
+
+/// 读取磁盘上的分区表, 没有分区表时返回空
+pub fn parse_partition_table(disk: &Arc<dyn BlockDevice>) -> Vec<PartitionEntry> {
+    let mut mbr = [0u8; BLOCK_SIZE];
+    disk.read_block(0, &mut mbr);
+    if mbr[510..512] != MBR_SIGNATURE || is_fat_boot_sector(&mbr) {
+        return Vec::new();
+    }
+    let entries: Vec<&[u8]> = (0..4)
+        .map(|i| {
+            let offset = MBR_ENTRIES_OFFSET + i * MBR_ENTRY_SIZE;
+            &mbr[offset..offset + MBR_ENTRY_SIZE]
+        })
+        .collect();
+    // 引导标志只能是 0 或 0x80, 否则这个扇区不是 MBR
+    if entries.iter().any(|entry| entry[0] & 0x7f != 0) {
+        return Vec::new();
+    }
+    if entries.iter().any(|entry| entry[4] == MBR_TYPE_GPT) {
+        return parse_gpt(disk);
+    }
+    // 扩展分区中的逻辑分区暂不支持
+    entries
+        .iter()
+        .filter(|entry| entry[4] != MBR_TYPE_EMPTY && !MBR_TYPE_EXTENDED.contains(&entry[4]))
+        .map(|entry| PartitionEntry {
+            start: le32(entry, 8) as usize,
+            blocks: le32(entry, 12) as usize,
+            label: String::new(),
+        })
+        .filter(|entry| entry.blocks > 0)
+        .collect()
+}
+
+fn parse_gpt(disk: &Arc<dyn BlockDevice>) -> Vec<PartitionEntry> {
+    let mut header = [0u8; BLOCK_SIZE];
+    disk.read_block(1, &mut header);
+    if &header[0..8] != GPT_SIGNATURE {
+        return Vec::new();
+    }
+    let entries_lba = le64(&header, 72) as usize;
+    let entry_count = (le32(&header, 80) as usize).min(GPT_MAX_ENTRIES);
+    let entry_size = le32(&header, 84) as usize;
+    if entry_size < GPT_NAME_OFFSET + GPT_NAME_LEN * 2 || BLOCK_SIZE % entry_size != 0 {
+        return Vec::new();
+    }
+    let entries_per_block = BLOCK_SIZE / entry_size;
+    let mut partitions = Vec::new();
+    let mut block = [0u8; BLOCK_SIZE];
+    for i in 0..entry_count {
+        if i % entries_per_block == 0 {
+            disk.read_block(entries_lba + i / entries_per_block, &mut block);
+        }
+        let offset = i % entries_per_block * entry_size;
+        let entry = &block[offset..offset + entry_size];
+        // 类型 GUID 全 0 表示未使用
+        if entry[0..16].iter().all(|&b| b == 0) {
+            continue;
+        }
+        let first = le64(entry, 32) as usize;
+        let last = le64(entry, 40) as usize;
+        if last < first {
+            error!(
+                "[partition] ignore GPT entry {}: last LBA {} < first LBA {}",
+                i, last, first
+            );
+            continue;
+        }
+        let name: Vec<u16> = (0..GPT_NAME_LEN)
+            .map(|j| {
+                u16::from_le_bytes([
+                    entry[GPT_NAME_OFFSET + j * 2],
+                    entry[GPT_NAME_OFFSET + j * 2 + 1],
+                ])
+            })
+            .take_while(|&c| c != 0)
+            .collect();
+        partitions.push(PartitionEntry {
+            start: first,
+            blocks: last + 1 - first,
+            label: String::from_utf16_lossy(&name),
+        });
+    }
+    partitions
+}
+
+/// 在 RamDisk 上构造分区表, 不碰真正的磁盘
+#[cfg(any(test, feature = "test"))]
+mod tests {
+    use super::super::ram_disk::RamDisk;
+    use super::*;
+
+    fn mbr_entry(mbr: &mut [u8], index: usize, kind: u8, start: u32, blocks: u32) {
+        let entry = &mut mbr[MBR_ENTRIES_OFFSET + index * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+        entry[4] = kind;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&blocks.to_le_bytes());
+    }
+
+    fn gpt_entry(block: &mut [u8], index: usize, first: u64, last: u64, name: &str) {
+        let entry = &mut block[index * 128..][..128];
+        entry[0] = 1;
+        entry[32..40].copy_from_slice(&first.to_le_bytes());
+        entry[40..48].copy_from_slice(&last.to_le_bytes());
+        for (j, c) in name.encode_utf16().enumerate() {
+            entry[GPT_NAME_OFFSET + j * 2..][..2].copy_from_slice(&c.to_le_bytes());
+        }
+    }
+
+    #[kernel_test]
+    fn mbr_test() {
+        let disk: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(64));
+        assert!(parse_partition_table(&disk).is_empty());
+        let mut mbr = [0u8; BLOCK_SIZE];
+        mbr[510..512].copy_from_slice(&MBR_SIGNATURE);
+        mbr_entry(&mut mbr, 0, 0x0c, 8, 16);
+        mbr_entry(&mut mbr, 1, MBR_TYPE_EXTENDED[0], 24, 8);
+        mbr_entry(&mut mbr, 3, 0x83, 32, 32);
+        disk.write_block(0, &mbr);
+        let entries = parse_partition_table(&disk);
+        let ranges: Vec<_> = entries.iter().map(|e| (e.start, e.blocks)).collect();
+        assert_eq!(ranges, [(8, 16), (32, 32)]);
+
+        // 分区内的块号从分区起始处算起, 越界的访问不会落到下一个分区
+        let partition = Partition::new(disk.clone(), &entries[0]);
+        let mut buf = [7u8; BLOCK_SIZE];
+        partition.write_block(0, &buf);
+        partition.write_block(16, &[9u8; BLOCK_SIZE]);
+        disk.read_block(8, &mut buf);
+        assert_eq!(buf, [7u8; BLOCK_SIZE]);
+        disk.read_block(24, &mut buf);
+        assert_eq!(buf, [0u8; BLOCK_SIZE]);
+        partition.read_block(16, &mut buf);
+        assert_eq!(buf, [0u8; BLOCK_SIZE]);
+    }
+
+    #[kernel_test]
+    fn gpt_test() {
+        let disk: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(64));
+        let mut mbr = [0u8; BLOCK_SIZE];
+        mbr[510..512].copy_from_slice(&MBR_SIGNATURE);
+        mbr_entry(&mut mbr, 0, MBR_TYPE_GPT, 1, 63);
+        disk.write_block(0, &mbr);
+        let mut header = [0u8; BLOCK_SIZE];
+        header[0..8].copy_from_slice(GPT_SIGNATURE);
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&u32::MAX.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        disk.write_block(1, &header);
+        let mut block = [0u8; BLOCK_SIZE];
+        gpt_entry(&mut block, 0, 34, 47, "rootfs");
+        // 损坏的表项: 结束扇区在起始扇区之前
+        gpt_entry(&mut block, 1, 48, 40, "broken");
+        gpt_entry(&mut block, 3, 48, 63, "");
+        disk.write_block(2, &block);
+        let entries = parse_partition_table(&disk);
+        let parsed: Vec<_> = entries
+            .iter()
+            .map(|e| (e.start, e.blocks, e.label.as_str()))
+            .collect();
+        assert_eq!(parsed, [(34, 14, "rootfs"), (48, 16, "")]);
+    }
+
+    #[kernel_test]
+    fn fat_boot_sector_test() {
+        let disk: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(8));
+        let mut sector = [0u8; BLOCK_SIZE];
+        sector[0] = 0xeb;
+        sector[11..13].copy_from_slice(&(BLOCK_SIZE as u16).to_le_bytes());
+        sector[510..512].copy_from_slice(&MBR_SIGNATURE);
+        // 看起来像分区表项的字节不影响判断
+        mbr_entry(&mut sector, 0, 0x0c, 1, 7);
+        disk.write_block(0, &sector);
+        assert!(parse_partition_table(&disk).is_empty());
+    }
+}
@@ -0,0 +1,45 @@
+//! 内存中的块设备, 内核测试用它代替真正的磁盘
+use super::BlockDevice;
+use crate::ktest::kernel_test;
+use crate::sync::SpinNoIrq;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const BLOCK_SIZE: usize = 512;
+
+pub struct RamDisk {
+    blocks: SpinNoIrq<Vec<[u8; BLOCK_SIZE]>>,
+}
+
+impl RamDisk {
+    /// 内容全为 0
+    pub fn new(blocks: usize) -> Self {
+        Self {
+            blocks: SpinNoIrq::new(vec![[0u8; BLOCK_SIZE]; blocks]),
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.blocks.lock()[block_id]);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.blocks.lock()[block_id].copy_from_slice(buf);
+    }
+}
+
+#[kernel_test]
+fn ram_disk_test() {
+    let ram_disk = RamDisk::new(8);
+    let mut write_buffer = [0u8; BLOCK_SIZE];
+    let mut read_buffer = [0u8; BLOCK_SIZE];
+    for i in 0..8 {
+        write_buffer.fill(i as u8);
+        ram_disk.write_block(i, &write_buffer);
+    }
+    for i in 0..8 {
+        ram_disk.read_block(i, &mut read_buffer);
+        assert_eq!(read_buffer, [i as u8; BLOCK_SIZE]);
+    }
+}
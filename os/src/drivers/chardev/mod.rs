@@ -0,0 +1,31 @@
+mod ns16550a;
+
+pub use ns16550a::NS16550a;
+use crate::board::CharDeviceImpl;
+use crate::fdt::MACHINE;
+use super::irq::register_irq;
+use alloc::sync::Arc;
+
+use lazy_static::*;
+
+pub trait CharDevice: Send + Sync {
+    /// 阻塞直到至少读到一个字节, 返回读到的字节数
+    fn read(&self, buf: &mut [u8]) -> usize;
+    fn write(&self, buf: &[u8]);
+    /// 不经过缓冲区直接轮询发送, 用于内核打印与 panic
+    fn putchar_sync(&self, c: u8);
+}
+
+/// 接收设备输入的上层 (如终端行规程), 在中断上下文中被调用
+pub trait InputSink: Send + Sync {
+    fn push_input(&self, c: u8);
+}
+
+lazy_static! {
+    pub static ref UART: Arc<CharDeviceImpl> = Arc::new(CharDeviceImpl::new());
+}
+
+pub fn init() {
+    UART.init();
+    register_irq(MACHINE.uart_irq, UART.clone());
+}
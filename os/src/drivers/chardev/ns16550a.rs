@@ -0,0 +1,186 @@
+use super::{CharDevice, InputSink};
+use crate::drivers::IrqHandler;
+use crate::fdt::MACHINE;
+use crate::sync::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::ptr::{read_volatile, write_volatile};
+
+// 寄存器偏移, DLAB = 0 时
+const RBR: usize = 0; // 接收缓冲 (读)
+const THR: usize = 0; // 发送保持 (写)
+const IER: usize = 1; // 中断使能
+const FCR: usize = 2; // FIFO 控制 (写)
+const LCR: usize = 3; // 线路控制
+const MCR: usize = 4; // Modem 控制
+const LSR: usize = 5; // 线路状态
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const IER_TX_EMPTY: u8 = 1 << 1;
+const FCR_ENABLE_AND_CLEAR: u8 = 0b111;
+const LCR_8N1: u8 = 0b11;
+const MCR_DTR_RTS_OUT2: u8 = 0b1011;
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+const RX_BUFFER_SIZE: usize = 1024;
+const TX_BUFFER_SIZE: usize = 4096;
+
+struct NS16550aInner {
+    rx_buffer: VecDeque<u8>,
+    tx_buffer: VecDeque<u8>,
+    ier: u8,
+    waiting_readers: VecDeque<Arc<TaskControlBlock>>,
+    /// 设置后收到的数据直接交给它, 不再进入 rx_buffer
+    sink: Option<Arc<dyn InputSink>>,
+}
+
+/// QEMU virt 板上的 16550 兼容串口
+pub struct NS16550a {
+    base: usize,
+    inner: UPSafeCell<NS16550aInner>,
+}
+
+impl NS16550a {
+    #[allow(unused)]
+    pub fn new() -> Self {
+        Self {
+            base: MACHINE.uart.0,
+            inner: unsafe {
+                UPSafeCell::new(NS16550aInner {
+                    rx_buffer: VecDeque::with_capacity(RX_BUFFER_SIZE),
+                    tx_buffer: VecDeque::with_capacity(TX_BUFFER_SIZE),
+                    ier: 0,
+                    waiting_readers: VecDeque::new(),
+                    sink: None,
+                })
+            },
+        }
+    }
+
+    fn read_reg(&self, offset: usize) -> u8 {
+        unsafe { read_volatile((self.base + offset) as *const u8) }
+    }
+
+    fn write_reg(&self, offset: usize, value: u8) {
+        unsafe { write_volatile((self.base + offset) as *mut u8, value) }
+    }
+
+    /// 波特率等参数沿用 SBI 的设置, 这里只打开 FIFO 和接收中断
+    pub fn init(&self) {
+        let mut inner = self.inner.exclusive_access();
+        self.write_reg(LCR, LCR_8N1);
+        self.write_reg(FCR, FCR_ENABLE_AND_CLEAR);
+        self.write_reg(MCR, MCR_DTR_RTS_OUT2);
+        inner.ier = IER_RX_AVAILABLE;
+        self.write_reg(IER, inner.ier);
+    }
+
+    pub fn set_input_sink(&self, sink: Arc<dyn InputSink>) {
+        self.inner.exclusive_access().sink = Some(sink);
+    }
+
+    fn set_tx_interrupt(&self, inner: &mut NS16550aInner, enable: bool) {
+        let ier = if enable {
+            inner.ier | IER_TX_EMPTY
+        } else {
+            inner.ier & !IER_TX_EMPTY
+        };
+        if ier != inner.ier {
+            inner.ier = ier;
+            self.write_reg(IER, ier);
+        }
+    }
+
+    /// 尽量把发送缓冲区中的数据写入硬件, 写不完时打开发送中断等待
+    fn flush_tx(&self, inner: &mut NS16550aInner) {
+        while self.read_reg(LSR) & LSR_THR_EMPTY != 0 {
+            match inner.tx_buffer.pop_front() {
+                Some(c) => self.write_reg(THR, c),
+                None => break,
+            }
+        }
+        let pending = !inner.tx_buffer.is_empty();
+        self.set_tx_interrupt(inner, pending);
+    }
+}
+
+impl CharDevice for NS16550a {
+    fn read(&self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if !inner.rx_buffer.is_empty() {
+                let len = buf.len().min(inner.rx_buffer.len());
+                for (dst, src) in buf.iter_mut().zip(inner.rx_buffer.drain(..len)) {
+                    *dst = src;
+                }
+                return len;
+            }
+            inner.waiting_readers.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+
+    fn write(&self, buf: &[u8]) {
+        for &c in buf {
+            let mut inner = self.inner.exclusive_access();
+            if inner.tx_buffer.len() >= TX_BUFFER_SIZE {
+                // 缓冲区满时退化为轮询, 保证不丢数据
+                drop(inner);
+                self.putchar_sync(c);
+                continue;
+            }
+            inner.tx_buffer.push_back(c);
+        }
+        let mut inner = self.inner.exclusive_access();
+        self.flush_tx(&mut inner);
+    }
+
+    fn putchar_sync(&self, c: u8) {
+        // 先发送缓冲区中已有的数据, 保证输出顺序.
+        // 在持有缓冲区时 panic 也要能输出, 所以不能用 exclusive_access
+        if let Some(mut inner) = self.inner.try_exclusive_access() {
+            while let Some(pending) = inner.tx_buffer.pop_front() {
+                while self.read_reg(LSR) & LSR_THR_EMPTY == 0 {}
+                self.write_reg(THR, pending);
+            }
+            self.set_tx_interrupt(&mut inner, false);
+        }
+        while self.read_reg(LSR) & LSR_THR_EMPTY == 0 {}
+        self.write_reg(THR, c);
+    }
+}
+
+impl IrqHandler for NS16550a {
+    fn handle_irq(&self) {
+        let mut inner = self.inner.exclusive_access();
+        let mut received = Vec::new();
+        while self.read_reg(LSR) & LSR_DATA_READY != 0 {
+            received.push(self.read_reg(RBR));
+        }
+        self.flush_tx(&mut inner);
+        if let Some(sink) = inner.sink.clone() {
+            // 上层会回显, 要再次写串口, 先释放借用
+            drop(inner);
+            for c in received {
+                sink.push_input(c);
+            }
+            return;
+        }
+        for c in received {
+            // 缓冲区满时丢弃新数据
+            if inner.rx_buffer.len() < RX_BUFFER_SIZE {
+                inner.rx_buffer.push_back(c);
+            }
+        }
+        if !inner.rx_buffer.is_empty() {
+            while let Some(task) = inner.waiting_readers.pop_front() {
+                wakeup_task(task);
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! 在帧缓冲区上用 8x16 点阵字体显示控制台输出
+use super::GpuDevice;
+use crate::board::GpuDeviceImpl;
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
+use core::convert::Infallible;
+use embedded_graphics::fonts::{Font8x16, Text};
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+use embedded_graphics::style::{TextStyle, TextStyleBuilder};
+use lazy_static::*;
+
+const CHAR_WIDTH: usize = 8;
+const CHAR_HEIGHT: usize = 16;
+const TAB_WIDTH: usize = 8;
+const BYTES_PER_PIXEL: usize = 4;
+
+/// 帧缓冲区作为 embedded-graphics 的绘制目标
+struct Display {
+    device: Arc<GpuDeviceImpl>,
+    width: usize,
+    height: usize,
+}
+
+impl DrawTarget<Rgb888> for Display {
+    type Error = Infallible;
+
+    fn draw_pixel(&mut self, Pixel(point, color): Pixel<Rgb888>) -> Result<(), Self::Error> {
+        let (x, y) = (point.x as usize, point.y as usize);
+        if point.x < 0 || point.y < 0 || x >= self.width || y >= self.height {
+            return Ok(());
+        }
+        let idx = (y * self.width + x) * BYTES_PER_PIXEL;
+        self.device.framebuffer()[idx..idx + BYTES_PER_PIXEL].copy_from_slice(&[
+            color.b(),
+            color.g(),
+            color.r(),
+            0xff,
+        ]);
+        Ok(())
+    }
+
+    fn size(&self) -> Size {
+        Size::new(self.width as u32, self.height as u32)
+    }
+}
+
+/// 只认识 CSI 转义序列, 其中的颜色等属性直接忽略
+enum Escape {
+    None,
+    Esc,
+    Csi,
+}
+
+struct FbConsole {
+    display: Display,
+    style: TextStyle<Rgb888, Font8x16>,
+    cols: usize,
+    rows: usize,
+    col: usize,
+    row: usize,
+    escape: Escape,
+}
+
+impl FbConsole {
+    fn new(device: Arc<GpuDeviceImpl>) -> Self {
+        let (width, height) = device.resolution();
+        let (width, height) = (width as usize, height as usize);
+        device.framebuffer().fill(0);
+        let display = Display {
+            device,
+            width,
+            height,
+        };
+        Self {
+            display,
+            style: TextStyleBuilder::new(Font8x16)
+                .text_color(Rgb888::WHITE)
+                .background_color(Rgb888::BLACK)
+                .build(),
+            cols: width / CHAR_WIDTH,
+            rows: height / CHAR_HEIGHT,
+            col: 0,
+            row: 0,
+            escape: Escape::None,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &c in bytes {
+            match self.escape {
+                Escape::Esc => {
+                    self.escape = if c == b'[' { Escape::Csi } else { Escape::None };
+                    continue;
+                }
+                Escape::Csi => {
+                    if (0x40..=0x7e).contains(&c) {
+                        self.escape = Escape::None;
+                    }
+                    continue;
+                }
+                Escape::None => {}
+            }
+            match c {
+                0x1b => self.escape = Escape::Esc,
+                b'\n' => self.new_line(),
+                b'\r' => self.col = 0,
+                0x08 => self.col = self.col.saturating_sub(1),
+                b'\t' => {
+                    for _ in 0..TAB_WIDTH - self.col % TAB_WIDTH {
+                        self.put_char(b' ');
+                    }
+                }
+                // UTF-8 的后续字节不占位置
+                0x80..=0xbf => {}
+                0x20..=0x7e => self.put_char(c),
+                0xc0..=0xff => self.put_char(b'?'),
+                _ => {}
+            }
+        }
+        self.display.device.flush();
+    }
+
+    fn put_char(&mut self, c: u8) {
+        if self.col >= self.cols {
+            self.new_line();
+        }
+        let mut buf = [0u8; 4];
+        let text = (c as char).encode_utf8(&mut buf);
+        let position = Point::new(
+            (self.col * CHAR_WIDTH) as i32,
+            (self.row * CHAR_HEIGHT) as i32,
+        );
+        let _ = Text::new(text, position)
+            .into_styled(self.style)
+            .draw(&mut self.display);
+        self.col += 1;
+    }
+
+    fn new_line(&mut self) {
+        self.col = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    /// 整个屏幕上移一行, 清空最后一行
+    fn scroll(&mut self) {
+        let line = self.display.width * CHAR_HEIGHT * BYTES_PER_PIXEL;
+        let used = line * self.rows;
+        let fb = self.display.device.framebuffer();
+        fb.copy_within(line..used, 0);
+        fb[used - line..used].fill(0);
+    }
+}
+
+lazy_static! {
+    static ref FB_CONSOLE: UPSafeCell<Option<FbConsole>> = unsafe { UPSafeCell::new(None) };
+}
+
+pub fn init(device: Arc<GpuDeviceImpl>) {
+    *FB_CONSOLE.exclusive_access() = Some(FbConsole::new(device));
+}
+
+pub fn console_write(bytes: &[u8]) {
+    // 输出过程中又有打印 (例如 panic) 时直接丢弃, 串口上仍然能看到
+    if let Some(mut console) = FB_CONSOLE.try_exclusive_access() {
+        if let Some(console) = console.as_mut() {
+            console.write(bytes);
+        }
+    }
+}
@@ -0,0 +1,9 @@
+pub trait GpuDevice: Send + Sync {
+    /// 屏幕分辨率 (宽, 高)
+    fn resolution(&self) -> (u32, u32);
+    /// 帧缓冲区, 每个像素 4 字节, 按 B G R A 排列
+    #[allow(clippy::mut_from_ref)]
+    fn framebuffer(&self) -> &mut [u8];
+    /// 把帧缓冲区的内容刷新到屏幕上
+    fn flush(&self);
+}
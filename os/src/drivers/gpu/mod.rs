@@ -0,0 +1,58 @@
+mod fb_console;
+mod gpu_device;
+mod virtio_gpu;
+
+pub use fb_console::console_write;
+pub use gpu_device::GpuDevice;
+pub use virtio_gpu::VirtIOGpuDevice;
+use super::virtio_slots;
+use crate::board::GpuDeviceImpl;
+use alloc::sync::Arc;
+use core::any::Any;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use lazy_static::*;
+use virtio_drivers::DeviceType;
+
+lazy_static! {
+    /// 启动时没有挂载显卡则为 None, 控制台只输出到串口
+    pub static ref GPU_DEVICE: Option<Arc<GpuDeviceImpl>> = virtio_slots(DeviceType::GPU)
+        .iter()
+        .find_map(|slot| GpuDeviceImpl::probe(slot.base))
+        .map(Arc::new);
+}
+
+/// 帧缓冲区在用户空间的映射数. 不为 0 时写入不经过内核, 只能定期刷新
+static FRAMEBUFFER_MAPPINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// 帧缓冲区在用户空间的一个映射, fork 出的映射共享它, 全部释放时计数减一
+struct FramebufferMapping;
+
+impl Drop for FramebufferMapping {
+    fn drop(&mut self) {
+        FRAMEBUFFER_MAPPINGS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn init() {
+    if let Some(device) = GPU_DEVICE.as_ref() {
+        let (width, height) = device.resolution();
+        fb_console::init(device.clone());
+        println!("[kernel] framebuffer {}x{}", width, height);
+    }
+}
+
+/// 映射帧缓冲区时调用, 返回值随映射一起释放
+pub fn map_framebuffer() -> Arc<dyn Any + Send + Sync> {
+    FRAMEBUFFER_MAPPINGS.fetch_add(1, Ordering::Relaxed);
+    Arc::new(FramebufferMapping)
+}
+
+/// 时钟中断中调用
+pub fn refresh_framebuffer() {
+    if FRAMEBUFFER_MAPPINGS.load(Ordering::Relaxed) > 0 {
+        if let Some(device) = GPU_DEVICE.as_ref() {
+            device.flush();
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use super::GpuDevice;
+use crate::drivers::block::VirtioHal;
+use crate::sync::UPSafeCell;
+use virtio_drivers::{DeviceType, VirtIOGpu, VirtIOHeader};
+
+pub struct VirtIOGpuDevice {
+    gpu: UPSafeCell<VirtIOGpu<'static, VirtioHal>>,
+    /// 帧缓冲区由 DMA 分配, 在内核中是恒等映射的
+    fb: &'static [u8],
+}
+
+impl VirtIOGpuDevice {
+    /// 该 MMIO 槽位上是 virtio-gpu 设备时才创建驱动
+    #[allow(unused)]
+    pub fn probe(base: usize) -> Option<Self> {
+        let header = unsafe { &mut *(base as *mut VirtIOHeader) };
+        if !header.verify() || header.device_type() != DeviceType::GPU {
+            return None;
+        }
+        let mut gpu = VirtIOGpu::<VirtioHal>::new(header).ok()?;
+        let fb = gpu.setup_framebuffer().ok()?;
+        let fb = unsafe { core::slice::from_raw_parts(fb.as_ptr(), fb.len()) };
+        Some(Self {
+            gpu: unsafe { UPSafeCell::new(gpu) },
+            fb,
+        })
+    }
+
+    /// 帧缓冲区的物理地址与长度, 用于 mmap
+    pub fn framebuffer_region(&self) -> (usize, usize) {
+        (self.fb.as_ptr() as usize, self.fb.len())
+    }
+}
+
+impl GpuDevice for VirtIOGpuDevice {
+    fn resolution(&self) -> (u32, u32) {
+        self.gpu.exclusive_access().resolution()
+    }
+
+    fn framebuffer(&self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.fb.as_ptr() as *mut u8, self.fb.len()) }
+    }
+
+    fn flush(&self) {
+        self.gpu.exclusive_access().flush().unwrap();
+    }
+}
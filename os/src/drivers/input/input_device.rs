@@ -0,0 +1,15 @@
+use super::EventQueue;
+use crate::drivers::chardev::InputSink;
+use alloc::string::String;
+use alloc::sync::Arc;
+
+pub trait InputDevice: Send + Sync {
+    fn name(&self) -> String;
+    fn is_keyboard(&self) -> bool;
+    /// 注册一个事件队列, 之后收到的事件都会复制一份放进去
+    fn subscribe(&self) -> EventQueue;
+    /// 独占设备, 此时键盘输入不再送给终端
+    fn set_grab(&self, grab: bool);
+    /// 键盘输入翻译成字符后交给 sink
+    fn set_input_sink(&self, sink: Arc<dyn InputSink>);
+}
@@ -0,0 +1,60 @@
+//! 把 Linux 键码按美式键盘布局翻译成终端字符
+use alloc::vec::Vec;
+
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_RIGHTSHIFT: u16 = 54;
+const KEY_CAPSLOCK: u16 = 58;
+const KEY_RIGHTCTRL: u16 = 97;
+const KEY_UP: u16 = 103;
+const KEY_LEFT: u16 = 105;
+const KEY_RIGHT: u16 = 106;
+const KEY_DOWN: u16 = 108;
+
+// 键码 0..=57, 0 表示没有对应的字符
+const NORMAL: &[u8; 58] =
+    b"\0\x1b1234567890-=\x7f\tqwertyuiop[]\r\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ";
+const SHIFTED: &[u8; 58] =
+    b"\0\x1b!@#$%^&*()_+\x7f\tQWERTYUIOP{}\r\0ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ";
+
+#[derive(Default)]
+pub struct KeyMap {
+    shift: bool,
+    ctrl: bool,
+    caps_lock: bool,
+}
+
+impl KeyMap {
+    /// value 为 0 表示松开, 1 表示按下, 2 表示按住自动重复
+    pub fn translate(&mut self, code: u16, value: u32, out: &mut Vec<u8>) {
+        let pressed = value != 0;
+        match code {
+            KEY_LEFTSHIFT | KEY_RIGHTSHIFT => self.shift = pressed,
+            KEY_LEFTCTRL | KEY_RIGHTCTRL => self.ctrl = pressed,
+            KEY_CAPSLOCK if value == 1 => self.caps_lock = !self.caps_lock,
+            _ if !pressed => {}
+            KEY_UP => out.extend_from_slice(b"\x1b[A"),
+            KEY_DOWN => out.extend_from_slice(b"\x1b[B"),
+            KEY_RIGHT => out.extend_from_slice(b"\x1b[C"),
+            KEY_LEFT => out.extend_from_slice(b"\x1b[D"),
+            _ => {
+                let c = match NORMAL.get(code as usize) {
+                    Some(&c) if c != 0 => c,
+                    _ => return,
+                };
+                let mut c = if self.shift {
+                    SHIFTED[code as usize]
+                } else {
+                    c
+                };
+                if self.caps_lock && c.is_ascii_alphabetic() {
+                    c ^= 0x20;
+                }
+                if self.ctrl && c.is_ascii_alphabetic() {
+                    c = c.to_ascii_uppercase() - b'@';
+                }
+                out.push(c);
+            }
+        }
+    }
+}
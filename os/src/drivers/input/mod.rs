@@ -0,0 +1,71 @@
+mod input_device;
+mod keymap;
+mod virtio_input;
+
+pub use input_device::InputDevice;
+pub use virtio_input::VirtIOInputDevice;
+use super::irq::register_irq;
+use super::virtio_slots;
+use crate::board::InputDeviceImpl;
+use crate::sync::UPSafeCell;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use lazy_static::*;
+use virtio_drivers::DeviceType;
+
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+
+/// 每个打开的事件设备最多缓存的事件数, 满了丢弃最旧的
+pub const MAX_QUEUED_EVENTS: usize = 256;
+
+/// 与 Linux 64 位下的 struct input_event 布局相同
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InputEvent {
+    pub tv_sec: i64,
+    pub tv_usec: i64,
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl InputEvent {
+    pub fn new(ns: u64, event_type: u16, code: u16, value: u32) -> Self {
+        Self {
+            tv_sec: (ns / 1_000_000_000) as i64,
+            tv_usec: (ns % 1_000_000_000 / 1000) as i64,
+            event_type,
+            code,
+            value: value as i32,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                core::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+pub type EventQueue = Arc<UPSafeCell<VecDeque<InputEvent>>>;
+
+lazy_static! {
+    /// 按探测到的顺序排列, 第 i 个对应 /dev/input/event{i}
+    pub static ref INPUT_DEVICES: Vec<(usize, Arc<InputDeviceImpl>)> = virtio_slots(DeviceType::Input)
+        .iter()
+        .filter_map(|slot| InputDeviceImpl::probe(slot.base).map(|device| (slot.irq, Arc::new(device))))
+        .collect();
+}
+
+pub fn init() {
+    for (irq, device) in INPUT_DEVICES.iter() {
+        register_irq(*irq, device.clone());
+        println!("[kernel] input device: {}", device.name());
+    }
+}
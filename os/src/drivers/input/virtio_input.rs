@@ -0,0 +1,120 @@
+use super::keymap::KeyMap;
+use super::{EventQueue, InputDevice, InputEvent, EV_KEY, EV_REL, MAX_QUEUED_EVENTS};
+use crate::drivers::block::VirtioHal;
+use crate::drivers::chardev::InputSink;
+use crate::drivers::IrqHandler;
+use crate::sync::UPSafeCell;
+use crate::timer::get_realtime_ns;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use virtio_drivers::{DeviceType, InputConfigSelect, VirtIOHeader, VirtIOInput};
+
+struct VirtIOInputInner {
+    input: VirtIOInput<'static, VirtioHal>,
+    clients: Vec<Weak<UPSafeCell<VecDeque<InputEvent>>>>,
+    grabbed: bool,
+    /// 只有键盘才有, 用来把按键翻译成字符
+    keymap: Option<KeyMap>,
+    sink: Option<Arc<dyn InputSink>>,
+}
+
+pub struct VirtIOInputDevice {
+    name: String,
+    inner: UPSafeCell<VirtIOInputInner>,
+}
+
+impl VirtIOInputDevice {
+    /// 该 MMIO 槽位上是 virtio-input 设备时才创建驱动
+    #[allow(unused)]
+    pub fn probe(base: usize) -> Option<Self> {
+        let header = unsafe { &mut *(base as *mut VirtIOHeader) };
+        if !header.verify() || header.device_type() != DeviceType::Input {
+            return None;
+        }
+        let mut input = VirtIOInput::<VirtioHal>::new(header).ok()?;
+        let mut buf = [0u8; 128];
+        let len = input.query_config_select(InputConfigSelect::IdName, 0, &mut buf);
+        let name = String::from_utf8_lossy(&buf[..len as usize]).into_owned();
+        // 有相对坐标轴的是鼠标, 否则只要有按键就当作键盘
+        let is_keyboard =
+            input.query_config_select(InputConfigSelect::EvBits, EV_REL as u8, &mut buf) == 0
+                && input.query_config_select(InputConfigSelect::EvBits, EV_KEY as u8, &mut buf) > 0;
+        Some(Self {
+            name,
+            inner: unsafe {
+                UPSafeCell::new(VirtIOInputInner {
+                    input,
+                    clients: Vec::new(),
+                    grabbed: false,
+                    keymap: if is_keyboard {
+                        Some(KeyMap::default())
+                    } else {
+                        None
+                    },
+                    sink: None,
+                })
+            },
+        })
+    }
+}
+
+impl InputDevice for VirtIOInputDevice {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn is_keyboard(&self) -> bool {
+        self.inner.exclusive_access().keymap.is_some()
+    }
+
+    fn subscribe(&self) -> EventQueue {
+        let queue: EventQueue = Arc::new(unsafe { UPSafeCell::new(VecDeque::new()) });
+        let mut inner = self.inner.exclusive_access();
+        inner.clients.retain(|client| client.strong_count() > 0);
+        inner.clients.push(Arc::downgrade(&queue));
+        queue
+    }
+
+    fn set_grab(&self, grab: bool) {
+        self.inner.exclusive_access().grabbed = grab;
+    }
+
+    fn set_input_sink(&self, sink: Arc<dyn InputSink>) {
+        self.inner.exclusive_access().sink = Some(sink);
+    }
+}
+
+impl IrqHandler for VirtIOInputDevice {
+    fn handle_irq(&self) {
+        let mut inner = self.inner.exclusive_access();
+        inner.input.ack_interrupt();
+        let ns = get_realtime_ns();
+        let mut chars = Vec::new();
+        while let Some(event) = inner.input.pop_pending_event() {
+            let event = InputEvent::new(ns, event.event_type, event.code, event.value);
+            for client in inner.clients.iter().filter_map(|client| client.upgrade()) {
+                let mut queue = client.exclusive_access();
+                if queue.len() >= MAX_QUEUED_EVENTS {
+                    queue.pop_front();
+                }
+                queue.push_back(event);
+            }
+            let grabbed = inner.grabbed;
+            if let (Some(keymap), false) = (inner.keymap.as_mut(), grabbed) {
+                if event.event_type == EV_KEY {
+                    keymap.translate(event.code, event.value as u32, &mut chars);
+                }
+            }
+        }
+        let sink = inner.sink.clone();
+        drop(inner);
+        // 与串口一样, 释放借用后再交给终端, 终端可能会回显
+        if let Some(sink) = sink {
+            for c in chars {
+                sink.push_input(c);
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! 外部中断注册表, 设备驱动在这里登记自己的中断号和处理函数,
+//! trap 处理时由 `handle_external_interrupt` 分发
+use super::plic::{IntrTargetPriority, Plic};
+use crate::fdt::MACHINE;
+use crate::smp::hart_id;
+use crate::sync::SpinNoIrq;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+
+pub trait IrqHandler: Send + Sync {
+    fn handle_irq(&self);
+}
+
+// 外部中断只发给启动 hart, 其他 hart 只处理时钟中断
+static IRQ_HART: AtomicUsize = AtomicUsize::new(0);
+const DEFAULT_PRIORITY: u32 = 1;
+
+lazy_static! {
+    static ref PLIC: Plic = Plic::new(MACHINE.plic.0);
+    static ref IRQ_TABLE: SpinNoIrq<BTreeMap<usize, Arc<dyn IrqHandler>>> =
+        SpinNoIrq::new(BTreeMap::new());
+}
+
+fn irq_hart() -> usize {
+    IRQ_HART.load(Ordering::Relaxed)
+}
+
+/// 在启动 hart 上调用
+pub fn init() {
+    IRQ_HART.store(hart_id(), Ordering::Relaxed);
+    // 屏蔽 M 态, S 态接收所有优先级大于 0 的中断
+    PLIC.set_threshold(irq_hart(), IntrTargetPriority::Machine, 1);
+    PLIC.set_threshold(irq_hart(), IntrTargetPriority::Supervisor, 0);
+}
+
+/// 登记中断处理函数并在 PLIC 中打开该中断
+pub fn register_irq(irq: usize, handler: Arc<dyn IrqHandler>) {
+    IRQ_TABLE.lock().insert(irq, handler);
+    PLIC.set_priority(irq, DEFAULT_PRIORITY);
+    PLIC.enable(irq_hart(), IntrTargetPriority::Supervisor, irq);
+}
+
+pub fn unregister_irq(irq: usize) {
+    PLIC.disable(irq_hart(), IntrTargetPriority::Supervisor, irq);
+    IRQ_TABLE.lock().remove(&irq);
+}
+
+pub fn handle_external_interrupt() {
+    let irq = PLIC.claim(irq_hart(), IntrTargetPriority::Supervisor);
+    if irq == 0 {
+        return;
+    }
+    // 处理函数可能再次访问注册表, 先释放借用
+    let handler = IRQ_TABLE.lock().get(&(irq as usize)).cloned();
+    match handler {
+        Some(handler) => handler.handle_irq(),
+        None => println!("[kernel] unhandled external interrupt {}", irq),
+    }
+    PLIC.complete(irq_hart(), IntrTargetPriority::Supervisor, irq);
+}
@@ -1,3 +1,28 @@
 pub mod block;
+pub mod chardev;
+#[cfg(feature = "graphics")]
+pub mod gpu;
+pub mod input;
+pub mod irq;
+pub mod net;
+pub mod plic;
+pub mod rtc;
+pub mod virtio;
 
 pub use block::{BLOCK_DEVICE,BlockDevice};
+pub use input::{InputDevice, INPUT_DEVICES};
+pub use irq::{register_irq, IrqHandler};
+pub use net::{NET_DEVICE, NetDevice};
+pub use rtc::{RTC_DEVICE, RtcDevice};
+pub use virtio::virtio_slots;
+
+/// 初始化中断控制器并登记各设备的中断
+pub fn init() {
+    irq::init();
+    block::init();
+    chardev::init();
+    net::init();
+    input::init();
+    #[cfg(feature = "graphics")]
+    gpu::init();
+}
@@ -0,0 +1,29 @@
+mod net_device;
+mod virtio_net;
+
+pub use net_device::NetDevice;
+pub use virtio_net::VirtIONetDevice;
+use super::irq::register_irq;
+use super::virtio_slots;
+use crate::board::NetDeviceImpl;
+use alloc::sync::Arc;
+use virtio_drivers::DeviceType;
+
+use lazy_static::*;
+
+lazy_static! {
+    /// 启动时没有挂载网卡则为 None, 此时只有回环接口可用; 有多块时使用地址最小的一块
+    pub static ref NET_DEVICE: Option<Arc<NetDeviceImpl>> = NET_SLOT
+        .and_then(|(base, _)| NetDeviceImpl::probe(base))
+        .map(Arc::new);
+    /// 网卡所在槽位的 (地址, 中断号)
+    static ref NET_SLOT: Option<(usize, usize)> = virtio_slots(DeviceType::Network)
+        .first()
+        .map(|slot| (slot.base, slot.irq));
+}
+
+pub fn init() {
+    if let (Some(device), Some((_, irq))) = (NET_DEVICE.as_ref(), *NET_SLOT) {
+        register_irq(irq, device.clone());
+    }
+}
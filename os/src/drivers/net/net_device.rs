@@ -0,0 +1,7 @@
+pub trait NetDevice: Send + Sync {
+    fn mac(&self) -> [u8; 6];
+    /// 发送一个以太网帧, 设备忙时返回 false
+    fn send(&self, frame: &[u8]) -> bool;
+    /// 取出一个收到的以太网帧, 没有时返回 None, 不会阻塞
+    fn recv(&self, buf: &mut [u8]) -> Option<usize>;
+}
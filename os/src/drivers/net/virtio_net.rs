@@ -0,0 +1,109 @@
+use super::NetDevice;
+use crate::drivers::block::VirtioHal;
+use crate::drivers::IrqHandler;
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use log::warn;
+use virtio_drivers::{DeviceType, VirtIOHeader, VirtIONet};
+
+// 以太网帧最大 1514 字节, 加上 virtio-net 头
+const RX_BUFFER_SIZE: usize = 2048;
+
+struct VirtIONetInner {
+    net: VirtIONet<'static, VirtioHal>,
+    /// 已交给设备的接收缓冲区, 以 recv_begin 返回的 token 为键, 收到数据包前不能访问
+    rx_buffers: BTreeMap<u16, Vec<u8>>,
+    /// 交给设备失败的缓冲区, 下次收包后重试
+    rx_spare: Vec<Vec<u8>>,
+}
+
+impl VirtIONetInner {
+    /// 把空闲的接收缓冲区交给设备
+    fn refill(&mut self) {
+        while let Some(mut buf) = self.rx_spare.pop() {
+            match unsafe { self.net.recv_begin(&mut buf) } {
+                Ok(token) => {
+                    self.rx_buffers.insert(token, buf);
+                }
+                Err(err) => {
+                    warn!("[virtio-net] failed to post rx buffer: {:?}", err);
+                    self.rx_spare.push(buf);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+pub struct VirtIONetDevice(UPSafeCell<VirtIONetInner>);
+
+impl VirtIONetDevice {
+    /// 该 MMIO 槽位上是 virtio-net 设备时才创建驱动
+    #[allow(unused)]
+    pub fn probe(base: usize) -> Option<Self> {
+        let header = unsafe { &mut *(base as *mut VirtIOHeader) };
+        if !header.verify() || header.device_type() != DeviceType::Network {
+            return None;
+        }
+        let net = VirtIONet::<VirtioHal>::new(header).ok()?;
+        let mut inner = VirtIONetInner {
+            net,
+            rx_buffers: BTreeMap::new(),
+            rx_spare: (0..VirtIONet::<VirtioHal>::max_recv_buffers())
+                .map(|_| vec![0u8; RX_BUFFER_SIZE])
+                .collect(),
+        };
+        inner.refill();
+        if inner.rx_buffers.is_empty() {
+            return None;
+        }
+        Some(Self(unsafe { UPSafeCell::new(inner) }))
+    }
+}
+
+impl NetDevice for VirtIONetDevice {
+    fn mac(&self) -> [u8; 6] {
+        self.0.exclusive_access().net.mac()
+    }
+
+    fn send(&self, frame: &[u8]) -> bool {
+        let mut inner = self.0.exclusive_access();
+        inner.net.can_send() && inner.net.send(frame).is_ok()
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut inner = self.0.exclusive_access();
+        // 在中断中调用, 出错时丢弃这一帧, 不能 panic
+        let (token, len) = match inner.net.recv_poll() {
+            Ok(packet) => packet?,
+            Err(err) => {
+                warn!("[virtio-net] failed to receive packet: {:?}", err);
+                return None;
+            }
+        };
+        let rx_buffer = match inner.rx_buffers.remove(&token) {
+            Some(rx_buffer) => rx_buffer,
+            None => {
+                warn!("[virtio-net] device returned unknown rx token {}", token);
+                return None;
+            }
+        };
+        let header_size = VirtIONet::<VirtioHal>::header_size();
+        let len = len.min(buf.len()).min(rx_buffer.len() - header_size);
+        buf[..len].copy_from_slice(&rx_buffer[header_size..header_size + len]);
+        // 取走数据后立即把缓冲区还给设备
+        inner.rx_spare.push(rx_buffer);
+        inner.refill();
+        Some(len)
+    }
+}
+
+impl IrqHandler for VirtIONetDevice {
+    fn handle_irq(&self) {
+        self.0.exclusive_access().net.ack_interrupt();
+        // 中断只在内核空闲时打开, 此时协议栈不会被占用
+        crate::net::poll_interfaces();
+    }
+}
@@ -0,0 +1,86 @@
+//! RISC-V PLIC (Platform-Level Interrupt Controller)
+use core::ptr::{read_volatile, write_volatile};
+
+const PRIORITY_BASE: usize = 0x0000;
+const ENABLE_BASE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT_BASE: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD: usize = 0x0;
+const CLAIM_COMPLETE: usize = 0x4;
+
+#[derive(Copy, Clone)]
+pub enum IntrTargetPriority {
+    Machine = 0,
+    Supervisor = 1,
+}
+
+impl IntrTargetPriority {
+    pub fn supported_number() -> usize {
+        2
+    }
+}
+
+pub struct Plic {
+    base: usize,
+}
+
+impl Plic {
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    // QEMU virt 上每个 hart 有 M/S 两个 context
+    fn context(hart_id: usize, target: IntrTargetPriority) -> usize {
+        hart_id * IntrTargetPriority::supported_number() + target as usize
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    fn enable_reg(&self, hart_id: usize, target: IntrTargetPriority, irq: usize) -> (usize, u32) {
+        let offset =
+            ENABLE_BASE + Self::context(hart_id, target) * ENABLE_STRIDE + (irq / 32) * 4;
+        (offset, 1 << (irq % 32))
+    }
+
+    fn context_reg(&self, hart_id: usize, target: IntrTargetPriority, reg: usize) -> usize {
+        CONTEXT_BASE + Self::context(hart_id, target) * CONTEXT_STRIDE + reg
+    }
+
+    /// 优先级为 0 的中断永远不会被触发, 有效范围为 1..=7
+    pub fn set_priority(&self, irq: usize, priority: u32) {
+        assert!(priority < 8);
+        self.write(PRIORITY_BASE + irq * 4, priority);
+    }
+
+    pub fn enable(&self, hart_id: usize, target: IntrTargetPriority, irq: usize) {
+        let (offset, mask) = self.enable_reg(hart_id, target, irq);
+        self.write(offset, self.read(offset) | mask);
+    }
+
+    pub fn disable(&self, hart_id: usize, target: IntrTargetPriority, irq: usize) {
+        let (offset, mask) = self.enable_reg(hart_id, target, irq);
+        self.write(offset, self.read(offset) & !mask);
+    }
+
+    /// 只有优先级高于阈值的中断会被送到该 context
+    pub fn set_threshold(&self, hart_id: usize, target: IntrTargetPriority, threshold: u32) {
+        assert!(threshold < 8);
+        self.write(self.context_reg(hart_id, target, THRESHOLD), threshold);
+    }
+
+    /// 取得当前待处理的最高优先级中断号, 没有时返回 0
+    pub fn claim(&self, hart_id: usize, target: IntrTargetPriority) -> u32 {
+        self.read(self.context_reg(hart_id, target, CLAIM_COMPLETE))
+    }
+
+    pub fn complete(&self, hart_id: usize, target: IntrTargetPriority, irq: u32) {
+        self.write(self.context_reg(hart_id, target, CLAIM_COMPLETE), irq);
+    }
+}
@@ -0,0 +1,42 @@
+use super::RtcDevice;
+use crate::fdt::MACHINE;
+use core::ptr::{read_volatile, write_volatile};
+
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+/// QEMU virt 板上的 goldfish RTC, 计数单位为纳秒
+pub struct GoldfishRtc {
+    base: usize,
+}
+
+impl GoldfishRtc {
+    #[allow(unused)]
+    pub fn new() -> Self {
+        Self {
+            base: MACHINE.rtc.expect("no goldfish RTC in device tree").0,
+        }
+    }
+
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+}
+
+impl RtcDevice for GoldfishRtc {
+    fn get_time(&self) -> u64 {
+        // 读 TIME_LOW 时设备会锁存 TIME_HIGH, 必须先读低位
+        let low = self.read_reg(TIME_LOW) as u64;
+        let high = self.read_reg(TIME_HIGH) as u64;
+        (high << 32) | low
+    }
+
+    fn set_time(&self, ns: u64) {
+        self.write_reg(TIME_HIGH, (ns >> 32) as u32);
+        self.write_reg(TIME_LOW, ns as u32);
+    }
+}
@@ -0,0 +1,13 @@
+mod goldfish;
+mod rtc_device;
+
+pub use goldfish::GoldfishRtc;
+pub use rtc_device::RtcDevice;
+use crate::board::RtcDeviceImpl;
+use alloc::sync::Arc;
+
+use lazy_static::*;
+
+lazy_static! {
+    pub static ref RTC_DEVICE: Arc<dyn RtcDevice> = Arc::new(RtcDeviceImpl::new());
+}
@@ -0,0 +1,5 @@
+pub trait RtcDevice: Send + Sync {
+    /// 自 1970-01-01 00:00:00 UTC 起的纳秒数
+    fn get_time(&self) -> u64;
+    fn set_time(&self, ns: u64);
+}
@@ -0,0 +1,17 @@
+//! 读取设备树中各 virtio-mmio 槽位的设备 ID, 找出挂着指定类型设备的槽位
+use crate::fdt::{VirtioSlot, MACHINE};
+use alloc::vec::Vec;
+use virtio_drivers::{DeviceType, VirtIOHeader};
+
+/// 按地址从小到大排列, 空槽位的设备 ID 为 0 会被跳过
+pub fn virtio_slots(device_type: DeviceType) -> Vec<VirtioSlot> {
+    MACHINE
+        .virtio
+        .iter()
+        .filter(|slot| {
+            let header = unsafe { &*(slot.base as *const VirtIOHeader) };
+            header.verify() && header.device_type() == device_type
+        })
+        .copied()
+        .collect()
+}
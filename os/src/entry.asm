@@ -1,12 +1,26 @@
     .section .text.entry
     .globl _start
 _start:
+    # a0 = hart id, 每个 hart 使用 boot_stack 中各自的一段
+    mv tp, a0
     la sp, boot_stack_top
+    slli t0, a0, 16
+    sub sp, sp, t0
     call rust_main
 
+    .globl _start_secondary
+_start_secondary:
+    # 由 SBI HSM 启动, 此时还没有开启分页
+    mv tp, a0
+    la sp, boot_stack_top
+    slli t0, a0, 16
+    sub sp, sp, t0
+    call rust_main_secondary
+
     .section .bss.stack
     .globl boot_stack_lower_bound
 boot_stack_lower_bound:
-    .space 4096 * 16
+    # 每个 hart 64K, 最多 MAX_HARTS 个
+    .space 4096 * 16 * 8
     .globl boot_stack_top
 boot_stack_top:
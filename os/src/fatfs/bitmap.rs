@@ -0,0 +1,97 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::table::RESERVED_FAT_ENTRIES;
+
+/// 内存中的空闲簇位图, 挂载时由 FAT 表构建, 之后分配与释放簇时同步更新,
+/// 避免每次分配都线性扫描 FAT 表
+pub struct ClusterBitmap {
+    /// 置 1 表示簇已被占用
+    bits: Vec<u64>,
+    end_cluster: u32,
+    free: u32,
+}
+
+impl ClusterBitmap {
+    /// 新建位图, 所有簇都视为已占用, 再由 `set_free` 标记空闲簇
+    pub fn new(total_clusters: u32) -> Self {
+        let end_cluster = total_clusters + RESERVED_FAT_ENTRIES;
+        let words = (end_cluster as usize + 63) / 64;
+        Self {
+            bits: vec![u64::MAX; words],
+            end_cluster,
+            free: 0,
+        }
+    }
+
+    pub fn free_count(&self) -> u32 {
+        self.free
+    }
+
+    pub fn is_free(&self, cluster: u32) -> bool {
+        cluster >= RESERVED_FAT_ENTRIES
+            && cluster < self.end_cluster
+            && self.bits[cluster as usize / 64] & (1 << (cluster % 64)) == 0
+    }
+
+    pub fn set_free(&mut self, cluster: u32) {
+        if cluster < RESERVED_FAT_ENTRIES || cluster >= self.end_cluster || self.is_free(cluster) {
+            return;
+        }
+        self.bits[cluster as usize / 64] &= !(1 << (cluster % 64));
+        self.free += 1;
+    }
+
+    pub fn set_used(&mut self, cluster: u32) {
+        if !self.is_free(cluster) {
+            return;
+        }
+        self.bits[cluster as usize / 64] |= 1 << (cluster % 64);
+        self.free -= 1;
+    }
+
+    /// 从 `start` 开始查找第一个空闲簇, 到末尾后回绕
+    pub fn find_free(&self, start: u32) -> Option<u32> {
+        self.find_extent(start, 1).map(|(cluster, _)| cluster)
+    }
+
+    /// 从 `start` 开始查找长度至少为 `len` 的连续空闲簇, 到末尾后回绕,
+    /// 找不到时返回最长的一段. 返回 (起始簇, 长度), 长度不超过 `len`
+    pub fn find_extent(&self, start: u32, len: u32) -> Option<(u32, u32)> {
+        if self.free == 0 || len == 0 {
+            return None;
+        }
+        let start = if start >= RESERVED_FAT_ENTRIES && start < self.end_cluster {
+            start
+        } else {
+            RESERVED_FAT_ENTRIES
+        };
+        let mut best: Option<(u32, u32)> = None;
+        for (from, to) in [(start, self.end_cluster), (RESERVED_FAT_ENTRIES, start)] {
+            let mut cluster = from;
+            while cluster < to {
+                // 整个字都已占用时直接跳过
+                if cluster % 64 == 0 && self.bits[cluster as usize / 64] == u64::MAX {
+                    cluster += 64;
+                    continue;
+                }
+                if !self.is_free(cluster) {
+                    cluster += 1;
+                    continue;
+                }
+                let run_start = cluster;
+                while cluster < to && cluster - run_start < len && self.is_free(cluster) {
+                    cluster += 1;
+                }
+                let run_len = cluster - run_start;
+                if run_len == len {
+                    return Some((run_start, run_len));
+                }
+                if best.map_or(true, |(_, best_len)| run_len > best_len) {
+                    best = Some((run_start, run_len));
+                }
+            }
+        }
+        best
+    }
+}
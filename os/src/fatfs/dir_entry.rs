@@ -20,7 +20,6 @@ use super::{
 
 use crate::{
     fatfs::lfn::{LongNameBuilder, ShortName, LFN_PART_LEN},
-    fs::Dirent,
     sync::UPSafeCell, console::print,
 };
 
@@ -97,6 +96,10 @@ impl DirFileEntry {
         self.attrs.contains(DirAttr::DIRECTORY)
     }
 
+    pub fn is_readonly(&self) -> bool {
+        self.attrs.contains(DirAttr::READ_ONLY)
+    }
+
     pub fn first_cluster(&self) -> Option<u32> {
         let n = (u32::from(self.first_cluster_hi) << 16) | u32::from(self.first_cluster_lo);
         if n == 0 {
@@ -477,7 +480,7 @@ impl DirEntry {
 
     fn find_free_entries(&mut self, num_entries: u32) -> Result<BlockCacheManager, Error<()>> {
         let start = self.seek(SeekFrom::Start(0)).unwrap();
-        let mut stream = self.disk.inner.borrow_mut();
+        let mut stream = self.disk.exclusive_access();
         
         let mut first_free: u32 = 0;
         let mut num_free: u32 = 0;
@@ -566,13 +569,24 @@ impl DirEntry {
     }
 
     pub fn create_file(&mut self, path: &str) -> Result<Inode, Error<()>> {
-        
+        self.create_file_inner(path, false)
+    }
+
+    /// 与 create_file 相同, 但文件已存在时返回 AlreadyExists
+    pub fn create_new_file(&mut self, path: &str) -> Result<Inode, Error<()>> {
+        self.create_file_inner(path, true)
+    }
+
+    fn create_file_inner(&mut self, path: &str, exclusive: bool) -> Result<Inode, Error<()>> {
         let (name, rest_opt) = split_path(path);
         if let Some(rest) = rest_opt {
-            return self.find_entry(name, Some(true))?.create_file(rest);
+            return self
+                .find_entry(name, Some(true))?
+                .create_file_inner(rest, exclusive);
         }
         let e = self.check_for_existence(name, Some(false))?;
         match e {
+            DirEntryOrShortName::DirEntry(_) if exclusive => Err(Error::AlreadyExists),
             DirEntryOrShortName::DirEntry(e) => match e.is_dir() {
                 true => Ok(Inode::Dir(e)),
                 false => Ok(Inode::File(e.to_file())),
@@ -664,7 +678,7 @@ impl DirEntry {
     pub fn read_next_entry(&mut self) -> Result<Option<DirEntry>, Error<()>> {
         
         let mut lfn_builder = LongNameBuilder::new();
-        let mut offset = self.disk.inner.borrow_mut().seek(SeekFrom::Current(0))?;
+        let mut offset = self.disk.exclusive_access().seek(SeekFrom::Current(0))?;
         let mut begin_offset = offset;
         loop {
             let raw_entry = DirEntryData::deserialize(self)?;
@@ -680,7 +694,7 @@ impl DirEntry {
             }
             match raw_entry {
                 DirEntryData::File(data) => {
-                    let entry_pos = self.disk.inner.borrow_mut().pos as u64 - DIR_ENTRY_SIZE as u64;
+                    let entry_pos = self.disk.exclusive_access().pos as u64 - DIR_ENTRY_SIZE as u64;
                     lfn_builder.validate_chksum(data.name());
                     let short_name = ShortName::new(data.name());
                     let mut blk = BlockCacheManager::new();
@@ -722,7 +736,7 @@ impl DirEntry {
 
     pub fn open_dir(&mut self, path: &str) -> Result<Inode, Error<()>> {
         let (name, rest_opt) = split_path(path);
-        let mut e = self.find_entry(name, Some(true)).unwrap();
+        let mut e = self.find_entry(name, Some(true))?;
         match rest_opt {
             Some(rest) => e.open_dir(rest),
             None => Ok(Inode::Dir(e)),
@@ -745,7 +759,7 @@ impl DirEntry {
             return Err(Error::DirectoryIsNotEmpty);
         }
         if let Some(_n) = e.dir_entry.first_cluster() {}
-        let mut stream = self.disk.inner.borrow_mut();
+        let mut stream = self.disk.exclusive_access();
         stream.seek(SeekFrom::Start(e.offset_range.0))?;
         let num = ((e.offset_range.1 - e.offset_range.0) / u64::from(DIR_ENTRY_SIZE)) as usize;
         for _ in 0..num {
@@ -757,6 +771,22 @@ impl DirEntry {
         Ok(())
     }
 
+    /// 修改目录项中的访问/修改时间, 根目录没有目录项, 直接忽略
+    pub fn set_times(&mut self, accessed: Option<DateTime>, modified: Option<DateTime>) {
+        if self.entry_pos == 0 {
+            return;
+        }
+        if let Some(accessed) = accessed {
+            self.dir_entry.set_accessed(accessed.date);
+        }
+        if let Some(modified) = modified {
+            self.dir_entry.set_modified(modified);
+        }
+        let mut disk = BlockCacheManager::new();
+        disk.seek(SeekFrom::Start(self.entry_pos)).unwrap();
+        self.dir_entry.serialize(&mut disk).unwrap();
+    }
+
     pub fn short_file_name_as_bytes(&self) -> &[u8] {
         self.short_name.as_bytes()
     }
@@ -792,8 +822,7 @@ impl Seek for DirEntry {
             }
         };
         self.disk
-            .inner
-            .borrow_mut()
+            .exclusive_access()
             .seek(SeekFrom::Start(new_offset))
             .unwrap();
         Ok(new_offset)
@@ -802,7 +831,7 @@ impl Seek for DirEntry {
 
 impl Read for DirEntry {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let mut disk = self.disk.inner.borrow_mut();
+        let mut disk = self.disk.exclusive_access();
         self.offset += buf.len() as u64;
 
         disk.read(buf)
@@ -867,6 +896,21 @@ impl DirEntryEditor {
         }
     }
 
+    pub fn set_created(&mut self, date_time: DateTime) {
+        self.data.set_created(date_time);
+        self.dirty = true;
+    }
+
+    pub fn set_accessed(&mut self, date: Date) {
+        self.data.set_accessed(date);
+        self.dirty = true;
+    }
+
+    pub fn set_modified(&mut self, date_time: DateTime) {
+        self.data.set_modified(date_time);
+        self.dirty = true;
+    }
+
     pub fn flush(&self) {
         let mut disk = BlockCacheManager::new();
         
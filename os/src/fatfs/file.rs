@@ -1,5 +1,5 @@
 use crate::{
-    fs::{Dirent, File, Kstat},
+    fs::{File, Kstat, StatMode},
     sync::UPSafeCell,
 };
 use alloc::{string::String, vec::Vec};
@@ -7,11 +7,16 @@ use core::{cmp, ptr::NonNull};
 use k210_pac::{aes::en, wdt0::cr};
 const MAX_FILE_SIZE: u32 = core::u32::MAX;
 use super::{
-    alloc_cluster, cluster_to_offset,
+    alloc_cluster, alloc_clusters, cluster_to_offset,
     dir_entry::{DirEntry, DirEntryEditor, DirFileEntry},
+    free_cluster_chain,
+    fs::write_zeros,
+    get_next_cluster,
     io::{Error, IoBase, Read, Seek, SeekFrom, Write},
+    lfn::DIR_ENTRY_SIZE,
     sdcard::BlockCacheManager,
-    FATFS,
+    time::{get_current_date_time, DateTime, Time},
+    truncate_cluster_chain, FATFS,
 };
 
 pub struct FileEntry {
@@ -32,7 +37,7 @@ impl FileEntry {
             None => 0,
         };
         let range = (abs_start_pos, abs_start_pos + entry.size());
-        let first_cluster = None;
+        let first_cluster = entry.first_cluster();
         let current_cluster = None;
         let mut disk = BlockCacheManager::from(abs_start_pos as usize, entry.size() as usize);
         disk.seek(SeekFrom::Start(abs_start_pos)).unwrap();
@@ -56,29 +61,203 @@ impl FileEntry {
         self.entry.inner().set_first_cluster(self.first_cluster);
     }
     pub fn update_dir_entry_after_write(&mut self) {
-        self.entry.set_size(self.pos as u32);
+        if self.pos > self.size() {
+            self.entry.set_size(self.pos as u32);
+        }
+    }
+
+    /// 返回文件中第 `index` 个簇的簇号, 簇链不够长时返回 None
+    fn cluster_at(&self, index: u64) -> Result<Option<u32>, Error<()>> {
+        let mut cluster = match self.first_cluster {
+            Some(cluster) => cluster,
+            None => return Ok(None),
+        };
+        for _ in 0..index {
+            cluster = match get_next_cluster(cluster)? {
+                Some(next) => next,
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(cluster))
+    }
+
+    /// 按 rust-fatfs 的约定, 当前簇是包含 `pos - 1` 处字节的簇
+    fn cluster_for_pos(&self, pos: u64) -> Result<Option<u32>, Error<()>> {
+        if pos == 0 {
+            return Ok(None);
+        }
+        let cluster_size = FATFS.cluster_size() as u64;
+        self.cluster_at((pos - 1) / cluster_size)
+    }
+
+    /// 保证簇链至少能容纳 `len` 字节, `zero` 为 true 时新分配的簇会被清零
+    fn reserve_clusters(&mut self, len: u64, zero: bool) -> Result<(), Error<()>> {
+        let cluster_size = FATFS.cluster_size() as u64;
+        let needed = (len + cluster_size - 1) / cluster_size;
+        let mut count = 0;
+        let mut last_cluster = None;
+        let mut cluster = self.first_cluster;
+        while let Some(n) = cluster {
+            count += 1;
+            last_cluster = Some(n);
+            cluster = get_next_cluster(n)?;
+        }
+        if count < needed {
+            // 一次分配剩余的所有簇, 尽量保证它们是连续的
+            let new_cluster = alloc_clusters(last_cluster, (needed - count) as u32, zero)?;
+            if self.first_cluster.is_none() {
+                self.set_first_cluster(new_cluster);
+            }
+        }
+        Ok(())
+    }
+
+    /// 写入 `len` 字节前预先分配簇链, 大块写入时可以拿到连续的簇
+    pub fn reserve_for_write(&mut self, len: u64) -> Result<(), Error<()>> {
+        let cluster_size = FATFS.cluster_size() as u64;
+        if self.pos > self.size() {
+            let pos = self.pos;
+            self.extend_to(pos)?;
+        }
+        if self.pos + len > self.size() && len > cluster_size {
+            let end = cmp::min(self.pos + len, MAX_FILE_SIZE as u64);
+            self.reserve_clusters(end, false)?;
+            self.current_cluster = self.cluster_for_pos(self.pos)?;
+        }
+        Ok(())
+    }
+
+    /// 把文件扩展到 `size`, 原文件尾所在簇的剩余部分清零, 保证新增部分读出为 0
+    fn extend_to(&mut self, size: u64) -> Result<(), Error<()>> {
+        let old_size = self.size();
+        self.reserve_clusters(size, true)?;
+        let cluster_size = FATFS.cluster_size() as u64;
+        let offset_in_cluster = old_size % cluster_size;
+        if offset_in_cluster != 0 {
+            let cluster = self
+                .cluster_at(old_size / cluster_size)?
+                .ok_or(Error::CorruptedFileSystem)?;
+            let len = cmp::min(cluster_size - offset_in_cluster, size - old_size);
+            let mut disk = BlockCacheManager::new();
+            disk.seek(SeekFrom::Start(cluster_to_offset(cluster) + offset_in_cluster))?;
+            write_zeros(&mut disk, len)?;
+        }
+        self.entry.set_size(size as u32);
+        Ok(())
+    }
+
+    /// 将文件截断或扩展到 `size` 字节, 截断时释放多余的簇
+    pub fn truncate(&mut self, size: u64) -> Result<(), Error<()>> {
+        if size > MAX_FILE_SIZE as u64 {
+            return Err(Error::InvalidInput);
+        }
+        if size > self.size() {
+            self.extend_to(size)?;
+        } else if size == 0 {
+            if let Some(n) = self.first_cluster.take() {
+                free_cluster_chain(n)?;
+            }
+            self.entry.set_first_cluster(None);
+            self.entry.set_size(0);
+        } else {
+            let cluster_size = FATFS.cluster_size() as u64;
+            if let Some(n) = self.cluster_at((size - 1) / cluster_size)? {
+                truncate_cluster_chain(n)?;
+            }
+            self.entry.set_size(size as u32);
+        }
+        if self.pos > size {
+            self.pos = size;
+        }
+        self.current_cluster = self.cluster_for_pos(self.pos)?;
+        self.entry.flush();
+        Ok(())
+    }
+
+    /// 为 `[offset, offset + len)` 预留簇, `keep_size` 为 false 时同时扩展文件大小
+    pub fn allocate(&mut self, offset: u64, len: u64, keep_size: bool) -> Result<(), Error<()>> {
+        let end = offset.checked_add(len).ok_or(Error::InvalidInput)?;
+        if end > MAX_FILE_SIZE as u64 {
+            return Err(Error::NotEnoughSpace);
+        }
+        if !keep_size && end > self.size() {
+            self.extend_to(end)?;
+        } else {
+            self.reserve_clusters(end, true)?;
+        }
+        self.current_cluster = self.cluster_for_pos(self.pos)?;
+        self.entry.flush();
+        Ok(())
     }
     pub fn stat(&self, stat: &mut Kstat) {
-        stat.st_dev = 1;
-        stat.sd_ino = 1;
-        stat.st_mode = 100000;
-        stat.st_nlink = 1;
-        stat.st_uid = 1;
-        stat.st_gid = 1;
-        stat.st_rdev = 1;
-        stat.st_size = self.size() as i64;
-        stat.st_blksize = 512;
-        stat.st_blocks = 0;
-        let access = self.entry.data.accessed();
-        let create = self.entry.data.created();
-        let modify = self.entry.data.modified();
+        let cluster_size = FATFS.cluster_size() as u64;
+        let clusters = (self.size() + cluster_size - 1) / cluster_size;
+        stat_entry(&self.entry.data, self.entry.pos, clusters, stat);
+    }
+
+    pub fn set_times(&mut self, accessed: Option<DateTime>, modified: Option<DateTime>) {
+        if let Some(accessed) = accessed {
+            self.entry.set_accessed(accessed.date);
+        }
+        if let Some(modified) = modified {
+            self.entry.set_modified(modified);
+        }
+        self.entry.flush();
+    }
+}
+
+/// 由目录项填充 Kstat, `entry_pos` 为目录项在磁盘上的位置, 根目录为 0
+fn stat_entry(data: &DirFileEntry, entry_pos: u64, clusters: u64, stat: &mut Kstat) {
+    let cluster_size = FATFS.cluster_size() as u64;
+    let (file_type, nlink) = if data.is_dir() {
+        (StatMode::S_IFDIR, 2)
+    } else {
+        (StatMode::S_IFREG, 1)
+    };
+    // FAT 没有权限位, 只读属性去掉所有写权限
+    let mut perm = StatMode::S_IRWXU | StatMode::S_IRWXG | StatMode::S_IRWXO;
+    if data.is_readonly() {
+        perm.remove(StatMode::S_IWUSR | StatMode::S_IWGRP | StatMode::S_IWOTH);
+    }
+    stat.st_dev = 1;
+    // 以目录项的序号作为 inode 号, 根目录没有目录项, 固定为 1
+    stat.sd_ino = if entry_pos == 0 {
+        1
+    } else {
+        entry_pos / u64::from(DIR_ENTRY_SIZE)
+    };
+    stat.st_mode = (file_type | perm).bits();
+    stat.st_nlink = nlink;
+    stat.st_uid = 0;
+    stat.st_gid = 0;
+    stat.st_rdev = 0;
+    stat.st_size = if data.is_dir() {
+        (clusters * cluster_size) as i64
+    } else {
+        data.size() as i64
+    };
+    stat.st_blksize = cluster_size as u32;
+    stat.st_blocks = clusters * cluster_size / 512;
+    if entry_pos == 0 {
+        // 根目录没有时间戳
         stat.st_atime_sec = 0;
+        stat.st_mtime_sec = 0;
+        stat.st_ctime_sec = 0;
         stat.st_atime_nsec = 0;
-        stat.st_mtime_sec = modify.time.sec() as i64;
-        stat.st_mtime_nsec = modify.time.msec() as i64 * 1000 ;
-        stat.st_ctime_sec = create.time.sec() as i64;
-        stat.st_ctime_nsec = create.time.msec() as i64 * 1000;
+        stat.st_mtime_nsec = 0;
+        stat.st_ctime_nsec = 0;
+        return;
     }
+    let (access_sec, _) = DateTime::new(data.accessed(), Time::new(0, 0, 0, 0)).unix_time();
+    let (modify_sec, modify_nsec) = data.modified().unix_time();
+    let (create_sec, create_nsec) = data.created().unix_time();
+    stat.st_atime_sec = access_sec as i64;
+    stat.st_atime_nsec = 0;
+    stat.st_mtime_sec = modify_sec as i64;
+    stat.st_mtime_nsec = modify_nsec as i64;
+    // FAT 不记录状态改变时间, 用创建时间代替
+    stat.st_ctime_sec = create_sec as i64;
+    stat.st_ctime_nsec = create_nsec as i64;
 }
 
 impl IoBase for FileEntry {
@@ -87,52 +266,50 @@ impl IoBase for FileEntry {
 
 impl Seek for FileEntry {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
-        let disk_offset = match pos {
-            SeekFrom::Start(x) => {
-                let offset = self.range.0 + x;
-                self.pos = x as u64;
-                offset
-            }
-            SeekFrom::End(x) => {
-                let offset = self.range.1 as i64 + x;
-                self.pos = x as u64;
-                offset as u64
-            }
-            SeekFrom::Current(x) => {
-                let offset = x + self.pos as i64;
-                self.range.0 + offset as u64
-            }
+        let new_pos = match pos {
+            SeekFrom::Start(x) => x as i64,
+            SeekFrom::End(x) => self.size() as i64 + x,
+            SeekFrom::Current(x) => self.pos as i64 + x,
         };
-        self.disk
-            .inner
-            .borrow_mut()
-            .seek(SeekFrom::Start(disk_offset))
-            .unwrap();
+        if new_pos < 0 {
+            return Err(());
+        }
+        self.current_cluster = self.cluster_for_pos(new_pos as u64).map_err(|_| ())?;
+        self.pos = new_pos as u64;
         Ok(self.pos)
     }
 }
 
 impl Read for FileEntry {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let mut disk = self.disk.inner.borrow_mut();
-        let off = self.size() - self.pos;
-        disk.seek(SeekFrom::Start(
-            cluster_to_offset(self.entry.inner().first_cluster().unwrap()) + self.pos,
-        ))
-        .unwrap();
-        match disk.read(buf) {
-            Ok(len) => {
-                let len = len as u64;
-                if off <= len {
-                    self.pos += off;
-                    Ok(off as usize)
-                } else {
-                    self.pos += len;
-                    Ok(len as usize)
-                }
-            }
-            Err(x) => Err(x),
+        let size = self.size();
+        if self.pos >= size {
+            return Ok(0);
         }
+        let cluster_size = FATFS.cluster_size() as u64;
+        let offset_in_cluster = self.pos % cluster_size;
+        let current_cluster = if offset_in_cluster == 0 {
+            match self.current_cluster {
+                Some(n) => get_next_cluster(n).unwrap(),
+                None => self.first_cluster,
+            }
+        } else {
+            self.current_cluster
+        };
+        let current_cluster = match current_cluster {
+            Some(n) => n,
+            None => return Ok(0),
+        };
+        let read_size = cmp::min(buf.len() as u64, cluster_size - offset_in_cluster);
+        let read_size = cmp::min(read_size, size - self.pos) as usize;
+        let mut disk = self.disk.exclusive_access();
+        disk.seek(SeekFrom::Start(
+            cluster_to_offset(current_cluster) + offset_in_cluster,
+        ))?;
+        let len = disk.read(&mut buf[..read_size])?;
+        self.pos += len as u64;
+        self.current_cluster = Some(current_cluster);
+        Ok(len)
     }
 }
 
@@ -147,15 +324,21 @@ impl Write for FileEntry {
         if write_size == 0 {
             return Ok(0);
         }
+        if self.pos > self.size() {
+            // 在文件尾之后写入, 中间的空洞需要清零
+            let pos = self.pos;
+            self.extend_to(pos).map_err(|_| ())?;
+            self.current_cluster = self.cluster_for_pos(pos).map_err(|_| ())?;
+        }
         let current_cluster = if self.pos % cluster_size == 0 {
             let next_cluster = match self.current_cluster {
-                Some(n) => return Err(()),
+                Some(n) => get_next_cluster(n).unwrap(),
                 None => self.first_cluster,
             };
             if let Some(n) = next_cluster {
                 n
             } else {
-                let new_cluster = alloc_cluster(self.current_cluster, false).unwrap();
+                let new_cluster = alloc_cluster(self.current_cluster, false).map_err(|_| ())?;
                 if self.first_cluster.is_none() {
                     self.set_first_cluster(new_cluster);
                 }
@@ -179,6 +362,7 @@ impl Write for FileEntry {
         self.pos += written_bytes as u64;
         self.current_cluster = Some(current_cluster);
         self.update_dir_entry_after_write();
+        self.entry.set_modified(get_current_date_time());
         self.entry.flush();
         Ok(written_bytes)
     }
@@ -193,6 +377,7 @@ pub enum Inode {
     Dir(DirEntry),
 }
 
+/// 访问目录项或修改 FAT 链的操作持有 `FileSystem::meta_lock`, 多个 hart 不会交错地分配簇、修改目录项
 impl Inode {
     pub fn ls(&mut self) {
         match self {
@@ -215,6 +400,7 @@ impl Inode {
     }
 
     pub fn create(&mut self, name: &str, isdir: bool) -> Option<Inode> {
+        let _meta = FATFS.meta_lock.lock();
         match self {
             Inode::File(_) => {}
             Inode::Dir(dir) => match isdir {
@@ -234,7 +420,17 @@ impl Inode {
         None
     }
 
+    /// O_CREAT | O_EXCL: 检查与创建在同一次加锁中完成, 同名的文件或目录已存在时返回 None
+    pub fn create_new(&mut self, name: &str) -> Option<Inode> {
+        let _meta = FATFS.meta_lock.lock();
+        match self {
+            Inode::File(_) => None,
+            Inode::Dir(dir) => dir.create_new_file(name).ok(),
+        }
+    }
+
     pub fn open(&mut self, name: &str, isdir: bool) -> Option<Inode> {
+        let _meta = FATFS.meta_lock.lock();
         match self {
             Inode::File(_) => {}
             Inode::Dir(dir) => match isdir {
@@ -257,22 +453,70 @@ impl Inode {
         match self {
             Inode::File(file) => {
                 file.seek(SeekFrom::Start(offset as u64)).unwrap();
-                file.read(buf).unwrap()
+                // 单次读不会跨越簇边界, 循环直到读满或到达文件尾
+                let mut total = 0;
+                while total < buf.len() {
+                    match file.read(&mut buf[total..]) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => total += n,
+                    }
+                }
+                total
             }
             Inode::Dir(_) => 0,
         }
     }
 
     pub fn write(&mut self, offset: usize, buf: &mut [u8]) -> usize {
+        let _meta = FATFS.meta_lock.lock();
         match self {
             Inode::File(file) => {
                 file.seek(SeekFrom::Start(offset as u64)).unwrap();
-                file.write(buf).unwrap()
+                // 预分配失败时退回逐簇分配, 尽量写入能写下的部分
+                let reserved = file.reserve_for_write(buf.len() as u64).is_ok();
+                let mut total = 0;
+                while total < buf.len() {
+                    match file.write(&buf[total..]) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => total += n,
+                    }
+                }
+                if reserved && total < buf.len() {
+                    // 释放预分配但没有写入的簇, 它们的内容未清零
+                    let size = file.size();
+                    file.truncate(size).ok();
+                }
+                total
             }
             Inode::Dir(_) => 0,
         }
     }
 
+    pub fn size(&self) -> usize {
+        match self {
+            Inode::File(file) => file.size() as usize,
+            Inode::Dir(_) => 0,
+        }
+    }
+
+    pub fn truncate(&mut self, size: usize) -> bool {
+        let _meta = FATFS.meta_lock.lock();
+        match self {
+            Inode::File(file) => file.truncate(size as u64).is_ok(),
+            Inode::Dir(_) => false,
+        }
+    }
+
+    pub fn allocate(&mut self, offset: usize, len: usize, keep_size: bool) -> bool {
+        let _meta = FATFS.meta_lock.lock();
+        match self {
+            Inode::File(file) => file
+                .allocate(offset as u64, len as u64, keep_size)
+                .is_ok(),
+            Inode::Dir(_) => false,
+        }
+    }
+
     pub fn read_all(&mut self, offset: usize) -> Vec<u8> {
         match self {
             Inode::File(file) => {
@@ -299,6 +543,7 @@ impl Inode {
     }
 
     pub fn remove(&mut self, path: &str) -> bool {
+        let _meta = FATFS.meta_lock.lock();
         match self {
             Inode::File(file) => false,
             Inode::Dir(dir) => dir.remove(path).is_ok(),
@@ -307,27 +552,32 @@ impl Inode {
     pub fn stat(&mut self, stat: &mut Kstat) {
         match self {
             Inode::File(file) => file.stat(stat),
-            Inode::Dir(dir) => {}
+            Inode::Dir(dir) => {
+                let mut clusters = 0;
+                let mut cluster = dir.dir_entry.first_cluster();
+                while let Some(n) = cluster {
+                    clusters += 1;
+                    cluster = get_next_cluster(n).unwrap();
+                }
+                stat_entry(&dir.dir_entry, dir.entry_pos, clusters, stat);
+            }
+        }
+    }
+    pub fn set_times(&mut self, accessed: Option<DateTime>, modified: Option<DateTime>) {
+        let _meta = FATFS.meta_lock.lock();
+        match self {
+            Inode::File(file) => file.set_times(accessed, modified),
+            Inode::Dir(dir) => dir.set_times(accessed, modified),
         }
     }
-    pub fn getdents(&mut self, dirent: &mut Dirent) -> isize {
+    /// 目录中的各项 (文件名, 是否为目录), 不是目录时返回 None
+    pub fn dir_entries(&mut self) -> Option<Vec<(String, bool)>> {
+        let _meta = FATFS.meta_lock.lock();
         match self {
-            Inode::File(_) => -1,
+            Inode::File(_) => None,
             Inode::Dir(dir) => {
-                dir.seek( SeekFrom::Start(0)).unwrap();
-                let current = dir.dirents;
-                let mut nread = 0;
-                dirent.d_ino = current as usize;
-                dirent.d_off = 0;
-                dirent.d_reclen = (dir.dirents - current) as u16;
-                unsafe {
-                    let d_name = core::slice::from_raw_parts_mut(dirent.d_name.as_ptr() as *mut u8, dir.file_name().len());
-                    d_name.clone_from_slice(dir.file_name().as_bytes());
-                }
-                for e in dir {
-                    nread+=1;
-                }
-                nread
+                dir.seek(SeekFrom::Start(0)).unwrap();
+                Some(dir.by_ref().map(|e| (e.file_name(), e.is_dir())).collect())
             }
         }
     }
@@ -1,14 +1,19 @@
-use crate::sync::UPSafeCell;
+use crate::sync::{KMutex, UPSafeCell};
 use alloc::sync::Arc;
 use core::convert::TryFrom;
 use core::{borrow::BorrowMut, cmp, marker::PhantomData};
 use log::{error, warn};
 
 use super::{
+    bitmap::ClusterBitmap,
     boot_sector::{BiosParameterBlock, BootSector},
-    io::{Error, IoBase, Read, ReadLeExt, ReadWriteSeek, Seek, SeekFrom, Write},
+    io::{Error, IoBase, Read, ReadLeExt, ReadWriteSeek, Seek, SeekFrom, Write, WriteLeExt},
     sdcard::BlockCacheManager,
-    table::table_alloc_cluster,
+    table::{
+        table_alloc_extent, table_free_cluster_chain, table_get_next_cluster,
+        table_read_status_flags, table_scan_free, table_set_dirty_flag,
+        table_truncate_cluster_chain,
+    },
 };
 
 pub struct FileSystem<IO: ReadWriteSeek> {
@@ -18,8 +23,16 @@ pub struct FileSystem<IO: ReadWriteSeek> {
     pub total_clusters: u32,
     pub first_data_sector: u32,
     pub fs_info: UPSafeCell<FsInfoSector>,
+    pub free_bitmap: UPSafeCell<ClusterBitmap>,
+    /// 挂载时读到的卷状态, 用于判断上次是否正常卸载
+    pub mount_status: FsStatusFlags,
+    /// 串行化 FAT 表、空闲位图、FsInfo 与目录项的多步更新, 由 `Inode` 的操作持有
+    pub meta_lock: KMutex<()>,
 }
 
+// FAT32 引导扇区中 BPB reserved_1 字段的偏移, Windows NT 用它保存卷状态
+const BPB_FAT32_STATUS_FLAGS_OFFSET: u64 = 0x41;
+
 pub trait IntoStorage<T: Read + Write + Seek> {
     fn into_storage(self) -> T;
 }
@@ -45,15 +58,105 @@ impl<IO: Read + Write + Seek> FileSystem<IO> {
             bpb.bytes_from_sectors(bpb.fs_info_sector()),
         ))?;
         let fs_info = FsInfoSector::deserialize(&mut disk)?;
-        unsafe {
-            Ok(Self {
+        let bpb_status = FsStatusFlags::decode(bpb.reserved_1);
+        let mut fs = unsafe {
+            Self {
                 disk: Arc::new(UPSafeCell::new(disk)),
                 root_dir_sectors,
                 first_data_sector,
                 total_clusters,
                 fs_info: UPSafeCell::new(fs_info),
+                free_bitmap: UPSafeCell::new(ClusterBitmap::new(total_clusters)),
+                mount_status: bpb_status,
+                meta_lock: KMutex::new(()),
                 bpb,
-            })
+            }
+        };
+        let fat_status = {
+            let mut fat = fs.fat_slice();
+            table_read_status_flags(&mut fat)
+        };
+        if let Ok(fat_status) = fat_status {
+            fs.mount_status = FsStatusFlags {
+                dirty: bpb_status.dirty || fat_status.dirty,
+                io_error: bpb_status.io_error || fat_status.io_error,
+            };
+        }
+        fs.rebuild_free_bitmap()
+            .map_err(|_| Error::CorruptedFileSystem)?;
+        Ok(fs)
+    }
+
+    /// 重新扫描 FAT 表构建空闲簇位图, 并据此校正 FsInfo 中的空闲簇数与下一个空闲簇提示
+    pub fn rebuild_free_bitmap(&self) -> Result<u32, Error<()>> {
+        let mut bitmap = ClusterBitmap::new(self.total_clusters);
+        {
+            let mut fat = self.fat_slice();
+            table_scan_free(&mut fat, self.total_clusters, |n| bitmap.set_free(n))?;
+        }
+        let free = bitmap.free_count();
+        let mut fs_info = self.fs_info.exclusive_access();
+        if fs_info.free_cluster_count != Some(free) {
+            warn!(
+                "[fatfs] FsInfo free cluster count {:?} differs from FAT ({})",
+                fs_info.free_cluster_count, free
+            );
+            fs_info.set_free_cluster_count(free);
+        }
+        let hint_valid = fs_info.next_free_cluster.map_or(false, |n| bitmap.is_free(n));
+        if !hint_valid {
+            if let Some(n) = bitmap.find_free(0) {
+                fs_info.set_next_free_cluster(n);
+            }
+        }
+        *self.free_bitmap.exclusive_access() = bitmap;
+        Ok(free)
+    }
+
+    /// 同时更新 BPB 与 FAT[1] 中的 dirty 标志, 挂载时置位, 正常卸载时清除
+    pub fn set_dirty_flag(&self, dirty: bool) -> Result<(), Error<()>> {
+        let flags = FsStatusFlags {
+            dirty,
+            io_error: self.mount_status.io_error,
+        };
+        let mut disk = BlockCacheManager::new();
+        disk.seek(SeekFrom::Start(BPB_FAT32_STATUS_FLAGS_OFFSET))?;
+        disk.write_u8(flags.encode())?;
+        let mut fat = self.fat_slice();
+        table_set_dirty_flag(&mut fat, dirty)
+    }
+
+    /// 将内存中的 FsInfo 写回磁盘
+    pub fn flush_fs_info(&self) -> Result<(), Error<()>> {
+        let mut fs_info = self.fs_info.exclusive_access();
+        if !fs_info.dirty {
+            return Ok(());
+        }
+        let mut disk = BlockCacheManager::new();
+        disk.seek(SeekFrom::Start(
+            self.bpb.bytes_from_sectors(self.bpb.fs_info_sector()),
+        ))?;
+        fs_info.serialize(&mut disk)?;
+        fs_info.dirty = false;
+        Ok(())
+    }
+
+    pub fn set_free_cluster_count(&self, free_cluster_count: u32) {
+        self.fs_info
+            .exclusive_access()
+            .set_free_cluster_count(free_cluster_count);
+    }
+
+    pub fn unmount(&self) -> Result<(), Error<()>> {
+        self.flush_fs_info()?;
+        self.set_dirty_flag(false)
+    }
+
+    pub fn stats(&self) -> FileSystemStats {
+        FileSystemStats {
+            cluster_size: self.cluster_size(),
+            total_clusters: self.total_clusters,
+            free_clusters: self.free_bitmap.exclusive_access().free_count(),
         }
     }
 
@@ -67,29 +170,78 @@ impl<IO: Read + Write + Seek> FileSystem<IO> {
         self.bpb.cluster_size()
     }
 
-    pub fn alloc_cluster(
+    pub fn alloc_cluster(&self, prev_cluster: Option<u32>, zero: bool) -> Result<u32, Error<()>> {
+        self.alloc_clusters(prev_cluster, 1, zero)
+    }
+
+    /// 分配 `count` 个簇并接在 `prev_cluster` 之后, 返回第一个新簇.
+    /// 优先分配连续的簇, 空间不够时不做任何分配
+    pub fn alloc_clusters(
         &self,
-        prev_cluster: Option<u32>,
+        mut prev_cluster: Option<u32>,
+        count: u32,
         zero: bool,
-    ) -> Result<u32, Error<IO::Error>> {
-        let hint = self.fs_info.inner.borrow_mut().next_free_cluster;
-        
-        let cluster = {
-            let mut fat = self.fat_slice();
-            match table_alloc_cluster(&mut fat, prev_cluster, hint, self.total_clusters) {
-                Ok(x) => x,
-                Err(_) => return Err(Error::AlreadyExists),
+    ) -> Result<u32, Error<()>> {
+        let mut bitmap = self.free_bitmap.exclusive_access();
+        if count == 0 || bitmap.free_count() < count {
+            return Err(Error::NotEnoughSpace);
+        }
+        let mut first_cluster = None;
+        let mut remaining = count;
+        while remaining > 0 {
+            let hint = self.fs_info.exclusive_access().next_free_cluster;
+            let (start, len) = bitmap
+                .find_extent(hint.unwrap_or(0), remaining)
+                .ok_or(Error::NotEnoughSpace)?;
+            {
+                let mut fat = self.fat_slice();
+                table_alloc_extent(&mut fat, prev_cluster, start, len)?;
             }
-        };
-        if zero {
-            let mut disk = self.disk.inner.borrow_mut();
-            disk.seek(SeekFrom::Start(self.offset_from_cluster(cluster)))?;
-            write_zeros(&mut *disk, u64::from(self.cluster_size()))?;
+            for n in start..start + len {
+                bitmap.set_used(n);
+            }
+            if zero {
+                let mut disk = BlockCacheManager::new();
+                disk.seek(SeekFrom::Start(self.offset_from_cluster(start)))?;
+                write_zeros(&mut disk, u64::from(len) * u64::from(self.cluster_size()))?;
+            }
+            let mut fs_info = self.fs_info.exclusive_access();
+            fs_info.set_next_free_cluster(start + len);
+            fs_info.set_free_cluster_count(bitmap.free_count());
+            first_cluster.get_or_insert(start);
+            prev_cluster = Some(start + len - 1);
+            remaining -= len;
+        }
+        Ok(first_cluster.unwrap())
+    }
+
+    pub fn get_next_cluster(&self, cluster: u32) -> Result<Option<u32>, Error<()>> {
+        let mut fat = self.fat_slice();
+        table_get_next_cluster(&mut fat, cluster)
+    }
+
+    pub fn free_cluster_chain(&self, first_cluster: u32) -> Result<(), Error<()>> {
+        let mut bitmap = self.free_bitmap.exclusive_access();
+        {
+            let mut fat = self.fat_slice();
+            table_free_cluster_chain(&mut fat, first_cluster, |n| bitmap.set_free(n))?;
+        }
+        self.fs_info
+            .exclusive_access()
+            .set_free_cluster_count(bitmap.free_count());
+        Ok(())
+    }
+
+    pub fn truncate_cluster_chain(&self, cluster: u32) -> Result<(), Error<()>> {
+        let mut bitmap = self.free_bitmap.exclusive_access();
+        {
+            let mut fat = self.fat_slice();
+            table_truncate_cluster_chain(&mut fat, cluster, |n| bitmap.set_free(n))?;
         }
-        let mut fs_info = self.fs_info.inner.borrow_mut();
-        fs_info.set_next_free_cluster(cluster + 1);
-        fs_info.map_free_clusters(|n| n - 1);
-        Ok(cluster)
+        self.fs_info
+            .exclusive_access()
+            .set_free_cluster_count(bitmap.free_count());
+        Ok(())
     }
 
     pub fn fat_slice(&self) -> impl ReadWriteSeek<Error = Error<()>> + '_ {
@@ -132,6 +284,14 @@ fn fat_slice<S: ReadWriteSeek, B: BorrowMut<S>>(
     DiskSlice::from_sectors(fat_first_sector, sectors_per_fat, mirrors, bpb, io)
 }
 
+/// 文件系统容量统计, 空闲簇数来自内存中的空闲簇位图
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct FileSystemStats {
+    pub cluster_size: u32,
+    pub total_clusters: u32,
+    pub free_clusters: u32,
+}
+
 #[derive(Clone, Default, Debug)]
 pub struct FsInfoSector {
     free_cluster_count: Option<u32>,
@@ -185,6 +345,19 @@ impl FsInfoSector {
         })
     }
 
+    fn serialize<W: Write>(&self, wrt: &mut W) -> Result<(), W::Error> {
+        wrt.write_u32_le(Self::LEAD_SIG)?;
+        let reserved = [0_u8; 480];
+        wrt.write_all(&reserved)?;
+        wrt.write_u32_le(Self::STRUC_SIG)?;
+        wrt.write_u32_le(self.free_cluster_count.unwrap_or(0xFFFF_FFFF))?;
+        wrt.write_u32_le(self.next_free_cluster.unwrap_or(0xFFFF_FFFF))?;
+        let reserved2 = [0_u8; 12];
+        wrt.write_all(&reserved2)?;
+        wrt.write_u32_le(Self::TRAIL_SIG)?;
+        Ok(())
+    }
+
     fn set_next_free_cluster(&mut self, cluster: u32) {
         self.next_free_cluster = Some(cluster);
         self.dirty = true;
@@ -0,0 +1,183 @@
+//! 挂载时发现卷没有被正常卸载后运行的一致性检查
+//!
+//! 只修复丢失的簇链和 FsInfo 中的空闲簇数, 交叉链接与损坏的目录项只报告,
+//! 需要用 fat32-fuse 的 `--check --repair` 在宿主机上修复
+use alloc::vec;
+use alloc::vec::Vec;
+use log::{info, warn};
+
+use super::{
+    fs::FileSystem,
+    io::{Read, Seek, SeekFrom},
+    lfn::{DIR_ENTRY_DELETED_FLAG, DIR_ENTRY_SIZE},
+    sdcard::BlockCacheManager,
+    table::{
+        table_free_unreferenced, table_get_next_cluster, table_is_free,
+        RESERVED_FAT_ENTRIES,
+    },
+};
+
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_LFN: u8 = 0x0F;
+
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub lost_clusters: u32,
+    pub cross_links: u32,
+    pub bad_entries: u32,
+    pub free_clusters: u32,
+}
+
+struct Checker<'a> {
+    fs: &'a FileSystem<BlockCacheManager>,
+    referenced: Vec<u64>,
+    report: FsckReport,
+}
+
+impl<'a> Checker<'a> {
+    fn new(fs: &'a FileSystem<BlockCacheManager>) -> Self {
+        let end_cluster = (fs.total_clusters + RESERVED_FAT_ENTRIES) as usize;
+        Self {
+            fs,
+            referenced: vec![0; (end_cluster + 63) / 64],
+            report: FsckReport::default(),
+        }
+    }
+
+    fn valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= RESERVED_FAT_ENTRIES && cluster < self.fs.total_clusters + RESERVED_FAT_ENTRIES
+    }
+
+    fn is_referenced(&self, cluster: u32) -> bool {
+        self.referenced[cluster as usize / 64] & (1 << (cluster % 64)) != 0
+    }
+
+    /// 标记一条簇链为已引用, 返回链上的簇
+    fn mark_chain(&mut self, first_cluster: u32) -> Vec<u32> {
+        let fs = self.fs;
+        let mut chain = Vec::new();
+        let mut fat = fs.fat_slice();
+        let mut cluster = Some(first_cluster);
+        while let Some(n) = cluster {
+            if !self.valid_cluster(n) {
+                warn!("[fsck] chain starting at {} points to invalid cluster {}", first_cluster, n);
+                self.report.bad_entries += 1;
+                break;
+            }
+            if self.is_referenced(n) {
+                warn!("[fsck] cluster {} is cross-linked (chain starting at {})", n, first_cluster);
+                self.report.cross_links += 1;
+                break;
+            }
+            self.referenced[n as usize / 64] |= 1 << (n % 64);
+            chain.push(n);
+            cluster = table_get_next_cluster(&mut fat, n).unwrap_or(None);
+        }
+        chain
+    }
+
+    /// 用显式的栈代替递归, 目录再深也不会耗尽内核栈.
+    /// 已引用的簇即访问过的簇, 目录项指回祖先目录时不会重复进入
+    fn check_tree(&mut self, root_cluster: u32) {
+        let mut pending = vec![root_cluster];
+        while let Some(dir) = pending.pop() {
+            self.check_dir(dir, &mut pending);
+        }
+    }
+
+    /// 检查一个目录的目录项, 子目录放入 pending 等待检查
+    fn check_dir(&mut self, first_cluster: u32, pending: &mut Vec<u32>) {
+        let fs = self.fs;
+        let chain = self.mark_chain(first_cluster);
+        let cluster_size = fs.cluster_size() as usize;
+        let mut buf = vec![0u8; cluster_size];
+        'outer: for cluster in chain {
+            let mut disk = BlockCacheManager::new();
+            disk.seek(SeekFrom::Start(fs.offset_from_cluster(cluster)))
+                .unwrap();
+            disk.read(&mut buf).unwrap();
+            for raw in buf.chunks(DIR_ENTRY_SIZE as usize) {
+                let attrs = raw[11];
+                if raw[0] == 0 {
+                    break 'outer;
+                }
+                if raw[0] == DIR_ENTRY_DELETED_FLAG
+                    || attrs & ATTR_LFN == ATTR_LFN
+                    || attrs & ATTR_VOLUME_ID != 0
+                    || raw[0] == b'.'
+                {
+                    continue;
+                }
+                let hi = u16::from_le_bytes([raw[20], raw[21]]) as u32;
+                let lo = u16::from_le_bytes([raw[26], raw[27]]) as u32;
+                let first = (hi << 16) | lo;
+                let size = u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]) as u64;
+                if first == 0 {
+                    if size != 0 || attrs & ATTR_DIRECTORY != 0 {
+                        warn!("[fsck] entry in dir cluster {} has no cluster but size {}", cluster, size);
+                        self.report.bad_entries += 1;
+                    }
+                    continue;
+                }
+                if !self.valid_cluster(first)
+                    || table_is_free(&mut fs.fat_slice(), first).unwrap_or(true)
+                {
+                    warn!("[fsck] entry in dir cluster {} points to bad cluster {}", cluster, first);
+                    self.report.bad_entries += 1;
+                    continue;
+                }
+                if attrs & ATTR_DIRECTORY != 0 {
+                    if self.is_referenced(first) {
+                        warn!("[fsck] dir in cluster {} loops back to cluster {}", cluster, first);
+                        self.report.cross_links += 1;
+                        continue;
+                    }
+                    pending.push(first);
+                } else {
+                    let len = self.mark_chain(first).len() as u64;
+                    let needed = (size + cluster_size as u64 - 1) / cluster_size as u64;
+                    if len != needed {
+                        warn!(
+                            "[fsck] file at cluster {} has {} clusters but size {}",
+                            first, len, size
+                        );
+                        self.report.bad_entries += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 检查卷. repair 为 true 时只回收丢失的簇并重建空闲簇计数,
+/// 交叉链接与损坏的目录项只计入报告, 不做修改
+pub fn check(fs: &FileSystem<BlockCacheManager>, repair: bool) -> FsckReport {
+    info!("[fsck] checking FAT volume");
+    let mut checker = Checker::new(fs);
+    checker.check_tree(fs.bpb.root_dir_first_cluster);
+    let Checker {
+        referenced,
+        mut report,
+        ..
+    } = checker;
+    let is_referenced = |n: u32| referenced[n as usize / 64] & (1 << (n % 64)) != 0;
+    {
+        let mut fat = fs.fat_slice();
+        if repair {
+            report.lost_clusters =
+                table_free_unreferenced(&mut fat, fs.total_clusters, is_referenced).unwrap();
+        }
+    }
+    // 修复后重建空闲簇位图, 同时校正 FsInfo
+    report.free_clusters = if repair {
+        fs.rebuild_free_bitmap().unwrap()
+    } else {
+        fs.stats().free_clusters
+    };
+    info!(
+        "[fsck] lost clusters {}, cross links {}, bad entries {}, free clusters {}",
+        report.lost_clusters, report.cross_links, report.bad_entries, report.free_clusters
+    );
+    report
+}
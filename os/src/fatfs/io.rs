@@ -114,7 +114,7 @@ pub trait Read: IoBase {
                         buf.push(cache[i])
                     }
                     size += len;
-                    if len < 512 {
+                    if len == 0 {
                         break;
                     }
                 }
@@ -1,8 +1,10 @@
 #![allow(unused)]
+pub mod bitmap;
 pub mod boot_sector;
 pub mod dir_entry;
 pub mod file;
 pub mod fs;
+pub mod fsck;
 pub mod io;
 pub mod lfn;
 pub mod sdcard;
@@ -15,7 +17,7 @@ use alloc::{
 };
 use fs::FileSystem;
 use lazy_static::lazy_static;
-use log::info;
+use log::{error, info, warn};
 use sdcard::BlockCacheManager;
 
 use crate::{
@@ -35,15 +37,55 @@ lazy_static! {
 }
 
 pub fn fs_init() {
+    if FATFS.mount_status.dirty() {
+        warn!("[fatfs] volume was not cleanly unmounted");
+        #[cfg(feature = "fsck")]
+        fsck::check(&FATFS, true);
+    }
+    FATFS.set_dirty_flag(true).unwrap();
+    crate::task::kthread_spawn(sdcard::writeback_daemon);
     root_dir().ls();
 
 }
 
+/// 关机前调用, 写回 FsInfo 并清除 dirty 标志.
+/// 最后的 sync_all 会等待 writeback_daemon 正在进行的写回, 返回后缓存中没有脏块
+pub fn fs_unmount() {
+    if FATFS.unmount().is_err() {
+        error!("[fatfs] failed to unmount volume cleanly");
+    }
+    sdcard::sync_all();
+}
+
 #[inline]
 pub fn alloc_cluster(prev_cluster: Option<u32>, zero: bool) -> Result<u32, Error<()>> {
     FATFS.alloc_cluster(prev_cluster, zero)
 }
 
+#[inline]
+pub fn alloc_clusters(
+    prev_cluster: Option<u32>,
+    count: u32,
+    zero: bool,
+) -> Result<u32, Error<()>> {
+    FATFS.alloc_clusters(prev_cluster, count, zero)
+}
+
+#[inline]
+pub fn get_next_cluster(cluster: u32) -> Result<Option<u32>, Error<()>> {
+    FATFS.get_next_cluster(cluster)
+}
+
+#[inline]
+pub fn free_cluster_chain(first_cluster: u32) -> Result<(), Error<()>> {
+    FATFS.free_cluster_chain(first_cluster)
+}
+
+#[inline]
+pub fn truncate_cluster_chain(cluster: u32) -> Result<(), Error<()>> {
+    FATFS.truncate_cluster_chain(cluster)
+}
+
 #[inline]
 pub fn root_dir() -> Inode {
     Inode::Dir(DirEntry::root_dir(FATFS.bpb.root_dir_first_cluster))
@@ -1,21 +1,62 @@
 use super::io::{IoBase, Read, Seek, SeekFrom, Write};
 use crate::drivers::BlockDevice;
 use crate::drivers::BLOCK_DEVICE;
-use crate::sync::UPSafeCell;
+use crate::sync::{KMutex, SpinNoIrq};
+use crate::task::kthread_sleep_ms;
 use alloc::collections::BTreeMap;
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
 use core::cmp::min;
 use core::convert::TryFrom;
 use k210_pac::dmac::id;
 use log::warn;
 lazy_static::lazy_static!(
-    pub static ref BLK_MANAGER: Arc<UPSafeCell<BlkManager>> = Arc::new(unsafe{UPSafeCell::new(BlkManager::new())});
+    pub static ref BLK_MANAGER: Arc<SpinNoIrq<BlkManager>> = Arc::new(SpinNoIrq::new(BlkManager::new()));
 );
+
+/// 脏块最多在缓存中停留的时间
+const WRITEBACK_INTERVAL_MS: usize = 1000;
+
+/// 同一时刻只有一个任务在锁外写回, 同一块的新旧内容不会乱序落盘
+static FLUSH_LOCK: KMutex<()> = KMutex::new(());
+
+/// 把缓存中所有的脏块写回磁盘. 持有 BLK_MANAGER 时只复制脏块, 磁盘 I/O 在释放后进行,
+/// 不会长时间关中断. 返回时调用前写入的数据都已落盘
+pub fn sync_all() {
+    let _flush = FLUSH_LOCK.lock();
+    let dirty = BLK_MANAGER.lock().take_dirty();
+    for (blk_id, cache) in dirty.iter() {
+        BLOCK_DEVICE.write_block(*blk_id, cache);
+    }
+    BLK_MANAGER
+        .lock()
+        .finish_flush(dirty.iter().map(|(blk_id, _)| *blk_id));
+}
+
+/// panic 时使用, 缓存正被持有时放弃, 不在这里死锁
+pub fn try_sync_all() -> bool {
+    match BLK_MANAGER.try_lock() {
+        Some(mut manager) => {
+            manager.flush_dirty();
+            true
+        }
+        None => false,
+    }
+}
+
+/// 内核线程, 定期写回脏块
+pub fn writeback_daemon() {
+    loop {
+        kthread_sleep_ms(WRITEBACK_INTERVAL_MS);
+        sync_all();
+    }
+}
 #[derive(Debug)]
 pub struct BlockCache {
     pub pos: usize,
     pub block_id: usize,
     pub dirty: bool,
+    /// 已被 sync_all 取走, 正在锁外写回, 不能换出也不能再由别处写回
+    pub flushing: bool,
     pub cache: [u8; 512],
 }
 
@@ -95,7 +136,7 @@ impl IoBase for BlockCacheManager {
 
 impl Read for BlockCacheManager {
     fn read(&mut self, mut buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let mut blk_manager = BLK_MANAGER.exclusive_access();
+        let mut blk_manager = BLK_MANAGER.lock();
         let start_pos = self.pos;
         while !buf.is_empty() {
             let offset = self.pos % 512;
@@ -117,7 +158,7 @@ impl Read for BlockCacheManager {
 
 impl Write for BlockCacheManager {
     fn write(&mut self, mut buf: &[u8]) -> Result<usize, Self::Error> {
-        let mut blk_manager = BLK_MANAGER.exclusive_access();
+        let mut blk_manager = BLK_MANAGER.lock();
         let start_pos = self.pos;
         while !buf.is_empty() {
             let offset = self.pos % 512;
@@ -137,7 +178,9 @@ impl Write for BlockCacheManager {
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        let mut blk_manager = BLK_MANAGER.exclusive_access();
+        // 等待锁外的写回结束, 避免它带着旧内容覆盖这里写下的块
+        let _flush = FLUSH_LOCK.lock();
+        let mut blk_manager = BLK_MANAGER.lock();
         let start = self.start / 512;
         let end = (self.start + self.size) / 512;
         for blk in start..end {
@@ -174,13 +217,15 @@ impl BlkManager {
 
     pub fn read_block_from_disk(&mut self, blk_id: usize) {
         if self.blocks.len() > 100 {
-            self.blocks.clear();
+            self.flush_dirty();
+            self.blocks.retain(|_, blk| blk.dirty || blk.flushing);
         }
 
         let mut blk = BlockCache {
             pos: 0,
             block_id: blk_id,
             dirty: false,
+            flushing: false,
             cache: [0; 512],
         };
         self.driver.read_block(blk_id, &mut blk.cache);
@@ -194,7 +239,45 @@ impl BlkManager {
     }
 
     pub fn sync_block(&mut self, blk_id: usize) {
-        self.blocks.remove(&blk_id);
+        if let Some(blk) = self.blocks.remove(&blk_id) {
+            if blk.dirty {
+                self.driver.write_block(blk_id, &blk.cache);
+            }
+        }
+    }
+
+    /// 写回所有脏块, 返回写回的块数. 正在锁外写回的块留给下一次 sync_all
+    pub fn flush_dirty(&mut self) -> usize {
+        let mut count = 0;
+        for (&blk_id, blk) in self
+            .blocks
+            .iter_mut()
+            .filter(|(_, blk)| blk.dirty && !blk.flushing)
+        {
+            self.driver.write_block(blk_id, &blk.cache);
+            blk.dirty = false;
+            count += 1;
+        }
+        count
+    }
+
+    /// 取走所有脏块的副本并标记为正在写回, 之后再写入的块重新变脏
+    pub fn take_dirty(&mut self) -> Vec<(usize, [u8; 512])> {
+        let mut dirty = Vec::new();
+        for (&blk_id, blk) in self.blocks.iter_mut().filter(|(_, blk)| blk.dirty) {
+            blk.dirty = false;
+            blk.flushing = true;
+            dirty.push((blk_id, blk.cache));
+        }
+        dirty
+    }
+
+    pub fn finish_flush(&mut self, blk_ids: impl Iterator<Item = usize>) {
+        for blk_id in blk_ids {
+            if let Some(blk) = self.blocks.get_mut(&blk_id) {
+                blk.flushing = false;
+            }
+        }
     }
 
     pub fn read_block(
@@ -222,15 +305,13 @@ impl BlkManager {
         if !self.blocks.contains_key(&blk_id) {
             self.read_block_from_disk(blk_id);
         };
-        let len = if let Some(blk) = self.blocks.get_mut(&blk_id) {
+        // 只标记为脏, 由 writeback_daemon 或 sync_all 写回
+        if let Some(blk) = self.blocks.get_mut(&blk_id) {
             let len = func.call_once((blk, buf));
-
             blk.dirty = true;
             len
         } else {
-            return 0;
-        };
-        self.write_block_to_disk(blk_id);
-        len
+            0
+        }
     }
 }
@@ -1,9 +1,13 @@
-use core::marker::PhantomData;
+use core::{cmp, marker::PhantomData};
 
 use log::{trace, warn};
 
+use super::fs::FsStatusFlags;
 use super::io::{self, Error, Read, ReadLeExt, Seek, Write, WriteLeExt};
 pub const RESERVED_FAT_ENTRIES: u32 = 2;
+// FAT32 第二个表项的高位: 置 1 表示卷已正常卸载 / 没有发生 IO 错误
+const FAT32_CLEAN_SHUTDOWN_BIT: u32 = 0x0800_0000;
+const FAT32_NO_IO_ERROR_BIT: u32 = 0x0400_0000;
 struct Fat<S> {
     phantom: PhantomData<S>,
 }
@@ -34,11 +38,7 @@ trait FatTrait {
     where
         S: Read + Write + Seek;
 
-    fn find_free<S>(fat: &mut S, start_cluster: u32, end_cluster: u32) -> Result<u32, Error<()>>
-    where
-        S: Read + Seek;
-
-    fn count_free<S>(fat: &mut S, end_cluster: u32) -> Result<u32, Error<()>>
+    fn scan_free<S>(fat: &mut S, end_cluster: u32, f: impl FnMut(u32)) -> Result<(), Error<()>>
     where
         S: Read + Seek;
 }
@@ -127,68 +127,162 @@ impl FatTrait for Fat32 {
         Self::set_raw(fat, cluster, raw_val)
     }
 
-    fn find_free<S>(fat: &mut S, start_cluster: u32, end_cluster: u32) -> Result<u32, Error<()>>
+    fn scan_free<S>(fat: &mut S, end_cluster: u32, mut f: impl FnMut(u32)) -> Result<(), Error<()>>
     where
         S: Read + Seek,
     {
-        let mut cluster = start_cluster;
-        
-        fat.seek(io::SeekFrom::Start(u64::from(cluster * 4)))
-            .unwrap();
-        while cluster < end_cluster {
-            let val = fat.read_u32_le().unwrap() & 0x0FFF_FFFF;
-            if val == 0 {
-                return Ok(cluster);
-            }
-            cluster += 1;
-        }
-        Err(Error::NotEnoughSpace)
-    }
-
-    fn count_free<S>(fat: &mut S, end_cluster: u32) -> Result<u32, Error<()>>
-    where
-        S: Read + Seek,
-    {
-        let mut count = 0;
+        // 按扇区读取, 避免每个表项都经过一次块缓存查找
+        let mut buf = [0_u8; 512];
         let mut cluster = RESERVED_FAT_ENTRIES;
         fat.seek(io::SeekFrom::Start(u64::from(cluster * 4)))
             .unwrap();
         while cluster < end_cluster {
-            let val = fat.read_u32_le().unwrap() & 0x0FFF_FFFF;
-            if val == 0 {
-                count += 1;
+            let entries = cmp::min((end_cluster - cluster) as usize, buf.len() / 4);
+            fat.read_exact(&mut buf[..entries * 4]).unwrap();
+            for raw in buf[..entries * 4].chunks(4) {
+                let val = u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) & 0x0FFF_FFFF;
+                if val == 0 {
+                    f(cluster);
+                }
+                cluster += 1;
             }
-            cluster += 1;
         }
-        Ok(count)
+        Ok(())
     }
 }
 
-pub fn table_alloc_cluster<S>(
+/// 把 `[start, start + len)` 连成一条簇链并接在 `prev_cluster` 之后,
+/// 调用者需保证这些簇都是空闲的
+pub fn table_alloc_extent<S>(
     fat: &mut S,
     prev_cluster: Option<u32>,
-    hint: Option<u32>,
+    start: u32,
+    len: u32,
+) -> Result<(), Error<()>>
+where
+    S: Read + Write + Seek,
+{
+    let end = start + len;
+    for cluster in start..end - 1 {
+        Fat32::set(fat, cluster, FatValue::Data(cluster + 1))?;
+    }
+    Fat32::set(fat, end - 1, FatValue::EndOfChain)?;
+    if let Some(n) = prev_cluster {
+        Fat32::set(fat, n, FatValue::Data(start))?;
+    }
+    trace!("allocated clusters {}..{}", start, end);
+    Ok(())
+}
+
+pub fn table_get_next_cluster<S>(fat: &mut S, cluster: u32) -> Result<Option<u32>, Error<()>>
+where
+    S: Read + Seek,
+{
+    match Fat32::get(fat, cluster)? {
+        FatValue::Data(n) => Ok(Some(n)),
+        _ => Ok(None),
+    }
+}
+
+/// 释放从 `cluster` 开始的整条簇链, 每释放一个簇调用一次 `freed`, 返回释放的簇数
+pub fn table_free_cluster_chain<S>(
+    fat: &mut S,
+    cluster: u32,
+    mut freed: impl FnMut(u32),
+) -> Result<u32, Error<()>>
+where
+    S: Read + Write + Seek,
+{
+    let mut cluster = Some(cluster);
+    let mut num_free = 0;
+    while let Some(n) = cluster {
+        cluster = table_get_next_cluster(fat, n)?;
+        Fat32::set(fat, n, FatValue::Free)?;
+        freed(n);
+        num_free += 1;
+    }
+    trace!("freed {} clusters", num_free);
+    Ok(num_free)
+}
+
+/// 将 `cluster` 设为链尾, 释放其后的所有簇, 返回释放的簇数
+pub fn table_truncate_cluster_chain<S>(
+    fat: &mut S,
+    cluster: u32,
+    freed: impl FnMut(u32),
+) -> Result<u32, Error<()>>
+where
+    S: Read + Write + Seek,
+{
+    let next = table_get_next_cluster(fat, cluster)?;
+    Fat32::set(fat, cluster, FatValue::EndOfChain)?;
+    match next {
+        Some(n) => table_free_cluster_chain(fat, n, freed),
+        None => Ok(0),
+    }
+}
+
+pub fn table_is_free<S>(fat: &mut S, cluster: u32) -> Result<bool, Error<()>>
+where
+    S: Read + Seek,
+{
+    Ok(Fat32::get(fat, cluster)? == FatValue::Free)
+}
+
+pub fn table_read_status_flags<S>(fat: &mut S) -> Result<FsStatusFlags, Error<()>>
+where
+    S: Read + Seek,
+{
+    let val = Fat32::get_raw(fat, 1)?;
+    Ok(FsStatusFlags {
+        dirty: val & FAT32_CLEAN_SHUTDOWN_BIT == 0,
+        io_error: val & FAT32_NO_IO_ERROR_BIT == 0,
+    })
+}
+
+pub fn table_set_dirty_flag<S>(fat: &mut S, dirty: bool) -> Result<(), Error<()>>
+where
+    S: Read + Write + Seek,
+{
+    let val = Fat32::get_raw(fat, 1)?;
+    let new_val = if dirty {
+        val & !FAT32_CLEAN_SHUTDOWN_BIT
+    } else {
+        val | FAT32_CLEAN_SHUTDOWN_BIT
+    };
+    if new_val != val {
+        Fat32::set_raw(fat, 1, new_val)?;
+    }
+    Ok(())
+}
+
+/// 释放所有已分配但 `is_referenced` 返回 false 的簇 (丢失的簇链), 返回释放的簇数
+pub fn table_free_unreferenced<S>(
+    fat: &mut S,
     total_clusters: u32,
+    is_referenced: impl Fn(u32) -> bool,
 ) -> Result<u32, Error<()>>
 where
     S: Read + Write + Seek,
 {
     let end_cluster = total_clusters + RESERVED_FAT_ENTRIES;
-    let start_cluster = match hint {
-        Some(n) if n < end_cluster => n,
-        _ => RESERVED_FAT_ENTRIES,
-    };
-    let new_cluster = match Fat32::find_free(fat, start_cluster, end_cluster) {
-        Ok(n) => n,
-        Err(_) if start_cluster > RESERVED_FAT_ENTRIES => {
-            Fat32::find_free(fat, RESERVED_FAT_ENTRIES, end_cluster).unwrap()
+    let mut num_free = 0;
+    for cluster in RESERVED_FAT_ENTRIES..end_cluster {
+        match Fat32::get(fat, cluster)? {
+            FatValue::Data(_) | FatValue::EndOfChain if !is_referenced(cluster) => {
+                Fat32::set(fat, cluster, FatValue::Free)?;
+                num_free += 1;
+            }
+            _ => {}
         }
-        Err(e) => return Err(e),
-    };
-    Fat32::set(fat, new_cluster, FatValue::EndOfChain).unwrap();
-    if let Some(n) = prev_cluster {
-        Fat32::set(fat, n, FatValue::Data(new_cluster)).unwrap();
     }
-    trace!("allocated cluster {}", new_cluster);
-    Ok(new_cluster)
+    Ok(num_free)
+}
+
+/// 对每个空闲簇调用一次 `f`, 用于挂载时构建空闲簇位图
+pub fn table_scan_free<S>(fat: &mut S, total_clusters: u32, f: impl FnMut(u32)) -> Result<(), Error<()>>
+where
+    S: Read + Seek,
+{
+    Fat32::scan_free(fat, total_clusters + RESERVED_FAT_ENTRIES, f)
 }
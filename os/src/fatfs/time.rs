@@ -1,4 +1,4 @@
-use crate::timer::{get_time_ms, get_time_sec};
+use crate::timer::{get_realtime_ns, NSEC_PER_SEC};
 
 const MIN_YEAR: u16 = 1980;
 const MAX_YEAR: u16 = 2107;
@@ -6,6 +6,7 @@ const MIN_MONTH: u16 = 1;
 const MAX_MONTH: u16 = 12;
 const MIN_DAY: u16 = 1;
 const MAX_DAY: u16 = 31;
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
 
 /// A DOS compatible date.
 ///
@@ -54,6 +55,39 @@ impl Date {
     pub(crate) fn encode(self) -> u16 {
         ((self.year - MIN_YEAR) << 9) | (self.month << 5) | self.day
     }
+
+    /// 由 1970-01-01 起的天数换算日期, 超出 FAT 可表示范围时取边界值
+    pub fn from_unix_days(days: u64) -> Self {
+        // Howard Hinnant 的 civil_from_days 算法
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        if year < MIN_YEAR as u64 {
+            Self::new(MIN_YEAR, MIN_MONTH, MIN_DAY)
+        } else if year > MAX_YEAR as u64 {
+            Self::new(MAX_YEAR, MAX_MONTH, MAX_DAY)
+        } else {
+            Self::new(year as u16, month as u16, day as u16)
+        }
+    }
+
+    /// 自 1970-01-01 起的天数
+    pub fn unix_days(self) -> u64 {
+        let year = u64::from(self.year) - if self.month <= 2 { 1 } else { 0 };
+        let month = u64::from(self.month);
+        let era = year / 400;
+        let yoe = year - era * 400;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + u64::from(self.day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
 }
 
 /// A DOS compatible time.
@@ -119,7 +153,7 @@ impl Time {
     }
 
     pub fn sec(self) -> u64 {
-        (self.hour * 60 * 60 + self.min * 60 + self.sec) as u64
+        u64::from(self.hour) * 60 * 60 + u64::from(self.min) * 60 + u64::from(self.sec)
     }
 
     pub fn msec(self) -> u64 {
@@ -146,22 +180,30 @@ impl DateTime {
             Time::decode(dos_time, dos_time_hi_res),
         )
     }
-}
-#[inline]
-fn get_current_date() -> Date {
-    Date::new(1980, 1, 1)
-}
-#[inline]
-fn get_current_time() -> Time {
-    let current = get_time_ms();
-    let millis = current / 1000;
-    let sec = current / (1000 * 60);
-    let min = current / (1000 * 60 * 60);
-    let hour = current / (1000 * 60 * 60 * 60);
-
-    Time::new(hour as u16, min as u16, sec as u16, millis as u16)
+
+    /// 由 Unix 时间 (纳秒) 换算, FAT 中的时间按 UTC 保存
+    pub fn from_unix_ns(ns: u64) -> Self {
+        let secs = ns / NSEC_PER_SEC;
+        let millis = (ns % NSEC_PER_SEC / 1_000_000) as u16;
+        let date = Date::from_unix_days(secs / SECS_PER_DAY);
+        let secs_of_day = secs % SECS_PER_DAY;
+        let time = Time::new(
+            (secs_of_day / 3600) as u16,
+            (secs_of_day / 60 % 60) as u16,
+            (secs_of_day % 60) as u16,
+            millis,
+        );
+        Self::new(date, time)
+    }
+
+    /// 换算为 Unix 时间, 返回 (秒, 纳秒)
+    pub fn unix_time(self) -> (u64, u64) {
+        let secs = self.date.unix_days() * SECS_PER_DAY + self.time.sec();
+        (secs, u64::from(self.time.millis) * 1_000_000)
+    }
 }
 
+/// 从 RTC 读取墙上时间
 pub fn get_current_date_time() -> DateTime {
-    DateTime::new(get_current_date(), get_current_time())
+    DateTime::from_unix_ns(get_realtime_ns())
 }
@@ -0,0 +1,164 @@
+//! 启动时从 SBI 传来的设备树中读出内存大小、时钟频率与各外设的位置
+mod parser;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+use parser::Fdt;
+
+/// 一个 virtio-mmio 槽位, 上面可能没有挂设备
+#[derive(Debug, Clone, Copy)]
+pub struct VirtioSlot {
+    pub base: usize,
+    pub size: usize,
+    pub irq: usize,
+}
+
+pub struct MachineInfo {
+    pub memory_start: usize,
+    pub memory_end: usize,
+    /// time CSR 的计数频率
+    pub timebase_freq: usize,
+    pub uart: (usize, usize),
+    pub uart_irq: usize,
+    pub plic: (usize, usize),
+    pub rtc: Option<(usize, usize)>,
+    /// 按地址从小到大排列
+    pub virtio: Vec<VirtioSlot>,
+    /// /chosen 中的内核命令行
+    pub bootargs: String,
+    /// 所有 hart 的 id
+    pub harts: Vec<usize>,
+}
+
+impl Default for MachineInfo {
+    /// 没有设备树时按 QEMU virt 板 128M 内存的布局
+    fn default() -> Self {
+        Self {
+            memory_start: 0x8000_0000,
+            memory_end: 0x8800_0000,
+            timebase_freq: 10_000_000,
+            uart: (0x1000_0000, 0x100),
+            uart_irq: 10,
+            plic: (0x0C00_0000, 0x40_0000),
+            rtc: Some((0x0010_1000, 0x1000)),
+            virtio: (0..8)
+                .map(|i| VirtioSlot {
+                    base: 0x1000_1000 + i * 0x1000,
+                    size: 0x1000,
+                    irq: i + 1,
+                })
+                .collect(),
+            bootargs: String::new(),
+            harts: Vec::from([0]),
+        }
+    }
+}
+
+impl MachineInfo {
+    fn from_fdt(fdt: &Fdt) -> Self {
+        let mut info = Self::default();
+        let mut virtio = Vec::new();
+        let mut harts = Vec::new();
+        for node in fdt.nodes() {
+            let reg = node.reg().first().copied();
+            let irq = node.prop_u32("interrupts").map(|irq| irq as usize);
+            match node.base_name() {
+                "memory" if node.prop_str("device_type") == Some("memory") => {
+                    if let Some((start, size)) = reg {
+                        info.memory_start = start;
+                        info.memory_end = start + size;
+                    }
+                }
+                "cpus" => {
+                    if let Some(freq) = node.prop_u32("timebase-frequency") {
+                        info.timebase_freq = freq as usize;
+                    }
+                }
+                "cpu" if node.prop_str("device_type") == Some("cpu") => {
+                    if let Some((hart, _)) = reg {
+                        harts.push(hart);
+                    }
+                }
+                "chosen" => {
+                    if let Some(bootargs) = node.prop_str("bootargs") {
+                        info.bootargs = bootargs.to_string();
+                    }
+                }
+                _ => {}
+            }
+            let reg = match reg {
+                Some(reg) => reg,
+                None => continue,
+            };
+            if node.is_compatible("virtio,mmio") {
+                virtio.push(VirtioSlot {
+                    base: reg.0,
+                    size: reg.1,
+                    irq: irq.unwrap_or(0),
+                });
+            } else if node.is_compatible("ns16550a") {
+                info.uart = reg;
+                info.uart_irq = irq.unwrap_or(info.uart_irq);
+            } else if node.is_compatible("riscv,plic0") || node.is_compatible("sifive,plic-1.0.0") {
+                info.plic = reg;
+            } else if node.is_compatible("google,goldfish-rtc") {
+                info.rtc = Some(reg);
+            }
+        }
+        if !virtio.is_empty() {
+            virtio.sort_by_key(|slot| slot.base);
+            info.virtio = virtio;
+        }
+        if !harts.is_empty() {
+            harts.sort();
+            info.harts = harts;
+        }
+        info
+    }
+
+    /// 需要在内核地址空间中恒等映射的设备寄存器
+    pub fn mmio_regions(&self) -> Vec<(usize, usize)> {
+        let mut regions = Vec::from([self.plic, self.uart]);
+        regions.extend(self.rtc);
+        regions.extend(self.virtio.iter().map(|slot| (slot.base, slot.size)));
+        regions
+    }
+}
+
+static DTB_ADDR: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    pub static ref MACHINE: MachineInfo =
+        match unsafe { Fdt::from_ptr(DTB_ADDR.load(Ordering::Relaxed)) } {
+            Some(fdt) => MachineInfo::from_fdt(&fdt),
+            None => MachineInfo::default(),
+        };
+}
+
+/// 必须在使用串口和分配物理页之前调用, 之后设备树所在的内存可能被覆盖
+pub fn init(dtb: usize) {
+    DTB_ADDR.store(dtb, Ordering::Relaxed);
+    lazy_static::initialize(&MACHINE);
+}
+
+pub fn print_machine_info() {
+    println!(
+        "[kernel] memory [{:#x}, {:#x}), timebase {} Hz, {} harts",
+        MACHINE.memory_start,
+        MACHINE.memory_end,
+        MACHINE.timebase_freq,
+        MACHINE.harts.len()
+    );
+    println!(
+        "[kernel] uart {:#x} irq {}, plic {:#x}",
+        MACHINE.uart.0, MACHINE.uart_irq, MACHINE.plic.0
+    );
+    if !MACHINE.bootargs.is_empty() {
+        println!("[kernel] bootargs: {}", MACHINE.bootargs);
+    }
+    if DTB_ADDR.load(Ordering::Relaxed) == 0 {
+        println!("[kernel] no device tree, using QEMU virt defaults");
+    }
+}
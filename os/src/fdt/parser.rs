@@ -0,0 +1,192 @@
+//! 扁平设备树 (FDT/DTB) 的最小解析器, 只读取结构块中的节点和属性
+use alloc::vec::Vec;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+// 规范规定的缺省值
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+fn be32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// 以 0 结尾的字符串, 不是合法 UTF-8 时返回空串
+fn c_str(data: &[u8], offset: usize) -> &str {
+    let len = data[offset..].iter().position(|&c| c == 0).unwrap_or(0);
+    core::str::from_utf8(&data[offset..offset + len]).unwrap_or("")
+}
+
+pub struct Property<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+pub struct Node<'a> {
+    /// 包括 @ 之后的单元地址, 根节点为空串
+    pub name: &'a str,
+    /// 父节点的 #address-cells 与 #size-cells, 决定 reg 的格式
+    pub address_cells: u32,
+    pub size_cells: u32,
+    pub props: Vec<Property<'a>>,
+}
+
+impl<'a> Node<'a> {
+    pub fn prop(&self, name: &str) -> Option<&'a [u8]> {
+        self.props
+            .iter()
+            .find(|prop| prop.name == name)
+            .map(|prop| prop.value)
+    }
+
+    pub fn prop_u32(&self, name: &str) -> Option<u32> {
+        self.prop(name)
+            .filter(|value| value.len() >= 4)
+            .map(|value| be32(value, 0))
+    }
+
+    /// 字符串属性, 去掉结尾的 0
+    pub fn prop_str(&self, name: &str) -> Option<&'a str> {
+        let value = self.prop(name)?;
+        let len = value.iter().position(|&c| c == 0).unwrap_or(value.len());
+        core::str::from_utf8(&value[..len]).ok()
+    }
+
+    /// 节点名去掉 @ 之后的部分
+    pub fn base_name(&self) -> &'a str {
+        self.name.split('@').next().unwrap()
+    }
+
+    /// compatible 是以 0 分隔的字符串列表
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.prop("compatible").map_or(false, |value| {
+            value
+                .split(|&c| c == 0)
+                .any(|item| item == compatible.as_bytes())
+        })
+    }
+
+    /// 按父节点的 cells 解析 reg, 返回 (地址, 长度) 列表
+    pub fn reg(&self) -> Vec<(usize, usize)> {
+        let value = match self.prop("reg") {
+            Some(value) => value,
+            None => return Vec::new(),
+        };
+        let read_cells = |offset: usize, cells: u32| {
+            (0..cells as usize).fold(0usize, |acc, i| {
+                (acc << 32) | be32(value, offset + i * 4) as usize
+            })
+        };
+        let entry_size = (self.address_cells + self.size_cells) as usize * 4;
+        if entry_size == 0 {
+            return Vec::new();
+        }
+        (0..value.len() / entry_size)
+            .map(|i| {
+                let offset = i * entry_size;
+                let addr = read_cells(offset, self.address_cells);
+                let size = read_cells(offset + self.address_cells as usize * 4, self.size_cells);
+                (addr, size)
+            })
+            .collect()
+    }
+}
+
+pub struct Fdt<'a> {
+    data: &'a [u8],
+    struct_offset: usize,
+    strings_offset: usize,
+}
+
+impl<'a> Fdt<'a> {
+    /// # Safety
+    ///
+    /// addr 必须指向一份完整且在使用期间不会被改写的设备树
+    pub unsafe fn from_ptr(addr: usize) -> Option<Self> {
+        if addr == 0 || addr % 4 != 0 {
+            return None;
+        }
+        let header = core::slice::from_raw_parts(addr as *const u8, 40);
+        if be32(header, 0) != FDT_MAGIC {
+            return None;
+        }
+        let total_size = be32(header, 4) as usize;
+        let data = core::slice::from_raw_parts(addr as *const u8, total_size);
+        Some(Self {
+            data,
+            struct_offset: be32(header, 8) as usize,
+            strings_offset: be32(header, 12) as usize,
+        })
+    }
+
+    /// 按先序遍历的顺序返回所有节点
+    pub fn nodes(&self) -> Vec<Node<'a>> {
+        let data = self.data;
+        let mut nodes: Vec<Node<'a>> = Vec::new();
+        // 当前路径上各节点在 nodes 中的下标
+        let mut stack: Vec<usize> = Vec::new();
+        let mut offset = self.struct_offset;
+        loop {
+            let token = be32(data, offset);
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = c_str(data, offset);
+                    offset = align4(offset + name.len() + 1);
+                    let (address_cells, size_cells) = match stack.last() {
+                        Some(&parent) => (
+                            nodes[parent]
+                                .prop_u32("#address-cells")
+                                .unwrap_or(DEFAULT_ADDRESS_CELLS),
+                            nodes[parent]
+                                .prop_u32("#size-cells")
+                                .unwrap_or(DEFAULT_SIZE_CELLS),
+                        ),
+                        None => (DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS),
+                    };
+                    stack.push(nodes.len());
+                    nodes.push(Node {
+                        name,
+                        address_cells,
+                        size_cells,
+                        props: Vec::new(),
+                    });
+                }
+                FDT_END_NODE => {
+                    stack.pop();
+                }
+                FDT_PROP => {
+                    let len = be32(data, offset) as usize;
+                    let name_offset = be32(data, offset + 4) as usize;
+                    offset += 8;
+                    let prop = Property {
+                        name: c_str(data, self.strings_offset + name_offset),
+                        value: &data[offset..offset + len],
+                    };
+                    offset = align4(offset + len);
+                    if let Some(&current) = stack.last() {
+                        nodes[current].props.push(prop);
+                    }
+                }
+                FDT_NOP => {}
+                FDT_END => break,
+                _ => break,
+            }
+        }
+        nodes
+    }
+}
@@ -0,0 +1,42 @@
+//! /proc/cmdline, 只读, 内容是内核命令行加换行
+use super::{File, Kstat, StatMode};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+
+pub struct CmdlineFile {
+    offset: UPSafeCell<usize>,
+}
+
+impl CmdlineFile {
+    pub fn open() -> Arc<Self> {
+        Arc::new(Self {
+            offset: unsafe { UPSafeCell::new(0) },
+        })
+    }
+}
+
+impl File for CmdlineFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, mut user_buf: UserBuffer) -> usize {
+        let content = crate::cmdline::cmdline().to_string() + "\n";
+        let mut offset = self.offset.exclusive_access();
+        let start = (*offset).min(content.len());
+        let len = user_buf.write(&content.as_bytes()[start..]);
+        *offset = start + len;
+        len
+    }
+    fn name(&self) -> String {
+        "cmdline".to_string()
+    }
+    fn kstat(&self, stat: &mut Kstat) {
+        stat.st_mode = (StatMode::S_IFREG | StatMode::S_IRUSR).bits();
+        stat.st_nlink = 1;
+    }
+}
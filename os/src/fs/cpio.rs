@@ -0,0 +1,53 @@
+//! cpio newc 格式 (magic 070701) 的解析, 用来解开内嵌在内核中的 initramfs
+//!
+//! 每一项是 110 字节的头部、文件名、文件内容, 文件名与内容各自按 4 字节对齐,
+//! 以名为 `TRAILER!!!` 的项结束
+const NEWC_MAGIC: &[u8] = b"070701";
+const HEADER_LEN: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+// 头部中各个 8 位十六进制字段的序号
+const FIELD_MODE: usize = 1;
+const FIELD_FILESIZE: usize = 6;
+const FIELD_NAMESIZE: usize = 11;
+
+pub struct CpioEntry<'a> {
+    pub name: &'a str,
+    pub mode: u32,
+    pub data: &'a [u8],
+}
+
+fn align4(x: usize) -> usize {
+    (x + 3) & !3
+}
+
+fn hex_field(header: &[u8], index: usize) -> Option<usize> {
+    let start = NEWC_MAGIC.len() + index * 8;
+    let field = core::str::from_utf8(header.get(start..start + 8)?).ok()?;
+    usize::from_str_radix(field, 16).ok()
+}
+
+/// 依次返回归档中的各项, 遇到结束项或格式错误时停止
+pub fn entries(archive: &[u8]) -> impl Iterator<Item = CpioEntry<'_>> {
+    let mut pos = 0;
+    core::iter::from_fn(move || {
+        let header = archive.get(pos..pos + HEADER_LEN)?;
+        if !header.starts_with(NEWC_MAGIC) {
+            return None;
+        }
+        let mode = hex_field(header, FIELD_MODE)? as u32;
+        let filesize = hex_field(header, FIELD_FILESIZE)?;
+        let namesize = hex_field(header, FIELD_NAMESIZE)?;
+        // namesize 包括结尾的 '\0'
+        let name_start = pos + HEADER_LEN;
+        let name = archive.get(name_start..name_start + namesize.checked_sub(1)?)?;
+        let name = core::str::from_utf8(name).ok()?;
+        if name == TRAILER {
+            return None;
+        }
+        let data_start = align4(name_start + namesize);
+        let data = archive.get(data_start..data_start + filesize)?;
+        pos = align4(data_start + filesize);
+        Some(CpioEntry { name, mode, data })
+    })
+}
@@ -0,0 +1,213 @@
+//! 帧缓冲设备 /dev/fb0, 可以 read/write 也可以 mmap 到用户空间直接绘制
+use super::{File, Kstat, MmapRegion, StatMode};
+use crate::drivers::gpu::{map_framebuffer, GpuDevice, GPU_DEVICE};
+use crate::fatfs::io::SeekFrom;
+use crate::mm::{translated_refmut, UserBuffer};
+use crate::sync::UPSafeCell;
+use crate::task::current_user_token;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+
+const FBIOGET_VSCREENINFO: usize = 0x4600;
+const FBIOGET_FSCREENINFO: usize = 0x4602;
+
+const FB_TYPE_PACKED_PIXELS: u32 = 0;
+const FB_VISUAL_TRUECOLOR: u32 = 2;
+const BITS_PER_PIXEL: u32 = 32;
+
+// 帧缓冲设备的设备号 (29, 0)
+const FB_RDEV: u64 = 29 << 8;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FbBitfield {
+    pub offset: u32,
+    pub length: u32,
+    pub msb_right: u32,
+}
+
+impl FbBitfield {
+    const fn new(offset: u32, length: u32) -> Self {
+        Self {
+            offset,
+            length,
+            msb_right: 0,
+        }
+    }
+}
+
+/// 与 Linux 的 struct fb_var_screeninfo 布局相同
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FbVarScreenInfo {
+    pub xres: u32,
+    pub yres: u32,
+    pub xres_virtual: u32,
+    pub yres_virtual: u32,
+    pub xoffset: u32,
+    pub yoffset: u32,
+    pub bits_per_pixel: u32,
+    pub grayscale: u32,
+    pub red: FbBitfield,
+    pub green: FbBitfield,
+    pub blue: FbBitfield,
+    pub transp: FbBitfield,
+    pub nonstd: u32,
+    pub activate: u32,
+    pub height: u32,
+    pub width: u32,
+    pub accel_flags: u32,
+    pub pixclock: u32,
+    pub left_margin: u32,
+    pub right_margin: u32,
+    pub upper_margin: u32,
+    pub lower_margin: u32,
+    pub hsync_len: u32,
+    pub vsync_len: u32,
+    pub sync: u32,
+    pub vmode: u32,
+    pub rotate: u32,
+    pub colorspace: u32,
+    pub reserved: [u32; 4],
+}
+
+/// 与 Linux 的 struct fb_fix_screeninfo 布局相同
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FbFixScreenInfo {
+    pub id: [u8; 16],
+    pub smem_start: u64,
+    pub smem_len: u32,
+    pub fb_type: u32,
+    pub type_aux: u32,
+    pub visual: u32,
+    pub xpanstep: u16,
+    pub ypanstep: u16,
+    pub ywrapstep: u16,
+    pub line_length: u32,
+    pub mmio_start: u64,
+    pub mmio_len: u32,
+    pub accel: u32,
+    pub capabilities: u16,
+    pub reserved: [u16; 2],
+}
+
+pub struct FrameBuffer {
+    offset: UPSafeCell<usize>,
+}
+
+impl FrameBuffer {
+    /// 没有显卡时返回 None
+    pub fn open() -> Option<Arc<Self>> {
+        GPU_DEVICE.as_ref()?;
+        Some(Arc::new(Self {
+            offset: unsafe { UPSafeCell::new(0) },
+        }))
+    }
+
+    fn var_screeninfo() -> FbVarScreenInfo {
+        let (width, height) = GPU_DEVICE.as_ref().unwrap().resolution();
+        FbVarScreenInfo {
+            xres: width,
+            yres: height,
+            xres_virtual: width,
+            yres_virtual: height,
+            bits_per_pixel: BITS_PER_PIXEL,
+            // 像素在内存中按 B G R A 排列
+            blue: FbBitfield::new(0, 8),
+            green: FbBitfield::new(8, 8),
+            red: FbBitfield::new(16, 8),
+            transp: FbBitfield::new(24, 8),
+            ..Default::default()
+        }
+    }
+
+    fn fix_screeninfo() -> FbFixScreenInfo {
+        let device = GPU_DEVICE.as_ref().unwrap();
+        let (width, _) = device.resolution();
+        let (start, len) = device.framebuffer_region();
+        let mut id = [0u8; 16];
+        id[..10].copy_from_slice(b"virtio-gpu");
+        FbFixScreenInfo {
+            id,
+            smem_start: start as u64,
+            smem_len: len as u32,
+            fb_type: FB_TYPE_PACKED_PIXELS,
+            visual: FB_VISUAL_TRUECOLOR,
+            line_length: width * BITS_PER_PIXEL / 8,
+            ..Default::default()
+        }
+    }
+}
+
+impl File for FrameBuffer {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn seek(&self, pos: SeekFrom) -> usize {
+        let len = GPU_DEVICE.as_ref().unwrap().framebuffer().len() as i64;
+        let mut offset = self.offset.exclusive_access();
+        let new_offset = match pos {
+            SeekFrom::Start(pos) => pos as i64,
+            SeekFrom::End(delta) => len + delta,
+            SeekFrom::Current(delta) => *offset as i64 + delta,
+        };
+        *offset = new_offset.clamp(0, len) as usize;
+        *offset
+    }
+    fn read(&self, mut user_buf: UserBuffer) -> usize {
+        let fb = GPU_DEVICE.as_ref().unwrap().framebuffer();
+        let mut offset = self.offset.exclusive_access();
+        let len = user_buf.write(&fb[*offset..]);
+        *offset += len;
+        len
+    }
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        let device = GPU_DEVICE.as_ref().unwrap();
+        let fb = device.framebuffer();
+        let mut offset = self.offset.exclusive_access();
+        let start = *offset;
+        for buffer in user_buf.buffers.iter() {
+            let len = buffer.len().min(fb.len() - *offset);
+            fb[*offset..*offset + len].copy_from_slice(&buffer[..len]);
+            *offset += len;
+        }
+        device.flush();
+        *offset - start
+    }
+    fn name(&self) -> String {
+        "fb0".to_string()
+    }
+    fn kstat(&self, stat: &mut Kstat) {
+        stat.sd_ino = FB_RDEV;
+        stat.st_mode = (StatMode::S_IFCHR | StatMode::S_IRUSR | StatMode::S_IWUSR).bits();
+        stat.st_nlink = 1;
+        stat.st_rdev = FB_RDEV;
+        stat.st_size = GPU_DEVICE.as_ref().unwrap().framebuffer().len() as i64;
+    }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        let token = current_user_token();
+        match request {
+            FBIOGET_VSCREENINFO => {
+                *translated_refmut(token, arg as *mut FbVarScreenInfo) = Self::var_screeninfo();
+            }
+            FBIOGET_FSCREENINFO => {
+                *translated_refmut(token, arg as *mut FbFixScreenInfo) = Self::fix_screeninfo();
+            }
+            _ => return -1,
+        }
+        0
+    }
+    fn mmap_region(&self) -> Option<MmapRegion> {
+        // 映射后的写入内核看不到, 映射存在期间在时钟中断中定期刷新
+        let (pa, size) = GPU_DEVICE.as_ref().unwrap().framebuffer_region();
+        Some(MmapRegion {
+            pa,
+            size,
+            holder: map_framebuffer(),
+        })
+    }
+}
@@ -1,16 +1,19 @@
 use alloc::sync::Arc;
 
-use super::{File, OSInode};
+use super::{File, MmapRegion, OSInode};
 use crate::mm::UserBuffer;
+use crate::net::Socket;
 
 #[derive(Clone)]
 // 抽象的文件描述符
 // OSInode是具体的文件
 // Abstract 主要是提供给 fd_table 以及 管道所使用
+// Socket 单独分出来, socket 相关的系统调用要通过它调用 bind/connect 等
 // 在这里FileDescriptor起分发的作用
 pub enum FileDescriptor {
     File(Arc<OSInode>),
     Abstract(Arc<dyn File + Send + Sync>),
+    Socket(Arc<dyn Socket>),
 }
 
 impl File for FileDescriptor {
@@ -18,42 +21,105 @@ impl File for FileDescriptor {
         match self {
             FileDescriptor::File(inode) => inode.readable(),
             FileDescriptor::Abstract(inode) => inode.readable(),
+            FileDescriptor::Socket(socket) => socket.readable(),
         }
     }
     fn writable(&self) -> bool {
         match self {
             FileDescriptor::File(inode) => inode.writable(),
             FileDescriptor::Abstract(inode) => inode.writable(),
+            FileDescriptor::Socket(socket) => socket.writable(),
         }
     }
     fn read(&self, buf: UserBuffer) -> usize {
         match self {
             FileDescriptor::File(inode) => inode.read(buf),
             FileDescriptor::Abstract(inode) => inode.read(buf),
+            FileDescriptor::Socket(socket) => socket.read(buf),
         }
     }
     fn write(&self, buf: UserBuffer) -> usize {
         match self {
             FileDescriptor::File(inode) => inode.write(buf),
             FileDescriptor::Abstract(inode) => inode.write(buf),
+            FileDescriptor::Socket(socket) => socket.write(buf),
         }
     }
     fn open(&self, name: &str, read: bool, write: bool, isdir: bool) -> Option<Arc<OSInode>> {
         match self {
             FileDescriptor::File(inode) => inode.open(name, read, write, isdir),
             FileDescriptor::Abstract(inode) => inode.open(name, read, write, isdir),
+            FileDescriptor::Socket(socket) => socket.open(name, read, write, isdir),
         }
     }
     fn create(&self, name: &str, read: bool, write: bool, isdir: bool) -> Option<Arc<OSInode>> {
         match self {
             FileDescriptor::File(inode) => inode.create(name, read, write, isdir),
             FileDescriptor::Abstract(inode) => inode.create(name, read, write, isdir),
+            FileDescriptor::Socket(socket) => socket.create(name, read, write, isdir),
+        }
+    }
+    fn create_new(&self, name: &str, read: bool, write: bool) -> Option<Arc<OSInode>> {
+        match self {
+            FileDescriptor::File(inode) => inode.create_new(name, read, write),
+            FileDescriptor::Abstract(inode) => inode.create_new(name, read, write),
+            FileDescriptor::Socket(socket) => socket.create_new(name, read, write),
+        }
+    }
+    fn truncate(&self, size: usize) -> bool {
+        match self {
+            FileDescriptor::File(inode) => inode.truncate(size),
+            FileDescriptor::Abstract(inode) => inode.truncate(size),
+            FileDescriptor::Socket(socket) => socket.truncate(size),
+        }
+    }
+    fn fallocate(&self, offset: usize, len: usize, keep_size: bool) -> bool {
+        match self {
+            FileDescriptor::File(inode) => inode.fallocate(offset, len, keep_size),
+            FileDescriptor::Abstract(inode) => inode.fallocate(offset, len, keep_size),
+            FileDescriptor::Socket(socket) => socket.fallocate(offset, len, keep_size),
+        }
+    }
+    fn utimens(&self, atime: Option<u64>, mtime: Option<u64>) -> bool {
+        match self {
+            FileDescriptor::File(inode) => inode.utimens(atime, mtime),
+            FileDescriptor::Abstract(inode) => inode.utimens(atime, mtime),
+            FileDescriptor::Socket(socket) => socket.utimens(atime, mtime),
         }
     }
     fn kstat(&self, stat: &mut super::Kstat) {
         match self {
             FileDescriptor::File(inode) => inode.kstat(stat),
-            FileDescriptor::Abstract(_) => {},
+            FileDescriptor::Abstract(inode) => inode.kstat(stat),
+            FileDescriptor::Socket(socket) => socket.kstat(stat),
+        }
+    }
+    fn getdents(&self, buf: UserBuffer) -> isize {
+        match self {
+            FileDescriptor::File(inode) => inode.getdents(buf),
+            FileDescriptor::Abstract(inode) => inode.getdents(buf),
+            FileDescriptor::Socket(_) => -1,
+        }
+    }
+    fn statfs(&self, stat: &mut super::Statfs) -> bool {
+        match self {
+            FileDescriptor::File(inode) => inode.statfs(stat),
+            FileDescriptor::Abstract(inode) => inode.statfs(stat),
+            FileDescriptor::Socket(socket) => socket.statfs(stat),
+        }
+    }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        match self {
+            FileDescriptor::File(inode) => inode.ioctl(request, arg),
+            FileDescriptor::Abstract(inode) => inode.ioctl(request, arg),
+            FileDescriptor::Socket(socket) => socket.ioctl(request, arg),
+        }
+    }
+    fn mmap_region(&self) -> Option<MmapRegion> {
+        match self {
+            FileDescriptor::File(inode) => inode.mmap_region(),
+            FileDescriptor::Abstract(inode) => inode.mmap_region(),
+            FileDescriptor::Socket(socket) => socket.mmap_region(),
         }
     }
 }
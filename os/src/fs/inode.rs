@@ -1,9 +1,11 @@
 use crate::fatfs::file::Inode;
 use crate::fatfs::io::SeekFrom;
-use crate::fatfs::root_dir;
+use crate::fatfs::{root_dir, FATFS};
+use crate::fatfs::time::DateTime;
 use crate::fs::File;
+use crate::ktest::kernel_test;
 use crate::mm::UserBuffer;
-use crate::sync::UPSafeCell;
+use crate::sync::KMutex;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -11,36 +13,52 @@ use alloc::vec::Vec;
 pub struct OSInode {
     readable: bool,
     writable: bool,
-    inner: UPSafeCell<OSInodeInner>,
+    /// 读写文件时可能等待磁盘, 用睡眠锁
+    inner: KMutex<OSInodeInner>,
 }
 pub struct OSInodeInner {
     offset: usize,
-    inode: Arc<UPSafeCell<Inode>>,
+    append: bool,
+    inode: Arc<KMutex<Inode>>,
 }
 
 impl OSInode {
-    pub fn new(readable: bool, writable: bool, inode: Arc<UPSafeCell<Inode>>) -> Self {
+    pub fn new(readable: bool, writable: bool, inode: Arc<KMutex<Inode>>) -> Self {
         Self {
             readable,
             writable,
-            inner: unsafe { UPSafeCell::new(OSInodeInner { offset: 0, inode }) },
+            inner: KMutex::new(OSInodeInner {
+                offset: 0,
+                append: false,
+                inode,
+            }),
         }
     }
 
+    /// 处理打开时的 O_TRUNC 与 O_APPEND
+    pub fn apply_open_flags(&self, flags: OpenFlags) {
+        let mut inner = self.inner.lock();
+        if flags.contains(OpenFlags::TRUNC) && self.writable {
+            inner.inode.lock().truncate(0);
+            inner.offset = 0;
+        }
+        inner.append = flags.contains(OpenFlags::APPEND);
+    }
+
     pub fn read_all(&self) -> Vec<u8> {
-        let inner = self.inner.exclusive_access();
+        let inner = self.inner.lock();
         let offset = inner.offset;
-        let v = inner.inode.inner.borrow_mut().read_all(offset);
+        let v = inner.inode.lock().read_all(offset);
         v
     }
 
     pub fn set_offset(&self, offset: usize) {
-        self.inner.exclusive_access().offset = offset;
+        self.inner.lock().offset = offset;
     }
 
     #[allow(unused)]
     pub fn get_offset(&self) -> usize {
-        self.inner.exclusive_access().offset
+        self.inner.lock().offset
     }
 }
 
@@ -50,7 +68,10 @@ bitflags! {
         const WRONLY = 0x001;
         const RDWR = 0x002;
         const CREATE = 0x40;
-        const TRUNC = 1 << 10;
+        const EXCL = 0x80;
+        const TRUNC = 0x200;
+        const APPEND = 0x400;
+        const NONBLOCK = 0x800;
         const DIRECTORY = 0x0200000;
         const DIR = 0x040000;
         const FILE = 0x100000;
@@ -75,32 +96,37 @@ pub fn root() -> Arc<OSInode> {
     Arc::new(OSInode::new(
         true,
         true,
-        Arc::new(unsafe { UPSafeCell::new(root_dir()) }),
+        Arc::new(KMutex::new(root_dir())),
     ))
 }
 
 pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
     let isdir=flags.contains(OpenFlags::DIRECTORY);
-    if flags.contains(OpenFlags::CREATE) {
-        let file = root_dir().create(path, false).unwrap();
-        Some(Arc::new(OSInode::new(
-            readable,
-            writable,
-            Arc::new(unsafe { UPSafeCell::new(file) }),
-        )))
-    } else {
-        if let Some(file) = root_dir().open(path, isdir) {
-            print!("");
-            Some(Arc::new(OSInode::new(
-                readable,
-                writable,
-                Arc::new(unsafe { UPSafeCell::new(file) }),
-            )))
-        } else {    
-            None
-        }
+    if path.trim_matches('/').is_empty() && !flags.contains(OpenFlags::CREATE) {
+        return Some(root());
     }
+    let file = if flags.contains(OpenFlags::CREATE) {
+        if flags.contains(OpenFlags::EXCL) {
+            root_dir().create_new(path)?
+        } else {
+            root_dir().create(path, false)?
+        }
+    } else if isdir {
+        root_dir().open(path, true)?
+    } else {
+        // 与 Linux 一样, 不带 O_DIRECTORY 也能打开目录
+        root_dir()
+            .open(path, false)
+            .or_else(|| root_dir().open(path, true))?
+    };
+    let os_inode = Arc::new(OSInode::new(
+        readable,
+        writable,
+        Arc::new(KMutex::new(file)),
+    ));
+    os_inode.apply_open_flags(flags);
+    Some(os_inode)
 }
 
 impl File for OSInode {
@@ -111,11 +137,16 @@ impl File for OSInode {
         self.writable
     }
 
+    fn create_new(&self, name: &str, read: bool, write: bool) -> Option<Arc<OSInode>> {
+        let inner = self.inner.lock();
+        let inode = inner.inode.lock().create_new(name)?;
+        Some(Arc::new(OSInode::new(read, write, Arc::new(KMutex::new(inode)))))
+    }
     fn open(&self, name: &str, read: bool, write: bool, isdir: bool) -> Option<Arc<OSInode>> {
-        let ier = self.inner.exclusive_access();
-        let mut inner = ier.inode.exclusive_access();
+        let ier = self.inner.lock();
+        let mut inner = ier.inode.lock();
         if let Some(inode) = inner.open(name, isdir) {
-            let os_inode = OSInode::new(read, write, Arc::new(unsafe { UPSafeCell::new(inode) }));
+            let os_inode = OSInode::new(read, write, Arc::new(KMutex::new(inode)));
             Some(Arc::new(os_inode))
         } else {
             None
@@ -123,18 +154,18 @@ impl File for OSInode {
     }
 
     fn seek(&self, offset: SeekFrom) -> usize {
-        let mut inner = self.inner.exclusive_access();
-        let offset = inner.inode.exclusive_access().seek(offset);
+        let mut inner = self.inner.lock();
+        let offset = inner.inode.lock().seek(offset);
         inner.offset = offset;
         offset
     }
 
     fn read(&self, mut buf: UserBuffer) -> usize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner.lock();
         let mut total_read_size = 0usize;
         for slice in buf.buffers.iter_mut() {
             let offset = inner.offset;
-            let len = inner.inode.exclusive_access().read(offset, *slice);
+            let len = inner.inode.lock().read(offset, *slice);
             if len == 0 {
                 break;
             }
@@ -144,12 +175,15 @@ impl File for OSInode {
         total_read_size
     }
     fn write(&self, mut buf: UserBuffer) -> usize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner.lock();
+        if inner.append {
+            inner.offset = inner.inode.lock().size();
+        }
 
         let mut total_write_size = 0usize;
         for slice in buf.buffers.iter_mut() {
             let offset = inner.offset;
-            let len = inner.inode.inner.borrow_mut().write(offset, *slice);
+            let len = inner.inode.lock().write(offset, *slice);
             if len == 0 {
                 break;
             }
@@ -159,50 +193,112 @@ impl File for OSInode {
         total_write_size
     }
     fn create(&self, name: &str, read: bool, write: bool, isdir: bool) -> Option<Arc<OSInode>> {
-        let inner = self.inner.exclusive_access();
-        let mut inner = inner.inode.exclusive_access();
+        let inner = self.inner.lock();
+        let mut inner = inner.inode.lock();
         if let Some(inode) = inner.create(name, isdir) {
-            let os_inode = OSInode::new(read, write, Arc::new(unsafe { UPSafeCell::new(inode) }));
+            let os_inode = OSInode::new(read, write, Arc::new(KMutex::new(inode)));
             Some(Arc::new(os_inode))
         } else {
             None
         }
     }
 
+    fn truncate(&self, size: usize) -> bool {
+        if !self.writable {
+            return false;
+        }
+        self.inner
+            .lock()
+            .inode
+            .lock()
+            .truncate(size)
+    }
+
+    fn fallocate(&self, offset: usize, len: usize, keep_size: bool) -> bool {
+        if !self.writable {
+            return false;
+        }
+        self.inner
+            .lock()
+            .inode
+            .lock()
+            .allocate(offset, len, keep_size)
+    }
+
+    fn utimens(&self, atime: Option<u64>, mtime: Option<u64>) -> bool {
+        self.inner
+            .lock()
+            .inode
+            .lock()
+            .set_times(atime.map(DateTime::from_unix_ns), mtime.map(DateTime::from_unix_ns));
+        true
+    }
+
     fn kstat(&self, stat: &mut Kstat) {
         self.inner
-            .exclusive_access()
+            .lock()
             .inode
-            .inner
-            .borrow_mut()
+            .lock()
             .stat(stat)
     }
 
+    fn statfs(&self, stat: &mut Statfs) -> bool {
+        let stats = FATFS.stats();
+        stat.f_type = MSDOS_SUPER_MAGIC;
+        stat.f_bsize = stats.cluster_size as i64;
+        stat.f_frsize = stats.cluster_size as i64;
+        stat.f_blocks = stats.total_clusters as u64;
+        stat.f_bfree = stats.free_clusters as u64;
+        stat.f_bavail = stats.free_clusters as u64;
+        stat.f_namelen = 255;
+        true
+    }
+
     fn remove(&self, path: &str) -> bool {
         self.inner
-            .exclusive_access()
+            .lock()
             .inode
-            .inner
-            .borrow_mut()
+            .lock()
             .remove(path)
     }
 
     fn name(&self) -> String {
         self.inner
-            .inner
-            .borrow_mut()
+            .lock()
             .inode
-            .inner
-            .borrow_mut()
+            .lock()
             .file_name()
     }
-    fn getdents(&self, dirent: &mut Dirent) -> isize {
-        self.inner
-            .exclusive_access()
-            .inode
-            .exclusive_access()
-            .getdents(dirent)
-            
+    fn getdents(&self, mut buf: UserBuffer) -> isize {
+        let mut inner = self.inner.lock();
+        let entries = match inner.inode.lock().dir_entries() {
+            Some(entries) => entries,
+            None => return -1,
+        };
+        // 目录的 offset 是下一个要读的目录项的序号
+        let mut data = Vec::new();
+        let mut index = inner.offset;
+        for (name, is_dir) in entries.iter().skip(index) {
+            let d_type = if *is_dir { DT_DIR } else { DT_REG };
+            let reclen = (DIRENT_NAME_OFFSET + name.len() + 1 + 7) & !7;
+            if data.len() + reclen > buf.len() {
+                break;
+            }
+            index += 1;
+            let start = data.len();
+            data.extend_from_slice(&(index as u64).to_le_bytes());
+            data.extend_from_slice(&(index as i64).to_le_bytes());
+            data.extend_from_slice(&(reclen as u16).to_le_bytes());
+            data.push(d_type);
+            data.extend_from_slice(name.as_bytes());
+            data.resize(start + reclen, 0);
+        }
+        if data.is_empty() && index < entries.len() {
+            // 缓冲区放不下一项
+            return -1;
+        }
+        inner.offset = index;
+        buf.write(&data) as isize
     }
 }
 
@@ -243,11 +339,122 @@ impl Kstat {
     }
 }
 
+bitflags! {
+    //st_mode 中的文件类型与权限位
+    pub struct StatMode: u32 {
+        const S_IFMT = 0o170000;
+        const S_IFIFO = 0o010000;
+        const S_IFCHR = 0o020000;
+        const S_IFDIR = 0o040000;
+        const S_IFREG = 0o100000;
+        const S_IFSOCK = 0o140000;
+        const S_IRWXU = 0o700;
+        const S_IRUSR = 0o400;
+        const S_IWUSR = 0o200;
+        const S_IXUSR = 0o100;
+        const S_IRWXG = 0o070;
+        const S_IRGRP = 0o040;
+        const S_IWGRP = 0o020;
+        const S_IXGRP = 0o010;
+        const S_IRWXO = 0o007;
+        const S_IROTH = 0o004;
+        const S_IWOTH = 0o002;
+        const S_IXOTH = 0o001;
+    }
+}
+
+//FAT 文件系统的 f_type
+pub const MSDOS_SUPER_MAGIC: i64 = 0x4d44;
+
 #[repr(C)]
-pub struct Dirent {
-    pub d_ino: usize,
-    pub d_off: isize,
-    pub d_reclen: u16,
-    pub d_type: u8,
-    pub d_name: [u8;0]
+#[derive(Debug,Default)]
+//文件系统状态信息结构
+pub struct Statfs {
+    pub f_type: i64,
+    pub f_bsize: i64,
+    pub f_blocks: u64,
+    pub f_bfree: u64,
+    pub f_bavail: u64,
+    pub f_files: u64,
+    pub f_ffree: u64,
+    pub f_fsid: [i32; 2],
+    pub f_namelen: i64,
+    pub f_frsize: i64,
+    pub f_flags: i64,
+    pub f_spare: [i64; 4],
+}
+
+impl Statfs {
+    pub fn as_bytes(&self) -> &[u8] {
+        let size = core::mem::size_of::<Self>();
+        unsafe {
+            core::slice::from_raw_parts(
+                self as *const _ as usize as *const u8,
+                size,
+            )
+        }
+    }
+}
+
+// getdents64 返回的 linux_dirent64 由 d_ino: u64, d_off: i64, d_reclen: u16, d_type: u8
+// 和以 '\0' 结尾的文件名组成, 每一项按 8 字节对齐
+const DIRENT_NAME_OFFSET: usize = 19;
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+
+/// 内核的缓冲区当作用户缓冲区写入文件, 测试用
+#[cfg(any(test, feature = "test"))]
+fn write_bytes(file: &OSInode, data: &[u8]) -> usize {
+    file.write(UserBuffer::new(alloc::vec![data.to_vec().leak()]))
+}
+
+#[cfg(any(test, feature = "test"))]
+fn file_size(file: &OSInode) -> i64 {
+    let mut stat = Kstat::default();
+    file.kstat(&mut stat);
+    stat.st_size
+}
+
+#[kernel_test]
+fn fat_file_test() {
+    const PATH: &str = "/ktest_file";
+    // 上次测试失败时可能留下
+    root_dir().remove(PATH);
+    let flags = OpenFlags::CREATE | OpenFlags::EXCL | OpenFlags::RDWR;
+    let file = open_file(PATH, flags).unwrap();
+    // O_EXCL: 同名的文件和目录都算已存在
+    assert!(open_file(PATH, flags).is_none());
+    assert!(open_file("/bin", flags).is_none());
+
+    assert_eq!(write_bytes(&file, b"hello"), 5);
+    assert!(file.truncate(2));
+    file.set_offset(0);
+    assert_eq!(file.read_all(), b"he");
+    assert!(file.truncate(4));
+    file.set_offset(0);
+    assert_eq!(file.read_all(), b"he\0\0");
+
+    // keep_size 时只分配簇, 大小不变; 否则扩展并清零
+    assert!(file.fallocate(0, 0x4000, true));
+    assert_eq!(file_size(&file), 4);
+    assert!(file.fallocate(4, 4, false));
+    assert_eq!(file_size(&file), 8);
+    assert!(!file.fallocate(usize::MAX, 2, false));
+    assert_eq!(file_size(&file), 8);
+    // 每次打开都有自己的目录项副本, 关闭时才写回, 下面依次打开
+    drop(file);
+
+    let append = open_file(PATH, OpenFlags::WRONLY | OpenFlags::APPEND).unwrap();
+    append.set_offset(0);
+    assert_eq!(write_bytes(&append, b"!"), 1);
+    drop(append);
+    let file = open_file(PATH, OpenFlags::RDONLY).unwrap();
+    assert_eq!(file.read_all(), b"he\0\0\0\0\0\0!");
+    drop(file);
+
+    let file = open_file(PATH, OpenFlags::WRONLY | OpenFlags::TRUNC).unwrap();
+    assert_eq!(file_size(&file), 0);
+    drop(file);
+    assert!(root_dir().remove(PATH));
+    assert!(open_file(PATH, OpenFlags::RDONLY).is_none());
 }
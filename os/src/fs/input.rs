@@ -0,0 +1,117 @@
+//! 输入事件设备 /dev/input/eventN, 每次 read 返回若干个完整的 struct input_event
+use super::{File, Kstat, StatMode};
+use crate::board::InputDeviceImpl;
+use crate::drivers::input::{EventQueue, InputDevice, InputEvent, INPUT_DEVICES};
+use crate::mm::{translated_byte_buffer, translated_refmut, UserBuffer};
+use crate::sync::UPSafeCell;
+use crate::task::{current_user_token, suspend_current_and_run_next};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+const EVIOCGVERSION: usize = 0x8004_4501;
+const EVIOCGNAME: usize = 0x8000_4506;
+const EVIOCGRAB: usize = 0x4004_4590;
+// EVIOCGNAME 的请求号中带有缓冲区长度
+const IOC_SIZE_MASK: usize = 0x3fff << 16;
+const EV_VERSION: i32 = 0x010001;
+
+// 事件设备的主设备号为 13, 次设备号从 64 开始
+const INPUT_MAJOR: u64 = 13;
+const EVENT_MINOR_BASE: u64 = 64;
+
+pub struct EventFile {
+    index: usize,
+    device: Arc<InputDeviceImpl>,
+    queue: EventQueue,
+    nonblock: bool,
+    grabbed: UPSafeCell<bool>,
+}
+
+impl EventFile {
+    /// 打开第 index 个输入设备, 之后产生的事件才能读到
+    pub fn open(index: usize, nonblock: bool) -> Option<Arc<Self>> {
+        let (_, device) = INPUT_DEVICES.get(index)?;
+        Some(Arc::new(Self {
+            index,
+            device: device.clone(),
+            queue: device.subscribe(),
+            nonblock,
+            grabbed: unsafe { UPSafeCell::new(false) },
+        }))
+    }
+}
+
+impl File for EventFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, mut user_buf: UserBuffer) -> usize {
+        let event_size = core::mem::size_of::<InputEvent>();
+        let count = user_buf.len() / event_size;
+        if count == 0 {
+            return -1isize as usize;
+        }
+        loop {
+            let mut queue = self.queue.exclusive_access();
+            if !queue.is_empty() {
+                let mut data = Vec::new();
+                for event in queue.drain(..count.min(queue.len())) {
+                    data.extend_from_slice(event.as_bytes());
+                }
+                return user_buf.write(&data);
+            }
+            if self.nonblock {
+                return -1isize as usize;
+            }
+            drop(queue);
+            suspend_current_and_run_next();
+        }
+    }
+    fn name(&self) -> String {
+        "event".to_string()
+    }
+    fn kstat(&self, stat: &mut Kstat) {
+        let rdev = (INPUT_MAJOR << 8) | (EVENT_MINOR_BASE + self.index as u64);
+        stat.sd_ino = rdev;
+        stat.st_mode = (StatMode::S_IFCHR | StatMode::S_IRUSR).bits();
+        stat.st_nlink = 1;
+        stat.st_rdev = rdev;
+    }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        let token = current_user_token();
+        match request {
+            EVIOCGVERSION => {
+                *translated_refmut(token, arg as *mut i32) = EV_VERSION;
+            }
+            EVIOCGRAB => {
+                // 与 Linux 相同, 参数本身就是是否独占, 不是指针
+                let grab = arg != 0;
+                *self.grabbed.exclusive_access() = grab;
+                self.device.set_grab(grab);
+            }
+            _ if request & !IOC_SIZE_MASK == EVIOCGNAME => {
+                let len = (request & IOC_SIZE_MASK) >> 16;
+                let mut name = self.device.name().into_bytes();
+                name.push(0);
+                let len = len.min(name.len());
+                UserBuffer::new(translated_byte_buffer(token, arg as *const u8, len))
+                    .write(&name[..len]);
+                return len as isize;
+            }
+            _ => return -1,
+        }
+        0
+    }
+}
+
+impl Drop for EventFile {
+    fn drop(&mut self) {
+        if *self.grabbed.exclusive_access() {
+            self.device.set_grab(false);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! /proc/sys/kernel/loglevel, 读出当前的日志过滤规则, 写入新的规则
+//!
+//! 写入 `info` 或 `warn,net=debug` 这样的规则, 也可以像 Linux 一样写入 0 到 8 的数字
+use super::{File, Kstat, StatMode};
+use crate::logging;
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+pub struct LogLevelFile {
+    offset: UPSafeCell<usize>,
+}
+
+impl LogLevelFile {
+    pub fn open() -> Arc<Self> {
+        Arc::new(Self {
+            offset: unsafe { UPSafeCell::new(0) },
+        })
+    }
+}
+
+impl File for LogLevelFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut user_buf: UserBuffer) -> usize {
+        let content = logging::filter_spec() + "\n";
+        let mut offset = self.offset.exclusive_access();
+        let start = (*offset).min(content.len());
+        let len = user_buf.write(&content.as_bytes()[start..]);
+        *offset = start + len;
+        len
+    }
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        let data: Vec<u8> = user_buf
+            .buffers
+            .iter()
+            .flat_map(|buffer| buffer.iter().copied())
+            .collect();
+        let spec = match core::str::from_utf8(&data) {
+            Ok(spec) => spec.trim(),
+            Err(_) => return -1isize as usize,
+        };
+        if logging::set_filter_spec(spec) {
+            data.len()
+        } else {
+            -1isize as usize
+        }
+    }
+    fn name(&self) -> String {
+        "loglevel".to_string()
+    }
+    fn kstat(&self, stat: &mut Kstat) {
+        stat.st_mode = (StatMode::S_IFREG | StatMode::S_IRUSR | StatMode::S_IWUSR).bits();
+        stat.st_nlink = 1;
+    }
+}
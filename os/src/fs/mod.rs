@@ -3,12 +3,30 @@ mod inode;
 mod pipe;
 mod stdio;
 mod file_descriptor;
+mod tty;
+mod input;
+mod loglevel;
+mod cmdline;
+mod cpio;
+mod tmpfs;
+#[cfg(feature = "graphics")]
+mod fb;
 
 use crate::{fatfs::io::SeekFrom, mm::UserBuffer};
+use core::any::Any;
+
+/// 可以直接映射到用户空间的设备内存
+pub struct MmapRegion {
+    pub pa: usize,
+    pub size: usize,
+    /// 与映射一起释放, 设备由此知道自己是否还被映射着
+    pub holder: Arc<dyn Any + Send + Sync>,
+}
 
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
+    /// 返回新的位置. 与 read/write 出错时相同, 位置非法时不移动并返回 `-1isize as usize`
     fn seek(&self, _offset: SeekFrom) -> usize {
         0
     }
@@ -21,6 +39,10 @@ pub trait File: Send + Sync {
     fn create(&self, name: &str, read: bool, write: bool, isdir: bool) -> Option<Arc<OSInode>> {
         None
     }
+    /// O_CREAT | O_EXCL 时使用, 同名的文件或目录已存在时返回 None
+    fn create_new(&self, name: &str, read: bool, write: bool) -> Option<Arc<OSInode>> {
+        None
+    }
     fn open(&self, name: &str, read: bool, write: bool, isdir: bool) -> Option<Arc<OSInode>> {
         None
     }
@@ -31,19 +53,149 @@ pub trait File: Send + Sync {
         false
     }
     fn kstat(&self, stat: &mut Kstat) {}
+    /// 所在文件系统的容量信息, 不属于任何文件系统时返回 false
+    fn statfs(&self, stat: &mut Statfs) -> bool {
+        false
+    }
     fn name(&self) -> String {
         "/".to_string()
     }
-    fn getdents(&self, dirent: &mut Dirent) -> isize {
+    /// 从上次读到的位置起, 以 linux_dirent64 的格式写入尽量多的目录项,
+    /// 返回写入的字节数, 已经读完时返回 0
+    fn getdents(&self, buf: UserBuffer) -> isize {
         -1
     }
+    fn truncate(&self, size: usize) -> bool {
+        false
+    }
+    fn fallocate(&self, offset: usize, len: usize, keep_size: bool) -> bool {
+        false
+    }
+    /// 设置访问/修改时间, 单位为自 1970 年起的纳秒, None 表示保持不变
+    fn utimens(&self, atime: Option<u64>, mtime: Option<u64>) -> bool {
+        false
+    }
+    /// 设备相关的控制操作, 目前只有终端支持
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        -1
+    }
+    /// 可以直接映射到用户空间的设备内存
+    fn mmap_region(&self) -> Option<MmapRegion> {
+        None
+    }
 }
 
-use alloc::{string::{String, ToString}, sync::Arc};
-pub use inode::{open_file, root, Dirent, Kstat, OSInode, OpenFlags};
+use alloc::{string::{String, ToString}, sync::Arc, vec::Vec};
+pub use inode::{open_file, root, Kstat, OSInode, OpenFlags, StatMode, Statfs};
 pub use pipe::{make_pipe, Pipe};
 pub use stdio::{Stdin, Stdout};
 pub use file_descriptor::FileDescriptor;
+pub use tty::{tty_init, TTY, TTY_BUF_SIZE};
+pub use tmpfs::{TmpFile, TmpFs};
+
+use lazy_static::*;
+use crate::ktest::kernel_test;
+use log::info;
+
+lazy_static! {
+    /// 内嵌在内核中的 initramfs, 第一次使用时解开
+    pub static ref INITRAMFS: TmpFs = {
+        let fs = TmpFs::new();
+        let count = fs.unpack_cpio(include_bytes!("../../initramfs.cpio"));
+        info!("initramfs: {} entries", count);
+        fs
+    };
+}
+
+/// 根文件系统中打不开时在 initramfs 中查找, 不支持创建
+pub fn open_initramfs(path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+    if flags.contains(OpenFlags::CREATE) {
+        return None;
+    }
+    let inode = INITRAMFS.lookup(path)?;
+    if flags.contains(OpenFlags::DIRECTORY) && !inode.is_dir() {
+        return None;
+    }
+    let (readable, writable) = flags.read_write();
+    let name = path.rsplit('/').next().unwrap_or(path);
+    Some(TmpFile::new(readable, writable, name, inode) as Arc<dyn File + Send + Sync>)
+}
+
+/// 以 cwd 为起点把 path 变成绝对路径, 并去掉其中的 `.` 与 `..`
+pub fn absolute_path(cwd: &str, path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    let start = if path.starts_with('/') { "" } else { cwd };
+    for name in start.split('/').chain(path.split('/')) {
+        match name {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(name),
+        }
+    }
+    let mut absolute = String::new();
+    for name in components {
+        absolute.push('/');
+        absolute.push_str(name);
+    }
+    if absolute.is_empty() {
+        absolute.push('/');
+    }
+    absolute
+}
+
+/// 目录在根文件系统或 initramfs 中存在
+pub fn is_dir(path: &str) -> bool {
+    open_file(path, OpenFlags::DIRECTORY).is_some()
+        || INITRAMFS.lookup(path).map_or(false, |inode| inode.is_dir())
+}
+
+/// 读出要执行的程序, 先在根文件系统中找, 找不到时使用 initramfs 中的
+pub fn read_program(path: &str) -> Option<Vec<u8>> {
+    if let Some(inode) = open_file(path, OpenFlags::RDONLY) {
+        return Some(inode.read_all());
+    }
+    let inode = INITRAMFS.lookup(path)?;
+    if inode.is_dir() {
+        return None;
+    }
+    Some(inode.read_all())
+}
+
+#[kernel_test]
+fn initramfs_test() {
+    let init = INITRAMFS.lookup("/bin/initproc").unwrap();
+    assert!(!init.is_dir());
+    assert_eq!(&init.read_all()[..4], b"\x7fELF");
+}
+
+#[kernel_test]
+fn absolute_path_test() {
+    assert_eq!(absolute_path("/", "bin/ls"), "/bin/ls");
+    assert_eq!(absolute_path("/bin", "./ls"), "/bin/ls");
+    assert_eq!(absolute_path("/bin", "../etc//passwd"), "/etc/passwd");
+    assert_eq!(absolute_path("/bin", "/.."), "/");
+    assert_eq!(absolute_path("/a/b", ".."), "/a");
+}
+
+/// 设备文件还没有放进文件系统, 打开时按路径查找
+pub fn open_device(path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+    if let Some(index) = path.strip_prefix("/dev/input/event") {
+        let nonblock = flags.contains(OpenFlags::NONBLOCK);
+        return input::EventFile::open(index.parse().ok()?, nonblock)
+            .map(|file| file as Arc<dyn File + Send + Sync>);
+    }
+    match path {
+        "/proc/cmdline" => Some(cmdline::CmdlineFile::open() as Arc<dyn File + Send + Sync>),
+        "/proc/sys/kernel/loglevel" => {
+            Some(loglevel::LogLevelFile::open() as Arc<dyn File + Send + Sync>)
+        }
+        #[cfg(feature = "graphics")]
+        "/dev/fb0" => fb::FrameBuffer::open().map(|fb| fb as Arc<dyn File + Send + Sync>),
+        _ => None,
+    }
+}
 
 // 等待实现的VFS
 pub trait VFS {
@@ -1,6 +1,6 @@
 use core::fmt::Debug;
 
-use super::File;
+use super::{File, Kstat, StatMode};
 use crate::sync::UPSafeCell;
 use crate::task::suspend_current_and_run_next;
 use crate::{mm::UserBuffer};
@@ -46,6 +46,7 @@ pub struct PipeRingBuffer {
     head: usize,
     tail: usize,
     status: RingBufferStatus,
+    read_end: Option<Weak<Pipe>>,
     write_end: Option<Weak<Pipe>>,
 }
 
@@ -56,9 +57,13 @@ impl PipeRingBuffer {
             head: 0,
             tail: 0,
             status: RingBufferStatus::EMPTY,
+            read_end: None,
             write_end: None,
         }
     }
+    pub fn set_read_end(&mut self, read_end: &Arc<Pipe>) {
+        self.read_end = Some(Arc::downgrade(read_end));
+    }
     pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
         self.write_end = Some(Arc::downgrade(write_end));
     }
@@ -97,6 +102,9 @@ impl PipeRingBuffer {
             RING_BUFFER_SIZE - self.available_read()
         }
     }
+    pub fn all_read_ends_closed(&self) -> bool {
+        self.read_end.as_ref().unwrap().upgrade().is_none()
+    }
     pub fn all_write_ends_closed(&self) -> bool {
         self.write_end.as_ref().unwrap().upgrade().is_none()
     }
@@ -108,6 +116,7 @@ pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
     let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
     let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
     let mut buf = buffer.exclusive_access();
+    buf.set_read_end(&read_end);
     buf.set_write_end(&write_end);
     (read_end, write_end)
 }
@@ -127,7 +136,8 @@ impl File for Pipe {
             let mut ring_buffer = self.buffer.exclusive_access();
             let loop_read = ring_buffer.available_read();
             if loop_read == 0 {
-                if ring_buffer.all_write_ends_closed() {
+                // 已经读到数据时直接返回, 不等缓冲区填满
+                if read_size > 0 || ring_buffer.all_write_ends_closed() {
                     return read_size;
                 }
                 drop(ring_buffer);
@@ -153,6 +163,13 @@ impl File for Pipe {
         let mut write_size = 0usize;
         loop {
             let mut ring_buffer = self.buffer.exclusive_access();
+            // 读端全部关闭后写入的数据没有人读, 不再等待. 一个字节也没写入时出错 (EPIPE)
+            if ring_buffer.all_read_ends_closed() {
+                if write_size == 0 {
+                    return -1isize as usize;
+                }
+                return write_size;
+            }
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
                 drop(ring_buffer);
@@ -173,6 +190,13 @@ impl File for Pipe {
     fn name(&self) -> String {
         String::from("pipe")
     }
+    fn kstat(&self, stat: &mut Kstat) {
+        // 读写两端共享同一个缓冲区, 用它的地址作为 inode 号
+        stat.sd_ino = Arc::as_ptr(&self.buffer) as u64;
+        stat.st_mode = (StatMode::S_IFIFO | StatMode::S_IRUSR | StatMode::S_IWUSR).bits();
+        stat.st_nlink = 1;
+        stat.st_blksize = RING_BUFFER_SIZE as u32;
+    }
 }
 
 impl Debug for PipeRingBuffer {
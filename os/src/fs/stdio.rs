@@ -1,14 +1,28 @@
 use alloc::string::{ToString,String};
+use alloc::vec;
 
-use super::File;
+use super::{File, Kstat, StatMode, TTY, TTY_BUF_SIZE};
 use crate::mm::UserBuffer;
-use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
 
 pub struct Stdin;
 
 pub struct Stdout;
 
+// 控制台字符设备 /dev/console 的设备号 (5, 1)
+const CONSOLE_RDEV: u64 = (5 << 8) | 1;
+
+fn console_kstat(stat: &mut Kstat) {
+    stat.sd_ino = CONSOLE_RDEV;
+    stat.st_mode = (StatMode::S_IFCHR
+        | StatMode::S_IRUSR
+        | StatMode::S_IWUSR
+        | StatMode::S_IWGRP)
+        .bits();
+    stat.st_nlink = 1;
+    stat.st_rdev = CONSOLE_RDEV;
+    stat.st_blksize = 1024;
+}
+
 impl File for Stdin {
     fn name(&self)->String {
         "Stdin".to_string()
@@ -20,27 +34,21 @@ impl File for Stdin {
         false
     }
     fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);
-        // busy loop
-        let mut c: usize;
-        loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
-            }
-        }
-        let ch = c as u8;
-        unsafe {
-            user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
-        }
-        1
+        // 经过终端行规程, 规范模式下阻塞到一整行输入完成.
+        // 终端缓冲的数据不超过 TTY_BUF_SIZE, 一次也最多读这么多
+        let mut buf = vec![0u8; user_buf.len().min(TTY_BUF_SIZE)];
+        let len = TTY.read(&mut buf);
+        user_buf.write(&buf[..len])
     }
     fn write(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot write to stdin!");
     }
+    fn kstat(&self, stat: &mut Kstat) {
+        console_kstat(stat);
+    }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        TTY.ioctl(request, arg)
+    }
 }
 
 impl File for Stdout {
@@ -58,8 +66,14 @@ impl File for Stdout {
     }
     fn write(&self, user_buf: UserBuffer) -> usize {
         for buffer in user_buf.buffers.iter() {
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
+            TTY.write(buffer);
         }
         user_buf.len()
     }
+    fn kstat(&self, stat: &mut Kstat) {
+        console_kstat(stat);
+    }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        TTY.ioctl(request, arg)
+    }
 }
@@ -0,0 +1,271 @@
+//! 内存中的文件系统, 存放从内嵌 initramfs 解出的文件.
+//! 根文件系统中找不到的程序从这里查找
+use super::cpio;
+use super::{File, Kstat, StatMode};
+use crate::fatfs::io::SeekFrom;
+use crate::ktest::kernel_test;
+use crate::mm::UserBuffer;
+use crate::sync::KMutex;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// 文件内容放在内核堆中, 单个文件最大的大小
+const MAX_FILE_SIZE: usize = 0x10_0000;
+
+pub struct TmpInode {
+    mode: u32,
+    inner: KMutex<TmpInodeInner>,
+}
+
+enum TmpInodeInner {
+    Dir(BTreeMap<String, Arc<TmpInode>>),
+    File(Vec<u8>),
+}
+
+impl TmpInode {
+    fn new_dir(mode: u32) -> Arc<Self> {
+        Arc::new(Self {
+            mode: StatMode::S_IFDIR.bits() | (mode & 0o7777),
+            inner: KMutex::new(TmpInodeInner::Dir(BTreeMap::new())),
+        })
+    }
+
+    fn new_file(mode: u32, data: Vec<u8>) -> Arc<Self> {
+        Arc::new(Self {
+            mode: StatMode::S_IFREG.bits() | (mode & 0o7777),
+            inner: KMutex::new(TmpInodeInner::File(data)),
+        })
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & StatMode::S_IFMT.bits() == StatMode::S_IFDIR.bits()
+    }
+
+    pub fn size(&self) -> usize {
+        match &*self.inner.lock() {
+            TmpInodeInner::Dir(_) => 0,
+            TmpInodeInner::File(data) => data.len(),
+        }
+    }
+
+    pub fn read_all(&self) -> Vec<u8> {
+        match &*self.inner.lock() {
+            TmpInodeInner::Dir(_) => Vec::new(),
+            TmpInodeInner::File(data) => data.clone(),
+        }
+    }
+
+    fn child(&self, name: &str) -> Option<Arc<TmpInode>> {
+        match &*self.inner.lock() {
+            TmpInodeInner::Dir(children) => children.get(name).cloned(),
+            TmpInodeInner::File(_) => None,
+        }
+    }
+
+    fn insert(&self, name: &str, node: Arc<TmpInode>) -> bool {
+        match &mut *self.inner.lock() {
+            TmpInodeInner::Dir(children) => {
+                children.insert(name.to_string(), node);
+                true
+            }
+            TmpInodeInner::File(_) => false,
+        }
+    }
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+}
+
+pub struct TmpFs {
+    root: Arc<TmpInode>,
+}
+
+impl TmpFs {
+    pub fn new() -> Self {
+        Self {
+            root: TmpInode::new_dir(0o755),
+        }
+    }
+
+    pub fn lookup(&self, path: &str) -> Option<Arc<TmpInode>> {
+        components(path).try_fold(self.root.clone(), |dir, name| dir.child(name))
+    }
+
+    /// 创建目录, 缺少的上级目录一并创建
+    pub fn mkdir_all(&self, path: &str, mode: u32) -> Option<Arc<TmpInode>> {
+        let mut dir = self.root.clone();
+        for name in components(path) {
+            dir = match dir.child(name) {
+                Some(node) => node,
+                None => {
+                    let node = TmpInode::new_dir(mode);
+                    if !dir.insert(name, node.clone()) {
+                        return None;
+                    }
+                    node
+                }
+            };
+        }
+        Some(dir)
+    }
+
+    /// 创建或覆盖普通文件, 缺少的上级目录一并创建
+    pub fn create_file(&self, path: &str, mode: u32, data: Vec<u8>) -> Option<Arc<TmpInode>> {
+        let (parent, name) = match path.trim_end_matches('/').rsplit_once('/') {
+            Some((parent, name)) => (parent, name),
+            None => ("", path),
+        };
+        if name.is_empty() {
+            return None;
+        }
+        let dir = self.mkdir_all(parent, 0o755)?;
+        let node = TmpInode::new_file(mode, data);
+        dir.insert(name, node.clone()).then_some(node)
+    }
+
+    /// 解开 cpio newc 归档, 返回解出的项数. 只支持目录与普通文件
+    pub fn unpack_cpio(&self, archive: &[u8]) -> usize {
+        let mut count = 0;
+        for entry in cpio::entries(archive) {
+            let kind = entry.mode & StatMode::S_IFMT.bits();
+            let node = if kind == StatMode::S_IFDIR.bits() {
+                self.mkdir_all(entry.name, entry.mode)
+            } else if kind == StatMode::S_IFREG.bits() {
+                self.create_file(entry.name, entry.mode, entry.data.to_vec())
+            } else {
+                None
+            };
+            if node.is_some() {
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+impl Default for TmpFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 打开的 tmpfs 文件
+pub struct TmpFile {
+    readable: bool,
+    writable: bool,
+    name: String,
+    inode: Arc<TmpInode>,
+    offset: KMutex<usize>,
+}
+
+impl TmpFile {
+    pub fn new(readable: bool, writable: bool, name: &str, inode: Arc<TmpInode>) -> Arc<Self> {
+        Arc::new(Self {
+            readable,
+            writable,
+            name: name.to_string(),
+            inode,
+            offset: KMutex::new(0),
+        })
+    }
+}
+
+impl File for TmpFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn seek(&self, pos: SeekFrom) -> usize {
+        let mut offset = self.offset.lock();
+        let new_offset = match pos {
+            SeekFrom::Start(pos) => pos as i64,
+            SeekFrom::Current(delta) => *offset as i64 + delta,
+            SeekFrom::End(delta) => self.inode.size() as i64 + delta,
+        };
+        if new_offset < 0 {
+            return -1isize as usize;
+        }
+        *offset = new_offset as usize;
+        *offset
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let mut offset = self.offset.lock();
+        let len = match &*self.inode.inner.lock() {
+            TmpInodeInner::File(data) => buf.write(&data[(*offset).min(data.len())..]),
+            TmpInodeInner::Dir(_) => return -1isize as usize,
+        };
+        *offset += len;
+        len
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut offset = self.offset.lock();
+        let len = match &mut *self.inode.inner.lock() {
+            TmpInodeInner::File(data) => {
+                // 超过大小上限的部分不写入, 与 Linux 的 EFBIG 相同, 一个字节也写不下时出错
+                if *offset >= MAX_FILE_SIZE {
+                    return -1isize as usize;
+                }
+                let end = (*offset + buf.len()).min(MAX_FILE_SIZE);
+                if data.len() < end {
+                    if data.try_reserve(end - data.len()).is_err() {
+                        return -1isize as usize;
+                    }
+                    data.resize(end, 0);
+                }
+                let mut pos = *offset;
+                for slice in buf.buffers.iter() {
+                    let len = slice.len().min(end - pos);
+                    data[pos..pos + len].copy_from_slice(&slice[..len]);
+                    pos += len;
+                }
+                pos - *offset
+            }
+            TmpInodeInner::Dir(_) => return -1isize as usize,
+        };
+        *offset += len;
+        len
+    }
+    fn kstat(&self, stat: &mut Kstat) {
+        stat.st_mode = self.inode.mode;
+        stat.st_nlink = 1;
+        stat.st_size = self.inode.size() as i64;
+    }
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn truncate(&self, size: usize) -> bool {
+        match &mut *self.inode.inner.lock() {
+            TmpInodeInner::File(data) => {
+                // 与 write 相同的大小上限
+                if size > MAX_FILE_SIZE {
+                    return false;
+                }
+                if data.len() < size && data.try_reserve(size - data.len()).is_err() {
+                    return false;
+                }
+                data.resize(size, 0);
+                true
+            }
+            TmpInodeInner::Dir(_) => false,
+        }
+    }
+}
+
+#[kernel_test]
+fn tmpfs_test() {
+    let fs = TmpFs::new();
+    assert!(fs
+        .create_file("/a/b/hello", 0o644, b"hello".to_vec())
+        .is_some());
+    assert!(fs.lookup("/a/b").unwrap().is_dir());
+    assert_eq!(fs.lookup("a//b/hello").unwrap().read_all(), b"hello");
+    // 不能在普通文件下创建
+    assert!(fs.create_file("/a/b/hello/x", 0o644, Vec::new()).is_none());
+    assert!(fs.lookup("/a/c").is_none());
+}
@@ -0,0 +1,431 @@
+//! 串口之上的终端行规程: 规范模式下的行编辑、回显与作业控制字符
+use crate::drivers::chardev::{CharDevice, InputSink, UART};
+use crate::drivers::{InputDevice, INPUT_DEVICES};
+use crate::mm::translated_refmut;
+use crate::sync::UPSafeCell;
+use crate::task::{
+    block_current_and_run_next, current_task, current_user_token, pid2process, wakeup_task,
+    SignalFlags, TaskControlBlock,
+};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use lazy_static::*;
+
+// ioctl 请求号
+pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+pub const TCSETSW: usize = 0x5403;
+pub const TCSETSF: usize = 0x5404;
+pub const TIOCGPGRP: usize = 0x540F;
+pub const TIOCSPGRP: usize = 0x5410;
+pub const TIOCGWINSZ: usize = 0x5413;
+pub const TIOCSWINSZ: usize = 0x5414;
+
+bitflags! {
+    pub struct InputFlags: u32 {
+        const INLCR = 0o100;
+        const IGNCR = 0o200;
+        const ICRNL = 0o400;
+        const IXON = 0o2000;
+    }
+}
+
+bitflags! {
+    pub struct OutputFlags: u32 {
+        const OPOST = 0o1;
+        const ONLCR = 0o4;
+    }
+}
+
+bitflags! {
+    pub struct LocalFlags: u32 {
+        const ISIG = 0o1;
+        const ICANON = 0o2;
+        const ECHO = 0o10;
+        const ECHOE = 0o20;
+        const ECHOK = 0o40;
+        const ECHONL = 0o100;
+        const ECHOCTL = 0o1000;
+        const ECHOKE = 0o4000;
+        const IEXTEN = 0o100000;
+    }
+}
+
+// c_cc 下标
+const VINTR: usize = 0;
+const VQUIT: usize = 1;
+const VERASE: usize = 2;
+const VKILL: usize = 3;
+const VEOF: usize = 4;
+const VTIME: usize = 5;
+const VMIN: usize = 6;
+const VSTART: usize = 8;
+const VSTOP: usize = 9;
+const VSUSP: usize = 10;
+const VWERASE: usize = 14;
+const NCCS: usize = 19;
+
+const BACKSPACE: u8 = 0x08;
+
+/// 正在编辑的行与待读数据合计的上限, 与 Linux 的 N_TTY_BUF_SIZE 相同
+pub const TTY_BUF_SIZE: usize = 4096;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+//内核的 struct termios, TCGETS/TCSETS 按此布局拷贝
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_line: u8,
+    pub c_cc: [u8; NCCS],
+}
+
+impl Default for Termios {
+    /// 与 Linux 控制台的默认设置一致
+    fn default() -> Self {
+        let mut c_cc = [0u8; NCCS];
+        c_cc[VINTR] = 0x03;
+        c_cc[VQUIT] = 0x1c;
+        c_cc[VERASE] = 0x7f;
+        c_cc[VKILL] = 0x15;
+        c_cc[VEOF] = 0x04;
+        c_cc[VTIME] = 0;
+        c_cc[VMIN] = 1;
+        c_cc[VSTART] = 0x11;
+        c_cc[VSTOP] = 0x13;
+        c_cc[VSUSP] = 0x1a;
+        c_cc[VWERASE] = 0x17;
+        Self {
+            c_iflag: (InputFlags::ICRNL | InputFlags::IXON).bits(),
+            c_oflag: (OutputFlags::OPOST | OutputFlags::ONLCR).bits(),
+            // B38400 | CS8 | CREAD | HUPCL
+            c_cflag: 0o17 | 0o60 | 0o200 | 0o2000,
+            c_lflag: (LocalFlags::ISIG
+                | LocalFlags::ICANON
+                | LocalFlags::ECHO
+                | LocalFlags::ECHOE
+                | LocalFlags::ECHOK
+                | LocalFlags::ECHOCTL
+                | LocalFlags::ECHOKE
+                | LocalFlags::IEXTEN)
+                .bits(),
+            c_line: 0,
+            c_cc,
+        }
+    }
+}
+
+impl Termios {
+    fn iflag(&self) -> InputFlags {
+        InputFlags::from_bits_truncate(self.c_iflag)
+    }
+    fn oflag(&self) -> OutputFlags {
+        OutputFlags::from_bits_truncate(self.c_oflag)
+    }
+    fn lflag(&self) -> LocalFlags {
+        LocalFlags::from_bits_truncate(self.c_lflag)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct WinSize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
+
+impl Default for WinSize {
+    fn default() -> Self {
+        Self {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        }
+    }
+}
+
+struct TtyInner {
+    termios: Termios,
+    winsize: WinSize,
+    /// 规范模式下正在编辑的行
+    line: Vec<u8>,
+    /// 可以被 read 取走的数据, 与 line 合计不超过 TTY_BUF_SIZE
+    ready: VecDeque<u8>,
+    /// 空行上按下 ^D 的次数, 每次让一个 read 返回 0
+    eof: usize,
+    /// 前台进程, ^C/^Z 的信号发给它
+    foreground: Option<usize>,
+    readers: VecDeque<Arc<TaskControlBlock>>,
+}
+
+pub struct Tty {
+    inner: UPSafeCell<TtyInner>,
+}
+
+lazy_static! {
+    pub static ref TTY: Arc<Tty> = Arc::new(Tty::new());
+}
+
+/// 让串口和键盘收到的数据经过行规程
+pub fn tty_init() {
+    UART.set_input_sink(TTY.clone());
+    for (_, device) in INPUT_DEVICES.iter() {
+        if device.is_keyboard() {
+            device.set_input_sink(TTY.clone());
+        }
+    }
+}
+
+/// 终端输出同时送到串口和屏幕
+fn emit(out: &[u8]) {
+    UART.write(out);
+    #[cfg(feature = "graphics")]
+    crate::drivers::gpu::console_write(out);
+}
+
+impl Tty {
+    pub fn new() -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(TtyInner {
+                    termios: Termios::default(),
+                    winsize: WinSize::default(),
+                    line: Vec::new(),
+                    ready: VecDeque::new(),
+                    eof: 0,
+                    foreground: None,
+                    readers: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// 按 c_oflag 处理输出
+    fn output(termios: &Termios, buf: &[u8], out: &mut Vec<u8>) {
+        let oflag = termios.oflag();
+        let onlcr = oflag.contains(OutputFlags::OPOST | OutputFlags::ONLCR);
+        for &c in buf {
+            if c == b'\n' && onlcr {
+                out.push(b'\r');
+            }
+            out.push(c);
+        }
+    }
+
+    /// 回显控制字符, ECHOCTL 打开时显示为 ^X
+    fn echo_ctl(termios: &Termios, c: u8, echo: &mut Vec<u8>) {
+        if termios.lflag().contains(LocalFlags::ECHOCTL) {
+            echo.push(b'^');
+            echo.push(c ^ 0x40);
+        }
+    }
+
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            let canonical = inner.termios.lflag().contains(LocalFlags::ICANON);
+            if !inner.ready.is_empty() {
+                let mut len = 0;
+                while len < buf.len() {
+                    match inner.ready.pop_front() {
+                        Some(c) => {
+                            buf[len] = c;
+                            len += 1;
+                            // 规范模式下一次最多返回一行
+                            if canonical && c == b'\n' {
+                                break;
+                            }
+                        }
+                        None => break,
+                    }
+                }
+                return len;
+            }
+            if inner.eof > 0 {
+                inner.eof -= 1;
+                return 0;
+            }
+            inner.readers.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+
+    pub fn write(&self, buf: &[u8]) -> usize {
+        let mut out = Vec::with_capacity(buf.len());
+        Self::output(&self.inner.exclusive_access().termios, buf, &mut out);
+        emit(&out);
+        buf.len()
+    }
+
+    pub fn ioctl(&self, request: usize, arg: usize) -> isize {
+        let token = current_user_token();
+        let mut inner = self.inner.exclusive_access();
+        match request {
+            TCGETS => {
+                *translated_refmut(token, arg as *mut Termios) = inner.termios;
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                inner.termios = *translated_refmut(token, arg as *mut Termios);
+                if request == TCSETSF {
+                    inner.ready.clear();
+                }
+                // 切换到非规范模式时, 已编辑的内容直接可读
+                if !inner.termios.lflag().contains(LocalFlags::ICANON) {
+                    let line: Vec<u8> = inner.line.drain(..).collect();
+                    inner.ready.extend(line);
+                }
+            }
+            TIOCGWINSZ => {
+                *translated_refmut(token, arg as *mut WinSize) = inner.winsize;
+            }
+            TIOCSWINSZ => {
+                inner.winsize = *translated_refmut(token, arg as *mut WinSize);
+            }
+            TIOCGPGRP => {
+                *translated_refmut(token, arg as *mut i32) =
+                    inner.foreground.map_or(0, |pid| pid as i32);
+            }
+            TIOCSPGRP => {
+                let pid = *translated_refmut(token, arg as *mut i32);
+                inner.foreground = if pid > 0 { Some(pid as usize) } else { None };
+            }
+            _ => return -1,
+        }
+        0
+    }
+}
+
+impl InputSink for Tty {
+    /// 在中断上下文中处理收到的字节
+    fn push_input(&self, mut c: u8) {
+        let mut inner = self.inner.exclusive_access();
+        let termios = inner.termios;
+        let (iflag, lflag, cc) = (termios.iflag(), termios.lflag(), termios.c_cc);
+        let echo = lflag.contains(LocalFlags::ECHO);
+        let mut echo_buf = Vec::new();
+        let mut signal = None;
+        // 缓冲区满时丢弃普通字符, 留出一个字节给换行, 使已经输入的行仍能提交
+        let buffered = inner.line.len() + inner.ready.len();
+        let full = buffered >= TTY_BUF_SIZE - 1;
+
+        if c == b'\r' {
+            if iflag.contains(InputFlags::IGNCR) {
+                return;
+            }
+            if iflag.contains(InputFlags::ICRNL) {
+                c = b'\n';
+            }
+        } else if c == b'\n' && iflag.contains(InputFlags::INLCR) {
+            c = b'\r';
+        }
+
+        if lflag.contains(LocalFlags::ISIG) && (c == cc[VINTR] || c == cc[VSUSP]) {
+            signal = Some(if c == cc[VINTR] {
+                SignalFlags::SIGINT
+            } else {
+                SignalFlags::SIGTSTP
+            });
+            inner.line.clear();
+            if echo {
+                Self::echo_ctl(&termios, c, &mut echo_buf);
+                echo_buf.push(b'\n');
+            }
+        } else if lflag.contains(LocalFlags::ICANON) {
+            if c == cc[VERASE] || c == BACKSPACE {
+                if inner.line.pop().is_some() && echo && lflag.contains(LocalFlags::ECHOE) {
+                    echo_buf.extend_from_slice(b"\x08 \x08");
+                }
+            } else if c == cc[VKILL] {
+                let erased = inner.line.len();
+                inner.line.clear();
+                if echo && lflag.contains(LocalFlags::ECHOK) {
+                    for _ in 0..erased {
+                        echo_buf.extend_from_slice(b"\x08 \x08");
+                    }
+                }
+            } else if c == cc[VWERASE] && lflag.contains(LocalFlags::IEXTEN) {
+                let mut erased = 0;
+                while inner.line.last() == Some(&b' ') {
+                    inner.line.pop();
+                    erased += 1;
+                }
+                while inner.line.last().map_or(false, |&c| c != b' ') {
+                    inner.line.pop();
+                    erased += 1;
+                }
+                if echo {
+                    for _ in 0..erased {
+                        echo_buf.extend_from_slice(b"\x08 \x08");
+                    }
+                }
+            } else if c == cc[VEOF] {
+                if inner.line.is_empty() {
+                    inner.eof += 1;
+                } else {
+                    let line: Vec<u8> = inner.line.drain(..).collect();
+                    inner.ready.extend(line);
+                }
+            } else if c == b'\n' {
+                if buffered >= TTY_BUF_SIZE {
+                    return;
+                }
+                let line: Vec<u8> = inner.line.drain(..).collect();
+                inner.ready.extend(line);
+                inner.ready.push_back(b'\n');
+                if echo || lflag.contains(LocalFlags::ECHONL) {
+                    echo_buf.push(b'\n');
+                }
+            } else if !full {
+                inner.line.push(c);
+                if echo {
+                    if c < 0x20 {
+                        Self::echo_ctl(&termios, c, &mut echo_buf);
+                    } else {
+                        echo_buf.push(c);
+                    }
+                }
+            }
+        } else if buffered < TTY_BUF_SIZE {
+            inner.ready.push_back(c);
+            if echo {
+                echo_buf.push(c);
+            }
+        }
+
+        let wake = !inner.ready.is_empty() || inner.eof > 0;
+        let readers: Vec<_> = if wake {
+            inner.readers.drain(..).collect()
+        } else {
+            Vec::new()
+        };
+        let foreground = inner.foreground;
+        let mut out = Vec::new();
+        Self::output(&termios, &echo_buf, &mut out);
+        drop(inner);
+
+        if !out.is_empty() {
+            emit(&out);
+        }
+        for task in readers {
+            wakeup_task(task);
+        }
+        if let (Some(signal), Some(pid)) = (signal, foreground) {
+            if let Some(process) = pid2process(pid) {
+                let mut process_inner = process.inner_exclusive_access();
+                // 之前残留的 SIGCONT 不能抵消新的停止请求
+                if signal == SignalFlags::SIGTSTP {
+                    process_inner.signals.remove(SignalFlags::SIGCONT);
+                }
+                process_inner.signals |= signal;
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! 内核单元测试, 用 `#[kernel_test]` 标记无参数的函数
+//!
+//! 启用 `test` feature 时在初始化之后、启动 initproc 之前依次运行, 全部运行完后
+//! 报告通过与失败的个数并关机, 有失败时以失败状态关机.
+//! 测试失败即 panic, panic 处理中记下失败, 在 panic 的栈上接着运行之后的测试.
+//! 失败时持有的锁不会被释放, 之后用到同一把锁的测试可能会卡住
+pub use kernel_test_macro::kernel_test;
+
+#[cfg(any(test, feature = "test"))]
+use crate::sbi::shutdown;
+#[cfg(any(test, feature = "test"))]
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// 由 `#[kernel_test]` 生成, 放在 `.kernel_tests` 段中
+#[cfg(any(test, feature = "test"))]
+pub struct KernelTest {
+    pub name: &'static str,
+    pub func: fn(),
+}
+
+#[cfg(any(test, feature = "test"))]
+static RUNNING: AtomicBool = AtomicBool::new(false);
+/// test_runner 的参数, 它不返回, 所以在整个测试过程中有效
+#[cfg(any(test, feature = "test"))]
+static TESTS: AtomicUsize = AtomicUsize::new(0);
+#[cfg(any(test, feature = "test"))]
+static TOTAL: AtomicUsize = AtomicUsize::new(0);
+/// 下一个要运行的测试
+#[cfg(any(test, feature = "test"))]
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+#[cfg(any(test, feature = "test"))]
+static PASSED: AtomicUsize = AtomicUsize::new(0);
+#[cfg(any(test, feature = "test"))]
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+/// 运行链接进内核的所有测试
+#[cfg(all(feature = "test", not(test)))]
+pub fn run_tests() -> ! {
+    use alloc::vec::Vec;
+    extern "C" {
+        fn skernel_tests();
+        fn ekernel_tests();
+    }
+    let start = skernel_tests as usize;
+    let count = (ekernel_tests as usize - start) / core::mem::size_of::<KernelTest>();
+    let tests = unsafe { core::slice::from_raw_parts(start as *const KernelTest, count) };
+    test_runner(&tests.iter().collect::<Vec<_>>())
+}
+
+/// `cargo test` 时也作为 custom_test_frameworks 的 test_runner
+#[cfg(any(test, feature = "test"))]
+pub fn test_runner(tests: &[&KernelTest]) -> ! {
+    println!("[ktest] running {} tests", tests.len());
+    TESTS.store(tests.as_ptr() as usize, Ordering::Relaxed);
+    TOTAL.store(tests.len(), Ordering::Relaxed);
+    RUNNING.store(true, Ordering::Relaxed);
+    run_remaining()
+}
+
+/// 依次运行还没有运行的测试, 之后报告结果并关机
+#[cfg(any(test, feature = "test"))]
+fn run_remaining() -> ! {
+    let tests = unsafe {
+        core::slice::from_raw_parts(
+            TESTS.load(Ordering::Relaxed) as *const &KernelTest,
+            TOTAL.load(Ordering::Relaxed),
+        )
+    };
+    while let Some(test) = tests.get(NEXT.fetch_add(1, Ordering::Relaxed)) {
+        print!("[ktest] {} ... ", test.name);
+        (test.func)();
+        println!("ok");
+        PASSED.fetch_add(1, Ordering::Relaxed);
+    }
+    RUNNING.store(false, Ordering::Relaxed);
+    // 与正常关机相同, 写回 FsInfo 并清除卷的 dirty 标志
+    crate::fatfs::fs_unmount();
+    let passed = PASSED.load(Ordering::Relaxed);
+    let failed = FAILED.load(Ordering::Relaxed);
+    println!(
+        "[ktest] test result: {}. {} passed; {} failed",
+        if failed == 0 { "ok" } else { "FAILED" },
+        passed,
+        failed
+    );
+    shutdown(failed != 0)
+}
+
+/// 由 panic 处理调用, 记下当前测试失败并接着运行之后的测试.
+/// 不是在运行测试时 panic 则直接返回
+#[cfg(any(test, feature = "test"))]
+pub fn report_failure() {
+    if !RUNNING.load(Ordering::Relaxed) {
+        return;
+    }
+    println!("[ktest] FAILED");
+    FAILED.fetch_add(1, Ordering::Relaxed);
+    run_remaining()
+}
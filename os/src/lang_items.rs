@@ -19,6 +19,12 @@ fn panic(info: &PanicInfo) -> ! {
     unsafe {
         backtrace();
     }
+    // 缓存是 write-back 的, 尽量把已经写入的数据留在磁盘上
+    if !crate::fatfs::sdcard::try_sync_all() {
+        error!("[kernel] block cache is busy, dirty blocks are lost");
+    }
+    #[cfg(any(test, feature = "test"))]
+    crate::ktest::report_failure();
     shutdown(true)
 }
 
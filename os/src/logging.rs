@@ -0,0 +1,263 @@
+//! 内核日志, 实现 `log::Log`
+//!
+//! 每条日志带有时间戳、级别、pid/tid 与模块名, 按级别着色输出到控制台,
+//! 同时以纯文本保存到环形缓冲区, 用户程序通过 syslog 系统调用读取.
+//! 过滤规则形如 `warn,net=debug,fatfs::dir_entry=trace`, 模块名取最长匹配,
+//! 编译时由环境变量 `LOG` 指定, 启动参数 `loglevel=` 可以覆盖,
+//! 运行时可以写 /proc/sys/kernel/loglevel 修改.
+use crate::ktest::kernel_test;
+use crate::sync::UPSafeCell;
+use crate::task::try_current_task;
+use crate::timer::get_time_ns;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// 没有设置 LOG 时的过滤规则
+const DEFAULT_FILTER: &str = "warn";
+pub const LOG_BUF_LEN: usize = 64 * 1024;
+
+// 本 crate 的日志 target 以包名开头, 过滤和输出时去掉
+const CRATE_PREFIX: &str = "MyOs::";
+
+struct Filter {
+    default: LevelFilter,
+    /// (模块路径, 级别)
+    modules: Vec<(String, LevelFilter)>,
+}
+
+fn parse_level(s: &str) -> Option<LevelFilter> {
+    // 与 Linux 的 console_loglevel 兼容, 数字越大输出越多
+    match s.parse::<u8>() {
+        Ok(0..=3) => Some(LevelFilter::Error),
+        Ok(4) => Some(LevelFilter::Warn),
+        Ok(5..=6) => Some(LevelFilter::Info),
+        Ok(7) => Some(LevelFilter::Debug),
+        Ok(_) => Some(LevelFilter::Trace),
+        Err(_) => s.parse().ok(),
+    }
+}
+
+impl Filter {
+    fn parse(spec: &str) -> Option<Self> {
+        let mut filter = Self {
+            default: LevelFilter::Warn,
+            modules: Vec::new(),
+        };
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let module = module.trim().trim_start_matches(CRATE_PREFIX).to_string();
+                    filter.modules.push((module, parse_level(level.trim())?));
+                }
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        // 长的在前, 查找时第一个匹配的就是最长匹配
+        filter.modules.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        Some(filter)
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(module, _)| {
+                target.starts_with(module.as_str())
+                    && matches!(target.as_bytes().get(module.len()), None | Some(b':'))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+
+    fn to_spec(&self) -> String {
+        let mut spec = self.default.as_str().to_ascii_lowercase();
+        for (module, level) in self.modules.iter() {
+            spec += &format!(",{}={}", module, level.as_str().to_ascii_lowercase());
+        }
+        spec
+    }
+}
+
+/// 写满后丢弃最旧的内容
+struct LogBuffer {
+    data: VecDeque<u8>,
+    /// 末尾还没有被 SYSLOG_ACTION_READ 读走的字节数
+    unread: usize,
+}
+
+lazy_static! {
+    static ref FILTER: UPSafeCell<Filter> = unsafe {
+        UPSafeCell::new(
+            option_env!("LOG")
+                .and_then(Filter::parse)
+                .unwrap_or_else(|| Filter::parse(DEFAULT_FILTER).unwrap()),
+        )
+    };
+    static ref LOG_BUFFER: UPSafeCell<LogBuffer> = unsafe {
+        UPSafeCell::new(LogBuffer {
+            data: VecDeque::with_capacity(LOG_BUF_LEN),
+            unread: 0,
+        })
+    };
+}
+
+/// 关闭后日志只进入缓冲区
+static CONSOLE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+fn level_color(level: Level) -> u8 {
+    match level {
+        Level::Error => 31,
+        Level::Warn => 93,
+        Level::Info => 34,
+        Level::Debug => 32,
+        Level::Trace => 90,
+    }
+}
+
+struct KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let target = metadata.target().trim_start_matches(CRATE_PREFIX);
+        // 正在修改过滤规则时产生的日志直接放行
+        match FILTER.try_exclusive_access() {
+            Some(filter) => metadata.level() <= filter.level_for(target),
+            None => true,
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let ns = get_time_ns();
+        // 启动阶段和空闲时没有当前任务
+        let ids = match try_current_task() {
+            Some(task) => {
+                let pid = task.process.upgrade().map_or(0, |process| process.getpid());
+                match task.try_tid() {
+                    Some(tid) => format!("{}:{}", pid, tid),
+                    None => format!("{}", pid),
+                }
+            }
+            None => "-".to_string(),
+        };
+        let line = format!(
+            "[{:>5}.{:06}] {:<5} [{}] {}: {}\n",
+            ns / 1_000_000_000,
+            ns % 1_000_000_000 / 1000,
+            record.level(),
+            ids,
+            record.target().trim_start_matches(CRATE_PREFIX),
+            record.args()
+        );
+        if CONSOLE_ENABLED.load(Ordering::Relaxed) {
+            println!(
+                "\x1b[{}m{}\x1b[0m",
+                level_color(record.level()),
+                line.trim_end()
+            );
+        }
+        if let Some(mut buffer) = LOG_BUFFER.try_exclusive_access() {
+            let bytes = line.as_bytes();
+            let bytes = &bytes[bytes.len().saturating_sub(LOG_BUF_LEN)..];
+            let overflow = (buffer.data.len() + bytes.len()).saturating_sub(LOG_BUF_LEN);
+            buffer.data.drain(..overflow);
+            buffer.data.extend(bytes);
+            buffer.unread = (buffer.unread + bytes.len()).min(buffer.data.len());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: KernelLogger = KernelLogger;
+
+pub fn init() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(FILTER.exclusive_access().max_level());
+}
+
+/// 当前的过滤规则, 与写入时的格式相同
+pub fn filter_spec() -> String {
+    FILTER.exclusive_access().to_spec()
+}
+
+/// 规则格式不对时返回 false, 原规则不变
+pub fn set_filter_spec(spec: &str) -> bool {
+    match Filter::parse(spec) {
+        Some(filter) => {
+            log::set_max_level(filter.max_level());
+            *FILTER.exclusive_access() = filter;
+            true
+        }
+        None => false,
+    }
+}
+
+/// 只修改默认级别, 保留各模块的规则, level 的格式与规则中的级别相同
+pub fn set_default_level(level: &str) -> bool {
+    match parse_level(level) {
+        Some(level) => {
+            let mut filter = FILTER.exclusive_access();
+            filter.default = level;
+            log::set_max_level(filter.max_level());
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn set_console_enabled(enabled: bool) {
+    CONSOLE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// 复制缓冲区中最后的至多 len 个字节, 不影响未读位置
+pub fn read_all(len: usize) -> Vec<u8> {
+    let buffer = LOG_BUFFER.exclusive_access();
+    let start = buffer.data.len().saturating_sub(len);
+    buffer.data.range(start..).copied().collect()
+}
+
+/// 取走至多 len 个未读字节
+pub fn read_unread(len: usize) -> Vec<u8> {
+    let mut buffer = LOG_BUFFER.exclusive_access();
+    let start = buffer.data.len() - buffer.unread;
+    let len = len.min(buffer.unread);
+    buffer.unread -= len;
+    buffer.data.range(start..start + len).copied().collect()
+}
+
+pub fn unread_len() -> usize {
+    LOG_BUFFER.exclusive_access().unread
+}
+
+pub fn clear() {
+    let mut buffer = LOG_BUFFER.exclusive_access();
+    buffer.data.clear();
+    buffer.unread = 0;
+}
+
+#[kernel_test]
+fn filter_test() {
+    let filter = Filter::parse("info, fs=debug, fs::fat=5, MyOs::net=off").unwrap();
+    assert_eq!(filter.level_for("task"), LevelFilter::Info);
+    assert_eq!(filter.level_for("fs::inode"), LevelFilter::Debug);
+    assert_eq!(filter.level_for("fs::fat::dir"), LevelFilter::Info);
+    // 只按完整的路径段匹配
+    assert_eq!(filter.level_for("fsync"), LevelFilter::Info);
+    assert_eq!(filter.level_for("net"), LevelFilter::Off);
+    assert_eq!(filter.max_level(), LevelFilter::Debug);
+    assert_eq!(filter.to_spec(), "info,fs::fat=info,net=off,fs=debug");
+    assert!(Filter::parse("fs=loud").is_none());
+}
@@ -3,6 +3,9 @@
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
 #![feature(fn_traits)]
+#![cfg_attr(test, feature(custom_test_frameworks))]
+#![cfg_attr(test, test_runner(crate::ktest::test_runner))]
+#![cfg_attr(test, reexport_test_harness_main = "test_main")]
 extern crate alloc;
 
 #[macro_use]
@@ -24,7 +27,13 @@ mod task;
 mod timer;
 mod trap;
 mod drivers;
+mod fdt;
+mod logging;
 mod fatfs;
+mod net;
+mod smp;
+mod cmdline;
+mod ktest;
 use core::arch::global_asm;
 use crate::fatfs::fs_init;
 
@@ -42,16 +51,44 @@ fn clear_bss() {
 }
 
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(hart_id: usize, dtb: usize) -> ! {
     clear_bss();
+    // 设备树所在的内存之后会被当作空闲物理页分配出去, 需要先读出来
+    mm::init_heap();
+    fdt::init(dtb);
+    logging::init();
+    cmdline::init();
     println!("[kernel] Hello, world!");
+    fdt::print_machine_info();
     mm::init();
-    mm::remap_test();
     trap::init();
+    drivers::init();
+    fs::tty_init();
+    net::init();
     trap::enable_timer_interrupt();
+    trap::enable_external_interrupt();
     timer::set_next_trigger();
     fs_init();
+    #[cfg(test)]
+    test_main();
+    #[cfg(all(feature = "test", not(test)))]
+    ktest::run_tests();
     task::add_initproc();
+    smp::set_online(hart_id);
+    smp::start_secondary_harts(hart_id);
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
+
+/// 其他 hart 的入口, 共享启动 hart 初始化好的内核地址空间
+#[no_mangle]
+pub fn rust_main_secondary(hart_id: usize) -> ! {
+    mm::KERNEL_SPACE.exclusive_access().activate();
+    trap::init();
+    trap::enable_timer_interrupt();
+    timer::set_next_trigger();
+    smp::set_online(hart_id);
+    println!("[kernel] hart {} online", hart_id);
+    task::run_tasks();
+    panic!("Unreachable in rust_main_secondary!");
+}
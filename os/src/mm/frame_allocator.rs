@@ -1,6 +1,8 @@
 use super::{PhysAddr, PhysPageNum};
-use crate::config::MEMORY_END;
-use crate::sync::UPSafeCell;
+use crate::fdt::MACHINE;
+use crate::ktest::kernel_test;
+use log::info;
+use crate::sync::SpinNoIrq;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::*;
@@ -48,7 +50,7 @@ impl StackFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
         self.current = l.0;
         self.end = r.0;
-        println!("last {} Physical Frames.", self.end - self.current);
+        info!("last {} Physical Frames.", self.end - self.current);
     }
 }
 impl FrameAllocator for StackFrameAllocator {
@@ -83,45 +85,45 @@ impl FrameAllocator for StackFrameAllocator {
 type FrameAllocatorImpl = StackFrameAllocator;
 
 lazy_static! {
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
-        unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
+    pub static ref FRAME_ALLOCATOR: SpinNoIrq<FrameAllocatorImpl> =
+        SpinNoIrq::new(FrameAllocatorImpl::new());
 }
 
 pub fn init_frame_allocator() {
     extern "C" {
         fn ekernel();
     }
-    FRAME_ALLOCATOR.exclusive_access().init(
+    FRAME_ALLOCATOR.lock().init(
         PhysAddr::from(ekernel as usize).ceil(),
-        PhysAddr::from(MEMORY_END).floor(),
+        PhysAddr::from(MACHINE.memory_end).floor(),
     );
 }
 
 pub fn frame_alloc() -> Option<FrameTracker> {
     FRAME_ALLOCATOR
-        .exclusive_access()
+        .lock()
         .alloc()
         .map(FrameTracker::new)
 }
 
 pub fn frame_dealloc(ppn: PhysPageNum) {
-    FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
+    FRAME_ALLOCATOR.lock().dealloc(ppn);
 }
 
-#[allow(unused)]
-pub fn frame_allocator_test() {
+#[kernel_test]
+fn frame_allocator_test() {
     let mut v: Vec<FrameTracker> = Vec::new();
-    for i in 0..5 {
-        let frame = frame_alloc().unwrap();
-        println!("{:?}", frame);
-        v.push(frame);
+    for _ in 0..5 {
+        v.push(frame_alloc().unwrap());
     }
+    let mut first: Vec<PhysPageNum> = v.iter().map(|frame| frame.ppn).collect();
     v.clear();
-    for i in 0..5 {
-        let frame = frame_alloc().unwrap();
-        println!("{:?}", frame);
-        v.push(frame);
+    // 释放的页帧会被重新分配
+    for _ in 0..5 {
+        v.push(frame_alloc().unwrap());
     }
-    drop(v);
-    println!("frame_allocator_test passed!");
+    let mut second: Vec<PhysPageNum> = v.iter().map(|frame| frame.ppn).collect();
+    first.sort_by_key(|ppn| ppn.0);
+    second.sort_by_key(|ppn| ppn.0);
+    assert_eq!(first, second);
 }
@@ -1,4 +1,5 @@
 use crate::config::KERNEL_HEAP_SIZE;
+use crate::ktest::kernel_test;
 use buddy_system_allocator::LockedHeap;
 
 #[global_allocator]
@@ -19,8 +20,8 @@ pub fn init_heap() {
     }
 }
 
-#[allow(unused)]
-pub fn heap_test() {
+#[kernel_test]
+fn heap_test() {
     use alloc::boxed::Box;
     use alloc::vec::Vec;
     extern "C" {
@@ -41,5 +42,4 @@ pub fn heap_test() {
     }
     assert!(bss_range.contains(&(v.as_ptr() as usize)));
     drop(v);
-    println!("heap_test passed!");
 }
@@ -2,11 +2,16 @@ use super::{frame_alloc, FrameTracker};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE};
+use crate::config::{MMAP_BASE, PAGE_SIZE, TRAMPOLINE};
+use crate::fdt::MACHINE;
+use crate::ktest::kernel_test;
+use crate::smp::tlb_shootdown;
+use log::debug;
 use crate::sync::UPSafeCell;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::any::Any;
 use core::arch::asm;
 use lazy_static::*;
 use riscv::register::satp;
@@ -60,6 +65,68 @@ impl MemorySet {
             None,
         );
     }
+    /// 与 insert_framed_area 相同, 物理页帧不够时不做映射并返回 false
+    pub fn try_insert_framed_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> bool {
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, permission);
+        if !map_area.try_map(&mut self.page_table) {
+            return false;
+        }
+        self.areas.push(map_area);
+        true
+    }
+    /// 把一段物理地址连续的设备内存映射到 [start_va, end_va), holder 随映射一起释放
+    pub fn insert_linear_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        pa: PhysAddr,
+        permission: MapPermission,
+        holder: Arc<dyn Any + Send + Sync>,
+    ) {
+        let mut area = MapArea::new(start_va, end_va, MapType::Linear(pa.floor()), permission);
+        area.holder = Some(holder);
+        self.push(area, None);
+    }
+    /// [start, end) 与已有的映射都不重叠
+    pub fn is_free(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        self.areas.iter().all(|area| {
+            area.vpn_range.get_end() <= start || end <= area.vpn_range.get_start()
+        })
+    }
+    /// 从 MMAP_BASE 开始找第一段足够长的空闲虚拟地址
+    pub fn find_free_area(&self, len: usize) -> VirtAddr {
+        let pages = VirtAddr::from(len).ceil().0;
+        let mut start = VirtAddr::from(MMAP_BASE).floor();
+        while !self.is_free(start, VirtPageNum(start.0 + pages)) {
+            start = self
+                .areas
+                .iter()
+                .map(|area| area.vpn_range.get_end())
+                .filter(|&end| end > start)
+                .min()
+                .unwrap();
+        }
+        start.into()
+    }
+    /// 删除恰好覆盖 [start, end) 的映射, 不支持只删除其中一部分
+    pub fn remove_area(&mut self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        match self.areas.iter().position(|area| {
+            area.vpn_range.get_start() == start && area.vpn_range.get_end() == end
+        }) {
+            Some(idx) => {
+                self.areas[idx].unmap(&mut self.page_table);
+                self.areas.remove(idx);
+                tlb_shootdown(VirtAddr::from(start).0, (end.0 - start.0) * PAGE_SIZE);
+                true
+            }
+            None => false,
+        }
+    }
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
             .areas
@@ -68,7 +135,9 @@ impl MemorySet {
             .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
         {
             area.unmap(&mut self.page_table);
+            let end_vpn = area.vpn_range.get_end();
             self.areas.remove(idx);
+            tlb_shootdown(VirtAddr::from(start_vpn).0, (end_vpn.0 - start_vpn.0) * PAGE_SIZE);
         }
     }
     fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
@@ -92,14 +161,14 @@ impl MemorySet {
         // map trampoline
         memory_set.map_trampoline();
         // map kernel sections
-        println!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
-        println!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
-        println!(".data [{:#x}, {:#x})", sdata as usize, edata as usize);
-        println!(
+        debug!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
+        debug!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
+        debug!(".data [{:#x}, {:#x})", sdata as usize, edata as usize);
+        debug!(
             ".bss [{:#x}, {:#x})",
             sbss_with_stack as usize, ebss as usize
         );
-        println!("mapping .text section");
+        debug!("mapping .text section");
         memory_set.push(
             MapArea::new(
                 (stext as usize).into(),
@@ -109,7 +178,7 @@ impl MemorySet {
             ),
             None,
         );
-        println!("mapping .rodata section");
+        debug!("mapping .rodata section");
         memory_set.push(
             MapArea::new(
                 (srodata as usize).into(),
@@ -119,7 +188,7 @@ impl MemorySet {
             ),
             None,
         );
-        println!("mapping .data section");
+        debug!("mapping .data section");
         memory_set.push(
             MapArea::new(
                 (sdata as usize).into(),
@@ -129,7 +198,7 @@ impl MemorySet {
             ),
             None,
         );
-        println!("mapping .bss section");
+        debug!("mapping .bss section");
         memory_set.push(
             MapArea::new(
                 (sbss_with_stack as usize).into(),
@@ -139,22 +208,22 @@ impl MemorySet {
             ),
             None,
         );
-        println!("mapping physical memory");
+        debug!("mapping physical memory");
         memory_set.push(
             MapArea::new(
                 (ekernel as usize).into(),
-                MEMORY_END.into(),
+                MACHINE.memory_end.into(),
                 MapType::Identical,
                 MapPermission::R | MapPermission::W,
             ),
             None,
         );
-        println!("mapping memory-mapped registers");
-        for pair in MMIO {
+        debug!("mapping memory-mapped registers");
+        for pair in MACHINE.mmio_regions() {
             memory_set.push(
                 MapArea::new(
-                    (*pair).0.into(),
-                    ((*pair).0 + (*pair).1).into(),
+                    pair.0.into(),
+                    (pair.0 + pair.1).into(),
                     MapType::Identical,
                     MapPermission::R | MapPermission::W,
                 ),
@@ -217,6 +286,10 @@ impl MemorySet {
         for area in user_space.areas.iter() {
             let new_area = MapArea::from_another(area);
             memory_set.push(new_area, None);
+            // 设备内存父子进程共享同一份, 不需要复制
+            if area.map_type != MapType::Framed {
+                continue;
+            }
             // copy data from another space
             for vpn in area.vpn_range {
                 let src_ppn = user_space.translate(vpn).unwrap().ppn();
@@ -249,6 +322,8 @@ pub struct MapArea {
     data_frames: BTreeMap<VirtPageNum, FrameTracker>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// 映射设备内存时由设备给出, fork 出的映射共享它
+    holder: Option<Arc<dyn Any + Send + Sync>>,
 }
 
 impl MapArea {
@@ -265,6 +340,7 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            holder: None,
         }
     }
     pub fn from_another(another: &MapArea) -> Self {
@@ -273,22 +349,34 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            holder: another.holder.clone(),
         }
     }
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        assert!(self.try_map_one(page_table, vpn), "out of physical frames");
+    }
+    /// 物理页帧不够时返回 false
+    pub fn try_map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
         let ppn: PhysPageNum;
         match self.map_type {
             MapType::Identical => {
                 ppn = PhysPageNum(vpn.0);
             }
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+                let frame = match frame_alloc() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
                 ppn = frame.ppn;
                 self.data_frames.insert(vpn, frame);
             }
+            MapType::Linear(base) => {
+                ppn = PhysPageNum(base.0 + vpn.0 - self.vpn_range.get_start().0);
+            }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         page_table.map(vpn, ppn, pte_flags);
+        true
     }
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         if self.map_type == MapType::Framed {
@@ -301,6 +389,18 @@ impl MapArea {
             self.map_one(page_table, vpn);
         }
     }
+    /// 物理页帧不够时撤销已经建立的映射并返回 false
+    pub fn try_map(&mut self, page_table: &mut PageTable) -> bool {
+        for vpn in self.vpn_range {
+            if !self.try_map_one(page_table, vpn) {
+                for mapped in VPNRange::new(self.vpn_range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return false;
+            }
+        }
+        true
+    }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
@@ -334,6 +434,8 @@ impl MapArea {
 pub enum MapType {
     Identical,
     Framed,
+    /// 映射到从给定物理页开始的一段连续物理内存
+    Linear(PhysPageNum),
 }
 
 bitflags! {
@@ -345,8 +447,8 @@ bitflags! {
     }
 }
 
-#[allow(unused)]
-pub fn remap_test() {
+#[kernel_test]
+fn remap_test() {
     let mut kernel_space = KERNEL_SPACE.exclusive_access();
     let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
     let mid_rodata: VirtAddr = ((srodata as usize + erodata as usize) / 2).into();
@@ -366,5 +468,4 @@ pub fn remap_test() {
         .translate(mid_data.floor())
         .unwrap()
         .executable(),);
-    println!("remap_test passed!");
 }
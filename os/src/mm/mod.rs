@@ -7,7 +7,6 @@ mod page_table;
 use address::VPNRange;
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
 pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
-pub use memory_set::remap_test;
 pub use memory_set::{kernel_token, MapPermission, MemorySet, KERNEL_SPACE};
 use page_table::PTEFlags;
 pub use page_table::{
@@ -15,8 +14,10 @@ pub use page_table::{
     PageTableEntry, UserBuffer,
 };
 
+pub use heap_allocator::init_heap;
+
+/// 调用前需要先初始化堆并解析设备树
 pub fn init() {
-    heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
     KERNEL_SPACE.exclusive_access().activate();
 }
@@ -210,6 +210,19 @@ impl UserBuffer {
         }
         total
     }
+    /// 把 `data` 拷贝到用户缓冲区, 返回拷贝的字节数
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let mut copied = 0;
+        for buffer in self.buffers.iter_mut() {
+            let len = buffer.len().min(data.len() - copied);
+            buffer[..len].copy_from_slice(&data[copied..copied + len]);
+            copied += len;
+            if copied == data.len() {
+                break;
+            }
+        }
+        copied
+    }
 }
 
 impl IntoIterator for UserBuffer {
@@ -0,0 +1,82 @@
+//! 把 `NetDevice` 适配为 smoltcp 的 `phy::Device`
+use crate::drivers::NetDevice;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use smoltcp::phy::{self, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+
+const MTU: usize = 1500;
+// 以太网帧头 14 字节
+const MAX_FRAME_SIZE: usize = MTU + 14;
+
+pub struct NetDeviceAdapter {
+    device: Arc<dyn NetDevice>,
+}
+
+impl NetDeviceAdapter {
+    pub fn new(device: Arc<dyn NetDevice>) -> Self {
+        Self { device }
+    }
+}
+
+impl phy::Device for NetDeviceAdapter {
+    type RxToken<'a> = RxToken;
+    type TxToken<'a> = TxToken;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let mut buffer = vec![0u8; MAX_FRAME_SIZE];
+        let len = self.device.recv(&mut buffer)?;
+        buffer.truncate(len);
+        Some((
+            RxToken { buffer },
+            TxToken {
+                device: self.device.clone(),
+            },
+        ))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(TxToken {
+            device: self.device.clone(),
+        })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = MAX_FRAME_SIZE;
+        caps.max_burst_size = Some(1);
+        caps.medium = Medium::Ethernet;
+        caps
+    }
+}
+
+pub struct RxToken {
+    buffer: Vec<u8>,
+}
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.buffer)
+    }
+}
+
+pub struct TxToken {
+    device: Arc<dyn NetDevice>,
+}
+
+impl phy::TxToken for TxToken {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buffer = vec![0u8; len];
+        let result = f(&mut buffer);
+        // 发送队列满时丢弃, 由 TCP 负责重传
+        self.device.send(&buffer);
+        result
+    }
+}
@@ -0,0 +1,245 @@
+//! 基于 smoltcp 的 TCP/IP 协议栈
+//! eth0 接在 virtio 网卡上, 地址按 QEMU user 模式网络配置; lo 为回环接口
+mod device;
+mod socket;
+mod tcp;
+mod udp;
+mod unix;
+
+use crate::drivers::{NetDevice, NET_DEVICE};
+use crate::sync::UPSafeCell;
+use crate::timer::get_time_ms;
+use alloc::vec;
+use alloc::vec::Vec;
+use device::NetDeviceAdapter;
+use lazy_static::*;
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+use smoltcp::phy::{Device, Loopback, Medium};
+use smoltcp::socket::{tcp, AnySocket};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, Ipv4Address};
+
+pub use socket::{SockAddr, Socket, SocketType, AF_INET, AF_UNIX, SOCKET_BUFFER_SIZE};
+pub use tcp::TcpSocket;
+pub use udp::UdpSocket;
+pub use unix::UnixSocket;
+
+// QEMU user 模式网络分配的地址
+const ETH_IP: Ipv4Address = Ipv4Address::new(10, 0, 2, 15);
+const ETH_PREFIX: u8 = 24;
+const ETH_GATEWAY: Ipv4Address = Ipv4Address::new(10, 0, 2, 2);
+const LOOPBACK_IP: Ipv4Address = Ipv4Address::new(127, 0, 0, 1);
+// 临时端口范围
+const EPHEMERAL_PORT_START: u16 = 49152;
+const EPHEMERAL_PORT_END: u16 = 65535;
+
+fn now() -> Instant {
+    Instant::from_millis(get_time_ms() as i64)
+}
+
+/// 一个网络接口及挂在它上面的所有 socket
+pub struct NetInterface<D: Device> {
+    pub name: &'static str,
+    iface: Interface,
+    device: D,
+    sockets: SocketSet<'static>,
+    /// 已经关闭但还在挥手的 TCP socket, 进入 Closed 状态后才从集合中移除
+    closing: Vec<SocketHandle>,
+}
+
+impl<D: Device> NetInterface<D> {
+    fn new(
+        name: &'static str,
+        mut device: D,
+        hardware_addr: HardwareAddress,
+        cidrs: &[IpCidr],
+    ) -> Self {
+        let mut iface = Interface::new(Config::new(hardware_addr), &mut device, now());
+        iface.update_ip_addrs(|addrs| {
+            for &cidr in cidrs {
+                addrs.push(cidr).unwrap();
+            }
+        });
+        Self {
+            name,
+            iface,
+            device,
+            sockets: SocketSet::new(vec![]),
+            closing: Vec::new(),
+        }
+    }
+
+    /// 收发数据包并推进各 socket 的状态, 有 socket 状态变化时返回 true
+    pub fn poll(&mut self) -> bool {
+        let changed = self.iface.poll(now(), &mut self.device, &mut self.sockets);
+        let sockets = &mut self.sockets;
+        self.closing.retain(|&handle| {
+            if sockets.get::<tcp::Socket>(handle).state() == tcp::State::Closed {
+                sockets.remove(handle);
+                false
+            } else {
+                true
+            }
+        });
+        changed
+    }
+
+    pub fn add_socket<T: AnySocket<'static>>(&mut self, socket: T) -> SocketHandle {
+        self.sockets.add(socket)
+    }
+
+    pub fn remove_socket(&mut self, handle: SocketHandle) {
+        self.sockets.remove(handle);
+    }
+
+    /// 发起 TCP 关闭, 挥手完成后 socket 由 poll 回收
+    pub fn close_tcp(&mut self, handle: SocketHandle) {
+        self.sockets.get_mut::<tcp::Socket>(handle).close();
+        self.closing.push(handle);
+    }
+
+    pub fn with_socket<T: AnySocket<'static>, R>(
+        &mut self,
+        handle: SocketHandle,
+        f: impl FnOnce(&mut T, &mut Interface) -> R,
+    ) -> R {
+        let socket = self.sockets.get_mut::<T>(handle);
+        f(socket, &mut self.iface)
+    }
+}
+
+lazy_static! {
+    pub static ref ETH0: Option<UPSafeCell<NetInterface<NetDeviceAdapter>>> =
+        NET_DEVICE.as_ref().map(|device| {
+            let mac = EthernetAddress(device.mac());
+            let mut eth0 = NetInterface::new(
+                "eth0",
+                NetDeviceAdapter::new(device.clone()),
+                HardwareAddress::Ethernet(mac),
+                &[IpCidr::new(IpAddress::Ipv4(ETH_IP), ETH_PREFIX)],
+            );
+            eth0.iface
+                .routes_mut()
+                .add_default_ipv4_route(ETH_GATEWAY)
+                .unwrap();
+            unsafe { UPSafeCell::new(eth0) }
+        });
+    pub static ref LOOPBACK: UPSafeCell<NetInterface<Loopback>> = unsafe {
+        UPSafeCell::new(NetInterface::new(
+            "lo",
+            Loopback::new(Medium::Ip),
+            HardwareAddress::Ip,
+            // 本机发往 eth0 地址的包也从回环接口收到
+            &[
+                IpCidr::new(IpAddress::Ipv4(LOOPBACK_IP), 8),
+                IpCidr::new(IpAddress::Ipv4(ETH_IP), 32),
+            ],
+        ))
+    };
+    static ref NEXT_EPHEMERAL_PORT: UPSafeCell<u16> = unsafe { UPSafeCell::new(EPHEMERAL_PORT_START) };
+}
+
+/// socket 所在的接口
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetIface {
+    Eth0,
+    Loopback,
+}
+
+impl NetIface {
+    /// 发往 addr 的数据包使用的接口, 发给本机 eth0 地址的也走回环
+    pub fn route(addr: IpAddress) -> Option<Self> {
+        if addr.is_loopback() || addr == IpAddress::Ipv4(ETH_IP) {
+            Some(Self::Loopback)
+        } else if ETH0.is_some() {
+            Some(Self::Eth0)
+        } else {
+            None
+        }
+    }
+
+    /// 在 addr 上监听时需要挂 socket 的接口, None 表示任意地址
+    pub fn listen_on(addr: Option<IpAddress>) -> Vec<Self> {
+        let mut ifaces = vec![Self::Loopback];
+        if ETH0.is_some() && addr.map_or(true, |addr| addr == IpAddress::Ipv4(ETH_IP)) {
+            ifaces.push(Self::Eth0);
+        }
+        ifaces
+    }
+
+    /// 本机在该接口上的地址, 用于 getsockname
+    pub fn local_addr(self) -> IpAddress {
+        match self {
+            Self::Eth0 => IpAddress::Ipv4(ETH_IP),
+            Self::Loopback => IpAddress::Ipv4(LOOPBACK_IP),
+        }
+    }
+
+    pub fn add_socket<T: AnySocket<'static>>(self, socket: T) -> SocketHandle {
+        match self {
+            Self::Eth0 => ETH0.as_ref().unwrap().exclusive_access().add_socket(socket),
+            Self::Loopback => LOOPBACK.exclusive_access().add_socket(socket),
+        }
+    }
+
+    pub fn remove_socket(self, handle: SocketHandle) {
+        match self {
+            Self::Eth0 => ETH0.as_ref().unwrap().exclusive_access().remove_socket(handle),
+            Self::Loopback => LOOPBACK.exclusive_access().remove_socket(handle),
+        }
+    }
+
+    pub fn close_tcp(self, handle: SocketHandle) {
+        match self {
+            Self::Eth0 => ETH0.as_ref().unwrap().exclusive_access().close_tcp(handle),
+            Self::Loopback => LOOPBACK.exclusive_access().close_tcp(handle),
+        }
+    }
+
+    pub fn with_socket<T: AnySocket<'static>, R>(
+        self,
+        handle: SocketHandle,
+        f: impl FnOnce(&mut T, &mut Interface) -> R,
+    ) -> R {
+        match self {
+            Self::Eth0 => ETH0.as_ref().unwrap().exclusive_access().with_socket(handle, f),
+            Self::Loopback => LOOPBACK.exclusive_access().with_socket(handle, f),
+        }
+    }
+}
+
+/// 分配一个临时端口, 不检查是否已被占用
+pub fn alloc_ephemeral_port() -> u16 {
+    let mut next = NEXT_EPHEMERAL_PORT.exclusive_access();
+    let port = *next;
+    *next = if port == EPHEMERAL_PORT_END {
+        EPHEMERAL_PORT_START
+    } else {
+        port + 1
+    };
+    port
+}
+
+pub fn init() {
+    match ETH0.as_ref() {
+        Some(eth0) => {
+            let eth0 = eth0.exclusive_access();
+            println!("[kernel] net: {} {:?}", eth0.name, eth0.iface.ip_addrs());
+        }
+        None => println!("[kernel] net: no network device, loopback only"),
+    }
+    LOOPBACK.exclusive_access().poll();
+}
+
+/// 驱动所有接口收发数据包. 中断或定时器到来时协议栈可能正被使用, 此时跳过
+pub fn poll_interfaces() {
+    if let Some(eth0) = ETH0.as_ref() {
+        if let Some(mut eth0) = eth0.try_exclusive_access() {
+            eth0.poll();
+        }
+    }
+    if let Some(mut lo) = LOOPBACK.try_exclusive_access() {
+        // 回环接口发出的包要再 poll 一次才能被收到
+        while lo.poll() {}
+    }
+}
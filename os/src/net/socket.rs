@@ -0,0 +1,183 @@
+//! socket 的公共接口与地址格式
+use crate::fs::{File, Kstat, StatMode};
+use crate::mm::UserBuffer;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
+
+pub const AF_UNIX: u16 = 1;
+pub const AF_INET: u16 = 2;
+
+// setsockopt/getsockopt 的 level 与选项
+pub const SOL_SOCKET: usize = 1;
+pub const SO_REUSEADDR: usize = 2;
+pub const SO_TYPE: usize = 3;
+pub const SO_ERROR: usize = 4;
+pub const SO_SNDBUF: usize = 7;
+pub const SO_RCVBUF: usize = 8;
+pub const SO_KEEPALIVE: usize = 9;
+pub const IPPROTO_TCP: usize = 6;
+pub const TCP_NODELAY: usize = 1;
+
+const SOCKADDR_IN_LEN: usize = 16;
+const SOCKADDR_UN_PATH_LEN: usize = 108;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketType {
+    Stream = 1,
+    Datagram = 2,
+}
+
+impl SocketType {
+    /// type 参数中还可能带有 SOCK_NONBLOCK/SOCK_CLOEXEC, 这里忽略
+    pub fn from_raw(raw: usize) -> Option<Self> {
+        match raw & 0xf {
+            1 => Some(Self::Stream),
+            2 => Some(Self::Datagram),
+            _ => None,
+        }
+    }
+}
+
+/// 用户态 sockaddr 在内核中的表示
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SockAddr {
+    Inet(IpEndpoint),
+    Unix(String),
+}
+
+impl SockAddr {
+    /// 解析 sockaddr_in/sockaddr_un, 格式不对时返回 None
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 2 {
+            return None;
+        }
+        match u16::from_ne_bytes([bytes[0], bytes[1]]) {
+            AF_INET if bytes.len() >= 8 => {
+                let port = u16::from_be_bytes([bytes[2], bytes[3]]);
+                let addr = Ipv4Address::from_bytes(&bytes[4..8]);
+                Some(Self::Inet(IpEndpoint::new(IpAddress::Ipv4(addr), port)))
+            }
+            AF_UNIX => {
+                let path = &bytes[2..];
+                let len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+                let path = core::str::from_utf8(&path[..len]).ok()?;
+                if path.is_empty() {
+                    return None;
+                }
+                Some(Self::Unix(String::from(path)))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Self::Inet(endpoint) => {
+                bytes.extend_from_slice(&AF_INET.to_ne_bytes());
+                bytes.extend_from_slice(&endpoint.port.to_be_bytes());
+                match endpoint.addr {
+                    IpAddress::Ipv4(addr) => bytes.extend_from_slice(addr.as_bytes()),
+                }
+                bytes.resize(SOCKADDR_IN_LEN, 0);
+            }
+            Self::Unix(path) => {
+                bytes.extend_from_slice(&AF_UNIX.to_ne_bytes());
+                let len = path.len().min(SOCKADDR_UN_PATH_LEN - 1);
+                bytes.extend_from_slice(&path.as_bytes()[..len]);
+                bytes.push(0);
+            }
+        }
+        bytes
+    }
+
+    /// 不是 AF_INET 地址时返回 None
+    pub fn inet(&self) -> Option<IpEndpoint> {
+        match self {
+            Self::Inet(endpoint) => Some(*endpoint),
+            Self::Unix(_) => None,
+        }
+    }
+}
+
+/// 所有 socket 都是文件, 可以用 read/write/close 操作
+pub trait Socket: File {
+    fn socket_type(&self) -> SocketType;
+    fn bind(&self, addr: SockAddr) -> isize;
+    fn listen(&self, backlog: usize) -> isize;
+    /// 阻塞到有新连接, 返回新的 socket 与对端地址
+    fn accept(&self) -> Option<(Arc<dyn Socket>, SockAddr)>;
+    fn connect(&self, addr: SockAddr) -> isize;
+    /// 发送数据, 面向连接的 socket 忽略 addr
+    fn sendto(&self, buf: &[u8], addr: Option<SockAddr>) -> isize;
+    /// 阻塞到收到数据, 返回长度与发送方地址
+    fn recvfrom(&self, buf: &mut [u8]) -> (isize, Option<SockAddr>);
+    fn local_addr(&self) -> Option<SockAddr>;
+    fn peer_addr(&self) -> Option<SockAddr>;
+    fn shutdown(&self, how: usize) -> isize;
+    fn setsockopt(&self, level: usize, name: usize, _value: u32) -> isize {
+        sol_socket_setsockopt(level, name)
+    }
+    fn getsockopt(&self, level: usize, name: usize) -> Option<u32> {
+        sol_socket_getsockopt(self.socket_type(), level, name)
+    }
+}
+
+/// SOL_SOCKET 层的选项只接受不生效
+pub fn sol_socket_setsockopt(level: usize, name: usize) -> isize {
+    match (level, name) {
+        (SOL_SOCKET, SO_REUSEADDR | SO_KEEPALIVE | SO_SNDBUF | SO_RCVBUF) => 0,
+        _ => -1,
+    }
+}
+
+pub fn sol_socket_getsockopt(socket_type: SocketType, level: usize, name: usize) -> Option<u32> {
+    match (level, name) {
+        (SOL_SOCKET, SO_TYPE) => Some(socket_type as u32),
+        (SOL_SOCKET, SO_ERROR) => Some(0),
+        (SOL_SOCKET, SO_REUSEADDR | SO_KEEPALIVE) => Some(0),
+        (SOL_SOCKET, SO_SNDBUF | SO_RCVBUF) => Some(SOCKET_BUFFER_SIZE as u32),
+        _ => None,
+    }
+}
+
+/// 每个 socket 收发缓冲区的大小. 缓冲区从 2 MiB 的内核堆分配, 不能太大
+pub const SOCKET_BUFFER_SIZE: usize = 16 * 1024;
+
+/// 分配一个 socket 缓冲区, 内核堆不够时返回 None
+pub fn alloc_socket_buffer() -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(SOCKET_BUFFER_SIZE).ok()?;
+    buf.resize(SOCKET_BUFFER_SIZE, 0);
+    Some(buf)
+}
+
+/// 各种 socket 的 `File::read`, 出错时返回 -1
+pub fn socket_read(socket: &dyn Socket, mut user_buf: UserBuffer) -> usize {
+    let mut buf = vec![0u8; user_buf.len().min(SOCKET_BUFFER_SIZE)];
+    let (len, _) = socket.recvfrom(&mut buf);
+    if len < 0 {
+        return -1isize as usize;
+    }
+    user_buf.write(&buf[..len as usize])
+}
+
+/// 各种 socket 的 `File::write`, 出错时返回 -1
+pub fn socket_write(socket: &dyn Socket, user_buf: UserBuffer) -> usize {
+    let buf: Vec<u8> = user_buf.buffers.concat();
+    let len = socket.sendto(&buf, None);
+    if len < 0 {
+        return -1isize as usize;
+    }
+    len as usize
+}
+
+pub fn socket_kstat(socket: &dyn Socket, stat: &mut Kstat) {
+    stat.sd_ino = socket as *const dyn Socket as *const u8 as u64;
+    stat.st_mode = (StatMode::S_IFSOCK | StatMode::S_IRUSR | StatMode::S_IWUSR).bits();
+    stat.st_nlink = 1;
+    stat.st_blksize = SOCKET_BUFFER_SIZE as u32;
+}
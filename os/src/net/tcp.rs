@@ -0,0 +1,378 @@
+//! AF_INET 流式 socket
+use super::socket::{
+    alloc_socket_buffer, socket_kstat, socket_read, socket_write, sol_socket_getsockopt,
+    sol_socket_setsockopt, SockAddr, Socket, SocketType, IPPROTO_TCP, TCP_NODELAY,
+};
+use super::{alloc_ephemeral_port, poll_interfaces, NetIface};
+use crate::fs::{File, Kstat};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::suspend_current_and_run_next;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::tcp;
+use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint, Ipv4Address};
+
+const SHUT_RD: usize = 0;
+/// listen 的 backlog 上限, 每个接口最多同时挂这么多个监听 socket
+const MAX_BACKLOG: usize = 4;
+
+enum TcpState {
+    /// 还没有连接或监听, 可能已经 bind
+    Closed,
+    /// 每个接口上挂 backlog 个处于 Listen 状态的 socket, 同时到来的连接各占一个,
+    /// 被 accept 后换成新的
+    Listening(Vec<(NetIface, SocketHandle)>),
+    Connected(NetIface, SocketHandle),
+}
+
+struct TcpInner {
+    local: Option<IpEndpoint>,
+    state: TcpState,
+    nodelay: bool,
+}
+
+pub struct TcpSocket {
+    inner: UPSafeCell<TcpInner>,
+}
+
+/// 内核堆不够时返回 None
+fn new_tcp_socket() -> Option<tcp::Socket<'static>> {
+    Some(tcp::Socket::new(
+        tcp::SocketBuffer::new(alloc_socket_buffer()?),
+        tcp::SocketBuffer::new(alloc_socket_buffer()?),
+    ))
+}
+
+/// 在 iface 上挂一个监听 endpoint 的 socket
+fn add_listener(iface: NetIface, endpoint: IpListenEndpoint) -> Option<SocketHandle> {
+    let handle = iface.add_socket(new_tcp_socket()?);
+    let result = iface.with_socket::<tcp::Socket, _>(handle, |socket, _| socket.listen(endpoint));
+    if result.is_err() {
+        iface.remove_socket(handle);
+        return None;
+    }
+    Some(handle)
+}
+
+fn listen_endpoint(local: IpEndpoint) -> IpListenEndpoint {
+    IpListenEndpoint {
+        addr: if local.addr.is_unspecified() {
+            None
+        } else {
+            Some(local.addr)
+        },
+        port: local.port,
+    }
+}
+
+fn unspecified_endpoint(port: u16) -> IpEndpoint {
+    IpEndpoint::new(IpAddress::Ipv4(Ipv4Address::UNSPECIFIED), port)
+}
+
+impl TcpSocket {
+    pub fn new() -> Self {
+        Self::with_state(None, TcpState::Closed)
+    }
+
+    fn with_state(local: Option<IpEndpoint>, state: TcpState) -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(TcpInner {
+                    local,
+                    state,
+                    nodelay: false,
+                })
+            },
+        }
+    }
+
+    fn connection(&self) -> Option<(NetIface, SocketHandle)> {
+        match self.inner.exclusive_access().state {
+            TcpState::Connected(iface, handle) => Some((iface, handle)),
+            _ => None,
+        }
+    }
+}
+
+impl Socket for TcpSocket {
+    fn socket_type(&self) -> SocketType {
+        SocketType::Stream
+    }
+
+    fn bind(&self, addr: SockAddr) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        let mut local = match addr.inet() {
+            Some(local) => local,
+            None => return -1,
+        };
+        if inner.local.is_some() || !matches!(inner.state, TcpState::Closed) {
+            return -1;
+        }
+        if local.port == 0 {
+            local.port = alloc_ephemeral_port();
+        }
+        inner.local = Some(local);
+        0
+    }
+
+    fn listen(&self, backlog: usize) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        if !matches!(inner.state, TcpState::Closed) {
+            return -1;
+        }
+        let local = *inner
+            .local
+            .get_or_insert_with(|| unspecified_endpoint(alloc_ephemeral_port()));
+        let endpoint = listen_endpoint(local);
+        let backlog = backlog.clamp(1, MAX_BACKLOG);
+        let mut listeners = Vec::new();
+        for iface in NetIface::listen_on(endpoint.addr) {
+            for _ in 0..backlog {
+                match add_listener(iface, endpoint) {
+                    Some(handle) => listeners.push((iface, handle)),
+                    None => break,
+                }
+            }
+        }
+        if listeners.is_empty() {
+            return -1;
+        }
+        inner.state = TcpState::Listening(listeners);
+        0
+    }
+
+    fn accept(&self) -> Option<(Arc<dyn Socket>, SockAddr)> {
+        loop {
+            poll_interfaces();
+            let mut inner = self.inner.exclusive_access();
+            let local = inner.local?;
+            let listeners = match &mut inner.state {
+                TcpState::Listening(listeners) if !listeners.is_empty() => listeners,
+                _ => return None,
+            };
+            for i in 0..listeners.len() {
+                let (iface, handle) = listeners[i];
+                let peer = iface.with_socket::<tcp::Socket, _>(handle, |socket, _| {
+                    if socket.may_send() || socket.may_recv() {
+                        socket.remote_endpoint()
+                    } else {
+                        None
+                    }
+                });
+                if let Some(peer) = peer {
+                    // 换上新的监听 socket, 已连上的交给新的 TcpSocket.
+                    // 内存不足时队列少一个位置
+                    match add_listener(iface, listen_endpoint(local)) {
+                        Some(listener) => listeners[i] = (iface, listener),
+                        None => {
+                            listeners.remove(i);
+                        }
+                    }
+                    let local = iface.with_socket::<tcp::Socket, _>(handle, |socket, _| {
+                        socket.local_endpoint()
+                    });
+                    let socket = TcpSocket::with_state(local, TcpState::Connected(iface, handle));
+                    return Some((Arc::new(socket), SockAddr::Inet(peer)));
+                }
+            }
+            drop(inner);
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn connect(&self, addr: SockAddr) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        let remote = match addr.inet() {
+            Some(remote) => remote,
+            None => return -1,
+        };
+        if !matches!(inner.state, TcpState::Closed) {
+            return -1;
+        }
+        let iface = match NetIface::route(remote.addr) {
+            Some(iface) => iface,
+            None => return -1,
+        };
+        let local_port = match inner.local {
+            Some(local) => local.port,
+            None => alloc_ephemeral_port(),
+        };
+        let socket = match new_tcp_socket() {
+            Some(socket) => socket,
+            None => return -1,
+        };
+        let handle = iface.add_socket(socket);
+        let nodelay = inner.nodelay;
+        let result = iface.with_socket::<tcp::Socket, _>(handle, |socket, iface| {
+            socket.set_nagle_enabled(!nodelay);
+            socket.connect(iface.context(), remote, local_port)
+        });
+        if result.is_err() {
+            iface.remove_socket(handle);
+            return -1;
+        }
+        inner.state = TcpState::Connected(iface, handle);
+        drop(inner);
+        loop {
+            poll_interfaces();
+            let state = iface.with_socket::<tcp::Socket, _>(handle, |socket, _| socket.state());
+            match state {
+                tcp::State::SynSent | tcp::State::SynReceived => suspend_current_and_run_next(),
+                tcp::State::Closed => {
+                    // 对方拒绝连接
+                    iface.remove_socket(handle);
+                    self.inner.exclusive_access().state = TcpState::Closed;
+                    return -1;
+                }
+                _ => return 0,
+            }
+        }
+    }
+
+    fn sendto(&self, buf: &[u8], _addr: Option<SockAddr>) -> isize {
+        let (iface, handle) = match self.connection() {
+            Some(connection) => connection,
+            None => return -1,
+        };
+        let mut sent = 0;
+        while sent < buf.len() {
+            let result = iface.with_socket::<tcp::Socket, _>(handle, |socket, _| {
+                if socket.can_send() {
+                    Some(socket.send_slice(&buf[sent..]).map_err(|_| ()))
+                } else if !socket.may_send() {
+                    Some(Err(()))
+                } else {
+                    None
+                }
+            });
+            match result {
+                Some(Ok(len)) => sent += len,
+                Some(Err(())) if sent == 0 => return -1,
+                Some(Err(())) => break,
+                None => suspend_current_and_run_next(),
+            }
+            poll_interfaces();
+        }
+        sent as isize
+    }
+
+    fn recvfrom(&self, buf: &mut [u8]) -> (isize, Option<SockAddr>) {
+        let (iface, handle) = match self.connection() {
+            Some(connection) => connection,
+            None => return (-1, None),
+        };
+        loop {
+            poll_interfaces();
+            let result = iface.with_socket::<tcp::Socket, _>(handle, |socket, _| {
+                if socket.can_recv() {
+                    Some(socket.recv_slice(buf).unwrap_or(0))
+                } else if !socket.may_recv() {
+                    // 对方已经关闭
+                    Some(0)
+                } else {
+                    None
+                }
+            });
+            match result {
+                Some(len) => return (len as isize, self.peer_addr()),
+                None => suspend_current_and_run_next(),
+            }
+        }
+    }
+
+    fn local_addr(&self) -> Option<SockAddr> {
+        match self.connection() {
+            Some((iface, handle)) => iface
+                .with_socket::<tcp::Socket, _>(handle, |socket, _| socket.local_endpoint())
+                .map(SockAddr::Inet),
+            None => Some(SockAddr::Inet(
+                self.inner
+                    .exclusive_access()
+                    .local
+                    .unwrap_or(unspecified_endpoint(0)),
+            )),
+        }
+    }
+
+    fn peer_addr(&self) -> Option<SockAddr> {
+        let (iface, handle) = self.connection()?;
+        iface
+            .with_socket::<tcp::Socket, _>(handle, |socket, _| socket.remote_endpoint())
+            .map(SockAddr::Inet)
+    }
+
+    fn shutdown(&self, how: usize) -> isize {
+        let (iface, handle) = match self.connection() {
+            Some(connection) => connection,
+            None => return -1,
+        };
+        // smoltcp 不支持只关闭读方向
+        if how != SHUT_RD {
+            iface.with_socket::<tcp::Socket, _>(handle, |socket, _| socket.close());
+            poll_interfaces();
+        }
+        0
+    }
+
+    fn setsockopt(&self, level: usize, name: usize, value: u32) -> isize {
+        if (level, name) != (IPPROTO_TCP, TCP_NODELAY) {
+            return sol_socket_setsockopt(level, name);
+        }
+        let nodelay = value != 0;
+        self.inner.exclusive_access().nodelay = nodelay;
+        if let Some((iface, handle)) = self.connection() {
+            iface.with_socket::<tcp::Socket, _>(handle, |socket, _| {
+                socket.set_nagle_enabled(!nodelay)
+            });
+        }
+        0
+    }
+
+    fn getsockopt(&self, level: usize, name: usize) -> Option<u32> {
+        if (level, name) == (IPPROTO_TCP, TCP_NODELAY) {
+            return Some(self.inner.exclusive_access().nodelay as u32);
+        }
+        sol_socket_getsockopt(SocketType::Stream, level, name)
+    }
+}
+
+impl Drop for TcpSocket {
+    fn drop(&mut self) {
+        match &self.inner.exclusive_access().state {
+            TcpState::Closed => {}
+            TcpState::Listening(listeners) => {
+                for &(iface, handle) in listeners {
+                    iface.remove_socket(handle);
+                }
+            }
+            &TcpState::Connected(iface, handle) => {
+                iface.close_tcp(handle);
+                poll_interfaces();
+            }
+        }
+    }
+}
+
+impl File for TcpSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        socket_read(self, user_buf)
+    }
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        socket_write(self, user_buf)
+    }
+    fn name(&self) -> String {
+        "socket".to_string()
+    }
+    fn kstat(&self, stat: &mut Kstat) {
+        socket_kstat(self, stat);
+    }
+}
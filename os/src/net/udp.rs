@@ -0,0 +1,252 @@
+//! AF_INET 数据报 socket
+use super::socket::{
+    alloc_socket_buffer, socket_kstat, socket_read, socket_write, SockAddr, Socket, SocketType,
+};
+use super::{alloc_ephemeral_port, poll_interfaces, NetIface};
+use crate::fs::{File, Kstat};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::suspend_current_and_run_next;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::udp;
+use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint, Ipv4Address};
+
+// 接收队列中最多缓存的数据报个数
+const UDP_PACKET_COUNT: usize = 32;
+
+struct UdpInner {
+    local: Option<IpEndpoint>,
+    /// connect 设置的默认目的地址
+    peer: Option<IpEndpoint>,
+    /// 每个接口上绑定同一端口的 smoltcp socket
+    handles: Vec<(NetIface, SocketHandle)>,
+}
+
+pub struct UdpSocket {
+    inner: UPSafeCell<UdpInner>,
+}
+
+/// 内核堆不够时返回 None
+fn new_udp_socket() -> Option<udp::Socket<'static>> {
+    Some(udp::Socket::new(
+        udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY; UDP_PACKET_COUNT],
+            alloc_socket_buffer()?,
+        ),
+        udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY; UDP_PACKET_COUNT],
+            alloc_socket_buffer()?,
+        ),
+    ))
+}
+
+impl UdpSocket {
+    pub fn new() -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(UdpInner {
+                    local: None,
+                    peer: None,
+                    handles: Vec::new(),
+                })
+            },
+        }
+    }
+
+    fn bind_inner(inner: &mut UdpInner, mut local: IpEndpoint) -> isize {
+        if !inner.handles.is_empty() {
+            return -1;
+        }
+        if local.port == 0 {
+            local.port = alloc_ephemeral_port();
+        }
+        let endpoint = IpListenEndpoint {
+            addr: if local.addr.is_unspecified() {
+                None
+            } else {
+                Some(local.addr)
+            },
+            port: local.port,
+        };
+        for iface in NetIface::listen_on(endpoint.addr) {
+            let socket = match new_udp_socket() {
+                Some(socket) => socket,
+                None => {
+                    for (iface, handle) in inner.handles.drain(..) {
+                        iface.remove_socket(handle);
+                    }
+                    return -1;
+                }
+            };
+            let handle = iface.add_socket(socket);
+            iface
+                .with_socket::<udp::Socket, _>(handle, |socket, _| socket.bind(endpoint))
+                .unwrap();
+            inner.handles.push((iface, handle));
+        }
+        inner.local = Some(local);
+        0
+    }
+
+    /// 没有 bind 就收发时自动绑定到任意地址的临时端口, 失败时返回 -1
+    fn ensure_bound(inner: &mut UdpInner) -> isize {
+        if !inner.handles.is_empty() {
+            return 0;
+        }
+        let local = IpEndpoint::new(IpAddress::Ipv4(Ipv4Address::UNSPECIFIED), 0);
+        Self::bind_inner(inner, local)
+    }
+}
+
+impl Socket for UdpSocket {
+    fn socket_type(&self) -> SocketType {
+        SocketType::Datagram
+    }
+
+    fn bind(&self, addr: SockAddr) -> isize {
+        match addr.inet() {
+            Some(local) => Self::bind_inner(&mut self.inner.exclusive_access(), local),
+            None => -1,
+        }
+    }
+
+    fn listen(&self, _backlog: usize) -> isize {
+        -1
+    }
+
+    fn accept(&self) -> Option<(Arc<dyn Socket>, SockAddr)> {
+        None
+    }
+
+    fn connect(&self, addr: SockAddr) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        match addr.inet() {
+            Some(peer) => {
+                if Self::ensure_bound(&mut inner) < 0 {
+                    return -1;
+                }
+                inner.peer = Some(peer);
+                0
+            }
+            None => -1,
+        }
+    }
+
+    fn sendto(&self, buf: &[u8], addr: Option<SockAddr>) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        let remote = match addr.and_then(|addr| addr.inet()).or(inner.peer) {
+            Some(remote) => remote,
+            None => return -1,
+        };
+        if Self::ensure_bound(&mut inner) < 0 {
+            return -1;
+        }
+        let handle = NetIface::route(remote.addr).and_then(|iface| {
+            inner
+                .handles
+                .iter()
+                .find(|&&(bound, _)| bound == iface)
+                .copied()
+        });
+        drop(inner);
+        let (iface, handle) = match handle {
+            Some(handle) => handle,
+            None => return -1,
+        };
+        loop {
+            let result = iface.with_socket::<udp::Socket, _>(handle, |socket, _| {
+                if socket.can_send() {
+                    Some(socket.send_slice(buf, remote).is_ok())
+                } else {
+                    None
+                }
+            });
+            match result {
+                Some(true) => break,
+                Some(false) => return -1,
+                None => {
+                    poll_interfaces();
+                    suspend_current_and_run_next();
+                }
+            }
+        }
+        poll_interfaces();
+        buf.len() as isize
+    }
+
+    fn recvfrom(&self, buf: &mut [u8]) -> (isize, Option<SockAddr>) {
+        let handles = {
+            let mut inner = self.inner.exclusive_access();
+            if Self::ensure_bound(&mut inner) < 0 {
+                return (-1, None);
+            }
+            inner.handles.clone()
+        };
+        loop {
+            poll_interfaces();
+            for &(iface, handle) in handles.iter() {
+                let result = iface.with_socket::<udp::Socket, _>(handle, |socket, _| {
+                    // 缓冲区不够时截断数据报, 多出的部分丢弃
+                    socket.recv().ok().map(|(data, meta)| {
+                        let len = data.len().min(buf.len());
+                        buf[..len].copy_from_slice(&data[..len]);
+                        (len, meta.endpoint)
+                    })
+                });
+                if let Some((len, remote)) = result {
+                    return (len as isize, Some(SockAddr::Inet(remote)));
+                }
+            }
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn local_addr(&self) -> Option<SockAddr> {
+        let local = self.inner.exclusive_access().local;
+        Some(SockAddr::Inet(local.unwrap_or(IpEndpoint::new(
+            IpAddress::Ipv4(Ipv4Address::UNSPECIFIED),
+            0,
+        ))))
+    }
+
+    fn peer_addr(&self) -> Option<SockAddr> {
+        self.inner.exclusive_access().peer.map(SockAddr::Inet)
+    }
+
+    fn shutdown(&self, _how: usize) -> isize {
+        0
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        for &(iface, handle) in self.inner.exclusive_access().handles.iter() {
+            iface.remove_socket(handle);
+        }
+    }
+}
+
+impl File for UdpSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        socket_read(self, user_buf)
+    }
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        socket_write(self, user_buf)
+    }
+    fn name(&self) -> String {
+        "socket".to_string()
+    }
+    fn kstat(&self, stat: &mut Kstat) {
+        socket_kstat(self, stat);
+    }
+}
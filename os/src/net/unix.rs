@@ -0,0 +1,287 @@
+//! AF_UNIX 流式 socket. 地址只登记在内核的名字表中, 不会在文件系统里创建文件
+use super::socket::{
+    socket_kstat, socket_read, socket_write, SockAddr, Socket, SocketType, SOCKET_BUFFER_SIZE,
+};
+use crate::fs::{File, Kstat};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::suspend_current_and_run_next;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use lazy_static::*;
+
+const SHUT_RD: usize = 0;
+const SHUT_WR: usize = 1;
+
+/// 单向的字节流, 连接的两端各持有一读一写两个
+struct Channel {
+    data: VecDeque<u8>,
+    reader_closed: bool,
+    writer_closed: bool,
+}
+
+type ChannelRef = Arc<UPSafeCell<Channel>>;
+
+fn new_channel() -> ChannelRef {
+    Arc::new(unsafe {
+        UPSafeCell::new(Channel {
+            data: VecDeque::new(),
+            reader_closed: false,
+            writer_closed: false,
+        })
+    })
+}
+
+/// 等待 accept 的连接, 名字表中保存它的 Weak
+type BacklogQueue = UPSafeCell<VecDeque<Arc<UnixSocket>>>;
+type Backlog = Arc<BacklogQueue>;
+
+enum UnixState {
+    Unconnected,
+    Listening(Backlog),
+    Connected { rx: ChannelRef, tx: ChannelRef },
+}
+
+struct UnixInner {
+    path: Option<String>,
+    peer_path: Option<String>,
+    state: UnixState,
+}
+
+pub struct UnixSocket {
+    inner: UPSafeCell<UnixInner>,
+}
+
+lazy_static! {
+    /// 正在监听的 socket, 按路径查找
+    static ref UNIX_LISTENERS: UPSafeCell<BTreeMap<String, Weak<BacklogQueue>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+impl UnixSocket {
+    pub fn new() -> Self {
+        Self::with_state(None, None, UnixState::Unconnected)
+    }
+
+    fn with_state(path: Option<String>, peer_path: Option<String>, state: UnixState) -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(UnixInner {
+                    path,
+                    peer_path,
+                    state,
+                })
+            },
+        }
+    }
+
+    fn channels(&self) -> Option<(ChannelRef, ChannelRef)> {
+        match &self.inner.exclusive_access().state {
+            UnixState::Connected { rx, tx } => Some((rx.clone(), tx.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl Socket for UnixSocket {
+    fn socket_type(&self) -> SocketType {
+        SocketType::Stream
+    }
+
+    fn bind(&self, addr: SockAddr) -> isize {
+        let path = match addr {
+            SockAddr::Unix(path) => path,
+            SockAddr::Inet(_) => return -1,
+        };
+        let mut inner = self.inner.exclusive_access();
+        let mut listeners = UNIX_LISTENERS.exclusive_access();
+        // 已关闭的 socket 留下的名字可以重新使用
+        if inner.path.is_some()
+            || listeners
+                .get(&path)
+                .map_or(false, |listener| listener.strong_count() > 0)
+        {
+            return -1;
+        }
+        listeners.remove(&path);
+        inner.path = Some(path);
+        0
+    }
+
+    fn listen(&self, _backlog: usize) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        let path = match &inner.path {
+            Some(path) => path.clone(),
+            None => return -1,
+        };
+        match inner.state {
+            UnixState::Unconnected => {}
+            UnixState::Listening(_) => return 0,
+            UnixState::Connected { .. } => return -1,
+        }
+        let backlog: Backlog = Arc::new(unsafe { UPSafeCell::new(VecDeque::new()) });
+        UNIX_LISTENERS
+            .exclusive_access()
+            .insert(path, Arc::downgrade(&backlog));
+        inner.state = UnixState::Listening(backlog);
+        0
+    }
+
+    fn accept(&self) -> Option<(Arc<dyn Socket>, SockAddr)> {
+        let backlog = match &self.inner.exclusive_access().state {
+            UnixState::Listening(backlog) => backlog.clone(),
+            _ => return None,
+        };
+        loop {
+            let socket = backlog.exclusive_access().pop_front();
+            if let Some(socket) = socket {
+                let peer = socket
+                    .inner
+                    .exclusive_access()
+                    .peer_path
+                    .clone()
+                    .unwrap_or_default();
+                return Some((socket, SockAddr::Unix(peer)));
+            }
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn connect(&self, addr: SockAddr) -> isize {
+        let path = match addr {
+            SockAddr::Unix(path) => path,
+            SockAddr::Inet(_) => return -1,
+        };
+        let mut inner = self.inner.exclusive_access();
+        if !matches!(inner.state, UnixState::Unconnected) {
+            return -1;
+        }
+        let backlog = match UNIX_LISTENERS
+            .exclusive_access()
+            .get(&path)
+            .and_then(|backlog| backlog.upgrade())
+        {
+            Some(backlog) => backlog,
+            None => return -1,
+        };
+        let to_server = new_channel();
+        let to_client = new_channel();
+        let server = UnixSocket::with_state(
+            Some(path.clone()),
+            inner.path.clone(),
+            UnixState::Connected {
+                rx: to_server.clone(),
+                tx: to_client.clone(),
+            },
+        );
+        backlog.exclusive_access().push_back(Arc::new(server));
+        inner.peer_path = Some(path);
+        inner.state = UnixState::Connected {
+            rx: to_client,
+            tx: to_server,
+        };
+        0
+    }
+
+    fn sendto(&self, buf: &[u8], _addr: Option<SockAddr>) -> isize {
+        let (_, tx) = match self.channels() {
+            Some(channels) => channels,
+            None => return -1,
+        };
+        let mut sent = 0;
+        while sent < buf.len() {
+            let mut channel = tx.exclusive_access();
+            if channel.reader_closed || channel.writer_closed {
+                return if sent == 0 { -1 } else { sent as isize };
+            }
+            let len = (SOCKET_BUFFER_SIZE - channel.data.len()).min(buf.len() - sent);
+            if len == 0 {
+                drop(channel);
+                suspend_current_and_run_next();
+                continue;
+            }
+            channel.data.extend(&buf[sent..sent + len]);
+            sent += len;
+        }
+        sent as isize
+    }
+
+    fn recvfrom(&self, buf: &mut [u8]) -> (isize, Option<SockAddr>) {
+        let (rx, _) = match self.channels() {
+            Some(channels) => channels,
+            None => return (-1, None),
+        };
+        loop {
+            let mut channel = rx.exclusive_access();
+            if !channel.data.is_empty() {
+                let len = buf.len().min(channel.data.len());
+                for (dst, src) in buf.iter_mut().zip(channel.data.drain(..len)) {
+                    *dst = src;
+                }
+                return (len as isize, self.peer_addr());
+            }
+            if channel.writer_closed || channel.reader_closed {
+                return (0, self.peer_addr());
+            }
+            drop(channel);
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn local_addr(&self) -> Option<SockAddr> {
+        self.inner.exclusive_access().path.clone().map(SockAddr::Unix)
+    }
+
+    fn peer_addr(&self) -> Option<SockAddr> {
+        self.inner
+            .exclusive_access()
+            .peer_path
+            .clone()
+            .map(SockAddr::Unix)
+    }
+
+    fn shutdown(&self, how: usize) -> isize {
+        let (rx, tx) = match self.channels() {
+            Some(channels) => channels,
+            None => return -1,
+        };
+        if how != SHUT_WR {
+            rx.exclusive_access().reader_closed = true;
+        }
+        if how != SHUT_RD {
+            tx.exclusive_access().writer_closed = true;
+        }
+        0
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        if let Some((rx, tx)) = self.channels() {
+            rx.exclusive_access().reader_closed = true;
+            tx.exclusive_access().writer_closed = true;
+        }
+    }
+}
+
+impl File for UnixSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        socket_read(self, user_buf)
+    }
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        socket_write(self, user_buf)
+    }
+    fn name(&self) -> String {
+        "socket".to_string()
+    }
+    fn kstat(&self, stat: &mut Kstat) {
+        socket_kstat(self, stat);
+    }
+}
@@ -1,15 +1,3 @@
-/// use sbi call to putchar in console (qemu uart handler)
-pub fn console_putchar(c: usize) {
-    #[allow(deprecated)]
-    sbi_rt::legacy::console_putchar(c);
-}
-
-/// use sbi call to getchar from console (qemu uart handler)
-pub fn console_getchar() -> usize {
-    #[allow(deprecated)]
-    sbi_rt::legacy::console_getchar()
-}
-
 /// use sbi call to set timer
 pub fn set_timer(timer: usize) {
     sbi_rt::set_timer(timer as _);
@@ -25,3 +13,13 @@ pub fn shutdown(failure: bool) -> ! {
     }
     unreachable!()
 }
+
+/// use sbi call to start another hart at `start_addr`, `opaque` is passed in a1
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> bool {
+    sbi_rt::hart_start(hartid, start_addr, opaque).error == 0
+}
+
+/// use sbi call to flush the TLB of the harts in `hart_mask`
+pub fn remote_sfence_vma(hart_mask: usize, start: usize, size: usize) {
+    sbi_rt::remote_sfence_vma(hart_mask, 0, start, size);
+}
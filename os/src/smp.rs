@@ -0,0 +1,49 @@
+//! 多核启动: 启动 hart 完成初始化后, 通过 SBI HSM 扩展唤醒其余 hart
+//!
+//! 每个 hart 在 tp 寄存器中保存自己的 hart id, 进入用户态时 tp 保存在 TrapContext 中
+use crate::config::MAX_HARTS;
+use crate::fdt::MACHINE;
+use crate::sbi;
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// 已经进入调度循环的 hart, 按位表示
+static ONLINE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn hart_id() -> usize {
+    let id: usize;
+    unsafe { asm!("mv {}, tp", out(reg) id) };
+    id
+}
+
+pub fn online_harts() -> usize {
+    ONLINE_HARTS.load(Ordering::Acquire)
+}
+
+pub fn set_online(hart_id: usize) {
+    ONLINE_HARTS.fetch_or(1 << hart_id, Ordering::AcqRel);
+}
+
+/// 由启动 hart 调用, hart id 不小于 MAX_HARTS 的 hart 不会被启动
+pub fn start_secondary_harts(boot_hart: usize) {
+    extern "C" {
+        fn _start_secondary();
+    }
+    for &hart in MACHINE.harts.iter() {
+        if hart == boot_hart || hart >= MAX_HARTS {
+            continue;
+        }
+        if !sbi::hart_start(hart, _start_secondary as usize, 0) {
+            println!("[kernel] failed to start hart {}", hart);
+        }
+    }
+}
+
+/// 让其他 hart 上缓存的地址转换失效, 在删除映射之后调用
+pub fn tlb_shootdown(start: usize, size: usize) {
+    let others = online_harts() & !(1 << hart_id());
+    if others != 0 {
+        sbi::remote_sfence_vma(others, start, size);
+    }
+    unsafe { asm!("sfence.vma") };
+}
@@ -0,0 +1,44 @@
+//! 按 hart 记录关中断的嵌套层数, 最外层退出时才恢复进入时的中断状态,
+//! 锁的释放顺序不必与获取顺序相反
+use crate::config::MAX_HARTS;
+use crate::smp::hart_id;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use riscv::register::sstatus;
+
+struct IrqState {
+    depth: AtomicUsize,
+    /// 最外层关中断之前是否开着中断
+    enabled: AtomicBool,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const IRQ_STATE_INIT: IrqState = IrqState {
+    depth: AtomicUsize::new(0),
+    enabled: AtomicBool::new(false),
+};
+
+static IRQ_STATE: [IrqState; MAX_HARTS] = [IRQ_STATE_INIT; MAX_HARTS];
+
+pub fn push_off() {
+    let enabled = sstatus::read().sie();
+    unsafe { sstatus::clear_sie() };
+    let state = &IRQ_STATE[hart_id()];
+    if state.depth.load(Ordering::Relaxed) == 0 {
+        state.enabled.store(enabled, Ordering::Relaxed);
+    }
+    state.depth.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn pop_off() {
+    let state = &IRQ_STATE[hart_id()];
+    let depth = state.depth.fetch_sub(1, Ordering::Relaxed);
+    assert!(depth > 0, "pop_off without push_off");
+    if depth == 1 && state.enabled.load(Ordering::Relaxed) {
+        unsafe { sstatus::set_sie() };
+    }
+}
+
+/// 本 hart 上还没有释放的 `push_off` 层数, 不为 0 时不能切换任务
+pub fn irq_off_depth() -> usize {
+    IRQ_STATE[hart_id()].depth.load(Ordering::Relaxed)
+}
@@ -0,0 +1,125 @@
+#[cfg(feature = "lockdep")]
+use super::lockdep;
+use super::SpinNoIrq;
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+
+/// 内核中使用的睡眠锁, 锁被占用时阻塞当前任务, 持有期间可以阻塞或切换任务
+///
+/// 不能在中断处理中使用. 没有当前任务时 (启动阶段) 退化为自旋
+#[repr(C)]
+pub struct KMutex<T> {
+    inner: SpinNoIrq<KMutexInner>,
+    /// 排在 inner 之后, lockdep 用它的地址标识这把锁, 与 inner 区分开
+    data: UnsafeCell<T>,
+}
+
+struct KMutexInner {
+    locked: bool,
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+// 与 SpinNoIrq 相同, 由使用者保证数据可以在 hart 之间共享
+unsafe impl<T> Sync for KMutex<T> {}
+unsafe impl<T> Send for KMutex<T> {}
+
+pub struct KMutexGuard<'a, T> {
+    mutex: &'a KMutex<T>,
+}
+
+impl<T> KMutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            inner: SpinNoIrq::new(KMutexInner {
+                locked: false,
+                wait_queue: VecDeque::new(),
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[cfg(feature = "lockdep")]
+    fn lockdep_id(&self) -> usize {
+        self.data.get() as usize
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> KMutexGuard<'_, T> {
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(self.lockdep_id(), core::panic::Location::caller());
+        loop {
+            let mut inner = self.inner.lock();
+            if !inner.locked {
+                inner.locked = true;
+                break;
+            }
+            match current_task() {
+                Some(task) => {
+                    inner.wait_queue.push_back(task);
+                    drop(inner);
+                    block_current_and_run_next();
+                    // 解锁时锁直接交给被唤醒的任务, 醒来时已经持有
+                    break;
+                }
+                None => {
+                    drop(inner);
+                    spin_loop();
+                }
+            }
+        }
+        KMutexGuard { mutex: self }
+    }
+
+    /// 锁被占用时返回 None, 不会阻塞
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<KMutexGuard<'_, T>> {
+        let mut inner = self.inner.lock();
+        if inner.locked {
+            return None;
+        }
+        inner.locked = true;
+        drop(inner);
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(self.lockdep_id(), core::panic::Location::caller());
+        Some(KMutexGuard { mutex: self })
+    }
+}
+
+#[cfg(feature = "lockdep")]
+impl<T> Drop for KMutex<T> {
+    fn drop(&mut self) {
+        lockdep::forget(self.lockdep_id());
+    }
+}
+
+impl<T> Deref for KMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for KMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for KMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.mutex.lockdep_id());
+        let mut inner = self.mutex.inner.lock();
+        match inner.wait_queue.pop_front() {
+            Some(task) => {
+                drop(inner);
+                wakeup_task(task);
+            }
+            None => inner.locked = false,
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! 简化的 lockdep, 只在启用 `lockdep` feature 时编译
+//!
+//! 以锁的地址区分不同的锁, 记录 "持有 A 时获取 B" 的顺序. 某次获取与已有的顺序
+//! 构成环 (可能死锁), 或者在同一个上下文中重复获取同一把锁时 panic.
+//! 上下文是当前任务, 没有任务时 (启动阶段、空闲循环、中断处理) 是当前 hart.
+use crate::smp::hart_id;
+use crate::task::try_current_task;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, Ordering};
+use riscv::register::sstatus;
+
+type Caller = &'static Location<'static>;
+
+struct LockDep {
+    /// 各上下文持有的锁, 按获取的顺序
+    held: BTreeMap<usize, Vec<(usize, Caller)>>,
+    /// edges[a][b] 是第一次在持有 a 时获取 b 的位置
+    edges: BTreeMap<usize, BTreeMap<usize, Caller>>,
+}
+
+/// 记录本身不能再用被检查的锁保护
+struct RawLock {
+    locked: AtomicBool,
+    state: UnsafeCell<LockDep>,
+}
+
+unsafe impl Sync for RawLock {}
+
+static LOCKDEP: RawLock = RawLock {
+    locked: AtomicBool::new(false),
+    state: UnsafeCell::new(LockDep {
+        held: BTreeMap::new(),
+        edges: BTreeMap::new(),
+    }),
+};
+
+fn with_lockdep<R>(f: impl FnOnce(&mut LockDep) -> R) -> R {
+    let irq_enabled = sstatus::read().sie();
+    unsafe { sstatus::clear_sie() };
+    while LOCKDEP
+        .locked
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        spin_loop();
+    }
+    let ret = f(unsafe { &mut *LOCKDEP.state.get() });
+    LOCKDEP.locked.store(false, Ordering::Release);
+    if irq_enabled {
+        unsafe { sstatus::set_sie() };
+    }
+    ret
+}
+
+fn context() -> usize {
+    // 任务地址在内核堆中, 不会与 hart id 重复
+    match try_current_task() {
+        Some(task) => Arc::as_ptr(&task) as usize,
+        None => hart_id(),
+    }
+}
+
+impl LockDep {
+    /// 从 from 出发沿已记录的顺序能否到达 to
+    fn reachable(&self, from: usize, to: usize) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut stack = Vec::from([from]);
+        while let Some(lock) = stack.pop() {
+            if lock == to {
+                return true;
+            }
+            if !visited.insert(lock) {
+                continue;
+            }
+            if let Some(next) = self.edges.get(&lock) {
+                stack.extend(next.keys());
+            }
+        }
+        false
+    }
+}
+
+/// 在真正获取锁 (可能自旋或阻塞) 之前调用
+pub fn acquire(lock: usize, caller: Caller) {
+    let ctx = context();
+    let violation = with_lockdep(|dep| {
+        let held = dep.held.get(&ctx).cloned().unwrap_or_default();
+        for &(prev, prev_caller) in held.iter() {
+            if prev == lock {
+                return Some(("recursive locking", prev_caller, None));
+            }
+            if dep.reachable(lock, prev) {
+                let reverse = dep
+                    .edges
+                    .get(&lock)
+                    .and_then(|next| next.get(&prev))
+                    .copied();
+                return Some(("lock order inversion", prev_caller, reverse));
+            }
+        }
+        for &(prev, _) in held.iter() {
+            dep.edges
+                .entry(prev)
+                .or_default()
+                .entry(lock)
+                .or_insert(caller);
+        }
+        dep.held.entry(ctx).or_default().push((lock, caller));
+        None
+    });
+    if let Some((reason, prev_caller, reverse)) = violation {
+        println!("[lockdep] {}: acquiring {:#x} at {}", reason, lock, caller);
+        println!("[lockdep] while holding a lock taken at {}", prev_caller);
+        if let Some(reverse) = reverse {
+            println!("[lockdep] the opposite order was seen at {}", reverse);
+        }
+        panic!("lockdep: {}", reason);
+    }
+}
+
+/// 释放锁之后调用, 锁不一定按获取的相反顺序释放
+pub fn release(lock: usize) {
+    let ctx = context();
+    with_lockdep(|dep| {
+        if let Some(held) = dep.held.get_mut(&ctx) {
+            if let Some(pos) = held.iter().rposition(|&(held_lock, _)| held_lock == lock) {
+                held.remove(pos);
+            }
+            if held.is_empty() {
+                dep.held.remove(&ctx);
+            }
+        }
+    });
+}
+
+/// 锁被销毁后它的地址可能被另一把锁重用, 清除相关的顺序
+pub fn forget(lock: usize) {
+    with_lockdep(|dep| {
+        dep.edges.remove(&lock);
+        for next in dep.edges.values_mut() {
+            next.remove(&lock);
+        }
+    });
+}
@@ -1,9 +1,17 @@
 mod condvar;
+mod irq;
+mod kmutex;
+#[cfg(feature = "lockdep")]
+mod lockdep;
 mod mutex;
 mod semaphore;
+mod spin;
 mod up;
 
 pub use condvar::Condvar;
+pub use irq::irq_off_depth;
+pub use kmutex::{KMutex, KMutexGuard};
 pub use mutex::{Mutex, MutexBlocking, MutexSpin};
 pub use semaphore::Semaphore;
-pub use up::UPSafeCell;
+pub use spin::{SpinNoIrq, SpinNoIrqGuard};
+pub use up::{UPRefMut, UPSafeCell};
@@ -0,0 +1,97 @@
+use super::irq::{pop_off, push_off};
+#[cfg(feature = "lockdep")]
+use super::lockdep;
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// 持有期间关闭本 hart 中断的自旋锁, 用于多个 hart 共享、中断处理中也会访问的数据
+///
+/// 持有锁时不能切换任务, 否则其他任务再获取同一把锁会一直自旋;
+/// 需要在持有期间阻塞的场合使用 `KMutex`
+pub struct SpinNoIrq<T> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+// 与 UPSafeCell 一样不要求 T: Send, 由使用者保证数据可以在 hart 之间共享
+unsafe impl<T> Sync for SpinNoIrq<T> {}
+unsafe impl<T> Send for SpinNoIrq<T> {}
+
+pub struct SpinNoIrqGuard<'a, T> {
+    lock: &'a SpinNoIrq<T>,
+}
+
+impl<T> SpinNoIrq<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> SpinNoIrqGuard<'_, T> {
+        push_off();
+        // 在自旋之前检查, 死锁时报告而不是卡住
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(self as *const _ as usize, core::panic::Location::caller());
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+        }
+        SpinNoIrqGuard { lock: self }
+    }
+
+    /// 锁已被持有时返回 None
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<SpinNoIrqGuard<'_, T>> {
+        push_off();
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            pop_off();
+            return None;
+        }
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(self as *const _ as usize, core::panic::Location::caller());
+        Some(SpinNoIrqGuard { lock: self })
+    }
+}
+
+#[cfg(feature = "lockdep")]
+impl<T> Drop for SpinNoIrq<T> {
+    fn drop(&mut self) {
+        lockdep::forget(self as *const _ as usize);
+    }
+}
+
+impl<T> Deref for SpinNoIrqGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinNoIrqGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinNoIrqGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.lock as *const _ as usize);
+        pop_off();
+    }
+}
@@ -1,29 +1,97 @@
+use super::irq::{pop_off, push_off};
+use crate::smp::hart_id;
 use core::cell::{RefCell, RefMut};
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const NO_OWNER: usize = usize::MAX;
 
 /// Wrap a static data structure inside it so that we are
 /// able to access it without any `unsafe`.
 ///
-/// We should only use it in uniprocessor.
+/// 不同 hart 之间互斥等待, 同一个 hart 上重复借用与 `RefCell` 一样会 panic,
+/// 便于发现忘记释放借用的错误. 借用期间关闭本 hart 的中断, 因此不会被抢占,
+/// 中断处理也不会遇到被打断的代码正借用着的数据.
 ///
 /// In order to get mutable reference of inner data, call
 /// `exclusive_access`.
 pub struct UPSafeCell<T> {
-    /// inner data
-    pub inner: RefCell<T>,
+    /// 持有借用的 hart
+    owner: AtomicUsize,
+    inner: RefCell<T>,
 }
 
 unsafe impl<T> Sync for UPSafeCell<T> {}
 
+pub struct UPRefMut<'a, T> {
+    cell: &'a UPSafeCell<T>,
+    inner: Option<RefMut<'a, T>>,
+}
+
 impl<T> UPSafeCell<T> {
-    /// User is responsible to guarantee that inner struct is only used in
-    /// uniprocessor.
+    /// User is responsible to guarantee that inner struct is only
+    /// shared by the harts through this cell.
     pub unsafe fn new(value: T) -> Self {
         Self {
+            owner: AtomicUsize::new(NO_OWNER),
             inner: RefCell::new(value),
         }
     }
-    /// Panic if the data has been borrowed.
-    pub fn exclusive_access(&self) -> RefMut<'_, T> {
-        self.inner.borrow_mut()
+    /// Panic if the data has been borrowed on this hart.
+    pub fn exclusive_access(&self) -> UPRefMut<'_, T> {
+        push_off();
+        let hart = hart_id();
+        while let Err(owner) =
+            self.owner
+                .compare_exchange_weak(NO_OWNER, hart, Ordering::Acquire, Ordering::Relaxed)
+        {
+            if owner == hart {
+                // 交给 RefCell 报告重复借用
+                break;
+            }
+            spin_loop();
+        }
+        UPRefMut {
+            cell: self,
+            inner: Some(self.inner.borrow_mut()),
+        }
+    }
+    /// 已被借用 (无论在哪个 hart 上) 时返回 None
+    pub fn try_exclusive_access(&self) -> Option<UPRefMut<'_, T>> {
+        push_off();
+        if self
+            .owner
+            .compare_exchange(NO_OWNER, hart_id(), Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            pop_off();
+            return None;
+        }
+        Some(UPRefMut {
+            cell: self,
+            inner: Some(self.inner.borrow_mut()),
+        })
+    }
+}
+
+impl<T> Deref for UPRefMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for UPRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.inner.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for UPRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.inner.take();
+        self.cell.owner.store(NO_OWNER, Ordering::Release);
+        pop_off();
     }
 }
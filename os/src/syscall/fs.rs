@@ -1,10 +1,17 @@
 use alloc::string::ToString;
-use crate::fs::{File, FileDescriptor, make_pipe, open_file, OpenFlags};
-use crate::mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer};
+use crate::config::MAX_FD;
+use crate::fatfs::sdcard::sync_all;
+use crate::fs::{
+    absolute_path, is_dir, make_pipe, open_device, open_file, open_initramfs, root, File,
+    FileDescriptor, Kstat, OpenFlags, Statfs,
+};
+use crate::mm::{
+    translated_byte_buffer, translated_ref, translated_refmut, translated_str, UserBuffer,
+};
+use crate::timer::{get_realtime_ns, TimeSpec};
 use crate::task::{current_process, current_user_token};
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    if fd != 1 {println!("write fd {}",fd);}
     let token = current_user_token();
     let process = current_process();
     let inner = process.inner_exclusive_access();
@@ -45,41 +52,71 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
 }
 
 pub fn sys_open(fd:isize, path: *const u8, flags: u32) -> isize {
-    print!("");
     let process = current_process();
     let token = current_user_token();
     let mut inner = process.inner_exclusive_access();
-    let path = translated_str(token, path).replace("./", "");
-    let flag = OpenFlags::from_bits(flags).unwrap();
+    let path = translated_str(token, path);
+    // AT_FDCWD: 相对于当前目录
+    let path = if fd < 0 {
+        absolute_path(&inner.cwd, &path)
+    } else {
+        path.replace("./", "")
+    };
+    let flag = OpenFlags::from_bits_truncate(flags);
     let (readable, writable) = flag.read_write();
+    // AT_FDCWD 时 path 已经是绝对路径, 从根目录查找
     let dir = if fd >= 0 {
-        let fd = fd as usize;
-        if let Some(dir) = inner.fd_table.get(fd).unwrap() {
-            dir
-        } else {
-            return -1;
+        match inner.fd_table.get(fd as usize) {
+            Some(Some(dir)) => dir.clone(),
+            _ => return -1,
         }
     } else {
-        &inner.work_dir.as_ref()
-    };
-    //打开当前
-    if path == ".".to_string() {
-        let tmp = dir.clone();
-        drop(dir);
-        let fd = inner.alloc_fd();
-        inner.fd_table.insert(fd, Some(tmp));
+        FileDescriptor::File(root())
+    };
+    //打开 dirfd 本身
+    if fd >= 0 && path == "." {
+        let fd = match inner.alloc_fd() {
+            Some(fd) => fd,
+            None => return -1,
+        };
+        inner.fd_table[fd] = Some(dir);
+        return fd as isize;
+    }
+    if let Some(device) = open_device(&path, flag) {
+        let fd = match inner.alloc_fd() {
+            Some(fd) => fd,
+            None => return -1,
+        };
+        inner.fd_table[fd] = Some(FileDescriptor::Abstract(device));
         return fd as isize;
     }
-    let flag = OpenFlags::from_bits(flags).unwrap();
     let file = if flag.contains(OpenFlags::CREATE) {
-        dir.create(&path, readable, writable, false)
+        let file = if flag.contains(OpenFlags::EXCL) {
+            dir.create_new(&path, readable, writable)
+        } else {
+            dir.create(&path, readable, writable, false)
+        };
+        file.map(|file| {
+            file.apply_open_flags(flag);
+            file
+        })
     } else {
         open_file(&path, flag)
         //dir.open(&path, readable, writable, directory)
     };
     if let Some(file) = file {
-        let fd = inner.alloc_fd();
-        inner.fd_table.insert(fd, Some(FileDescriptor::File(file)));
+        let fd = match inner.alloc_fd() {
+            Some(fd) => fd,
+            None => return -1,
+        };
+        inner.fd_table[fd] = Some(FileDescriptor::File(file));
+        fd as isize
+    } else if let Some(file) = open_initramfs(&path, flag) {
+        let fd = match inner.alloc_fd() {
+            Some(fd) => fd,
+            None => return -1,
+        };
+        inner.fd_table[fd] = Some(FileDescriptor::Abstract(file));
         fd as isize
     } else {
         -1
@@ -87,7 +124,6 @@ pub fn sys_open(fd:isize, path: *const u8, flags: u32) -> isize {
 }
 
 pub fn sys_close(fd: usize) -> isize {
-    println!("enter close");
     let process = current_process();
     let mut inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
@@ -98,4 +134,285 @@ pub fn sys_close(fd: usize) -> isize {
     }
     inner.fd_table[fd].take();
     0
-}
\ No newline at end of file
+}
+pub fn sys_dup(fd: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    let new_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -1,
+    };
+    inner.fd_table[new_fd] = Some(file);
+    new_fd as isize
+}
+/// newfd 已经打开时先关闭, 两者相同或 newfd 超过上限时返回 -1
+pub fn sys_dup3(old_fd: usize, new_fd: usize, _flags: u32) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if old_fd == new_fd || new_fd >= MAX_FD {
+        return -1;
+    }
+    let file = match inner.fd_table.get(old_fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    if new_fd >= inner.fd_table.len() {
+        inner.fd_table.resize(new_fd + 1, None);
+    }
+    inner.fd_table[new_fd] = Some(file);
+    new_fd as isize
+}
+/// 与 pipe2 相同, flags 被忽略. fds[0] 为读端, fds[1] 为写端
+pub fn sys_pipe(fds: *mut u32, _flags: u32) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let (read_end, write_end) = make_pipe();
+    let read_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -1,
+    };
+    inner.fd_table[read_fd] = Some(FileDescriptor::Abstract(read_end));
+    let write_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => {
+            inner.fd_table[read_fd] = None;
+            return -1;
+        }
+    };
+    inner.fd_table[write_fd] = Some(FileDescriptor::Abstract(write_end));
+    drop(inner);
+    *translated_refmut(token, fds) = read_fd as u32;
+    *translated_refmut(token, unsafe { fds.add(1) }) = write_fd as u32;
+    0
+}
+/// 成功时返回 buf, 与 Linux 一样路径放不下时返回 -1
+pub fn sys_getcwd(buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    if cwd.len() + 1 > len {
+        return -1;
+    }
+    let mut bytes = cwd.into_bytes();
+    bytes.push(0);
+    let mut written = 0;
+    for slice in translated_byte_buffer(token, buf, bytes.len()) {
+        slice.copy_from_slice(&bytes[written..written + slice.len()]);
+        written += slice.len();
+    }
+    buf as isize
+}
+pub fn sys_chdir(path: *const u8) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let cwd = process.inner_exclusive_access().cwd.clone();
+    let path = absolute_path(&cwd, &translated_str(token, path));
+    if !is_dir(&path) {
+        return -1;
+    }
+    process.inner_exclusive_access().cwd = path;
+    0
+}
+pub fn sys_getdents64(fd: usize, buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    drop(inner);
+    file.getdents(UserBuffer::new(translated_byte_buffer(token, buf, len)))
+}
+pub fn sys_truncate(path: *const u8, length: usize) -> isize {
+    let token = current_user_token();
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    let path = absolute_path(&cwd, &translated_str(token, path));
+    if let Some(file) = open_file(&path, OpenFlags::RDWR) {
+        if file.truncate(length) {
+            return 0;
+        }
+    }
+    -1
+}
+
+pub fn sys_ftruncate(fd: usize, length: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if let Some(Some(file)) = inner.fd_table.get(fd) {
+        let file = file.clone();
+        drop(inner);
+        if file.truncate(length) {
+            return 0;
+        }
+    }
+    -1
+}
+
+const FALLOC_FL_KEEP_SIZE: u32 = 0x01;
+
+pub fn sys_fallocate(fd: usize, mode: u32, offset: usize, len: usize) -> isize {
+    // 只支持默认模式与 FALLOC_FL_KEEP_SIZE
+    if mode & !FALLOC_FL_KEEP_SIZE != 0 || len == 0 {
+        return -1;
+    }
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if let Some(Some(file)) = inner.fd_table.get(fd) {
+        let file = file.clone();
+        drop(inner);
+        if file.fallocate(offset, len, mode & FALLOC_FL_KEEP_SIZE != 0) {
+            return 0;
+        }
+    }
+    -1
+}
+
+const UTIME_NOW: usize = (1 << 30) - 1;
+const UTIME_OMIT: usize = (1 << 30) - 2;
+
+/// path 为空时修改 dirfd 本身的时间 (即 futimens)
+pub fn sys_utimensat(dirfd: isize, path: *const u8, times: *const TimeSpec, _flags: u32) -> isize {
+    let token = current_user_token();
+    let now = get_realtime_ns();
+    let (atime, mtime) = if times.is_null() {
+        (Some(now), Some(now))
+    } else {
+        let convert = |ts: TimeSpec| match ts.tv_nsec {
+            UTIME_NOW => Some(now),
+            UTIME_OMIT => None,
+            _ => Some(ts.as_ns()),
+        };
+        let atime = *translated_ref(token, times);
+        let mtime = *translated_ref(token, unsafe { times.add(1) });
+        (convert(atime), convert(mtime))
+    };
+    let path = (!path.is_null()).then(|| translated_str(token, path));
+    // 只在借用中取出 cwd 和 dirfd, 打开文件时不持有进程的 inner
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let cwd = inner.cwd.clone();
+    let dir = if dirfd >= 0 {
+        match inner.fd_table.get(dirfd as usize) {
+            Some(Some(dir)) => Some(dir.clone()),
+            _ => return -1,
+        }
+    } else {
+        None
+    };
+    drop(inner);
+    let file = match path {
+        None => match dir {
+            Some(dir) => dir,
+            None => return -1,
+        },
+        Some(path) => {
+            // 与 sys_open 相同: AT_FDCWD 相对于当前目录, 否则相对于 dirfd
+            let file = match dir {
+                Some(dir) => {
+                    let path = path.replace("./", "");
+                    dir.open(&path, true, false, false)
+                        .or_else(|| dir.open(&path, true, false, true))
+                }
+                None => open_file(&absolute_path(&cwd, &path), OpenFlags::RDONLY),
+            };
+            match file {
+                Some(file) => FileDescriptor::File(file),
+                None => return -1,
+            }
+        }
+    };
+    if file.utimens(atime, mtime) {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn sys_fstat(fd: usize, kst: *mut u8) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    drop(inner);
+    let mut stat = Kstat::default();
+    file.kstat(&mut stat);
+    let bytes = stat.as_bytes();
+    UserBuffer::new(translated_byte_buffer(token, kst, bytes.len())).write(bytes);
+    0
+}
+
+fn write_statfs(token: usize, file: &dyn File, buf: *mut u8) -> isize {
+    let mut stat = Statfs::default();
+    if !file.statfs(&mut stat) {
+        return -1;
+    }
+    let bytes = stat.as_bytes();
+    UserBuffer::new(translated_byte_buffer(token, buf, bytes.len())).write(bytes);
+    0
+}
+
+pub fn sys_statfs(path: *const u8, buf: *mut u8) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path).replace("./", "");
+    let file = if path == "/" || path == "." || path.is_empty() {
+        FileDescriptor::File(crate::fs::root())
+    } else {
+        match open_file(&path, OpenFlags::RDONLY)
+            .or_else(|| open_file(&path, OpenFlags::DIRECTORY))
+        {
+            Some(file) => FileDescriptor::File(file),
+            None => return -1,
+        }
+    };
+    write_statfs(token, &file, buf)
+}
+
+/// 块缓存是 write-back 的, 把所有脏块写回磁盘
+pub fn sys_sync() -> isize {
+    sync_all();
+    0
+}
+
+/// 块缓存不区分文件, 与 sync 相同地写回所有脏块
+pub fn sys_fsync(fd: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if !matches!(inner.fd_table.get(fd), Some(Some(_))) {
+        return -1;
+    }
+    drop(inner);
+    sync_all();
+    0
+}
+
+pub fn sys_fstatfs(fd: usize, buf: *mut u8) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    drop(inner);
+    write_statfs(token, &file, buf)
+}
+
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    // ioctl 可能阻塞或访问进程信息, 先释放借用
+    drop(inner);
+    file.ioctl(request, arg)
+}
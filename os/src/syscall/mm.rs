@@ -0,0 +1,107 @@
+use crate::config::{PAGE_SIZE, USER_SPACE_END};
+use crate::fs::File;
+use crate::mm::{MapPermission, PhysAddr, VirtAddr};
+use crate::task::current_process;
+
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+const PROT_EXEC: usize = 4;
+
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+
+/// 只支持匿名映射与设备内存的映射, 普通文件不能 mmap
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    if len == 0 || offset % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let mut permission = MapPermission::U;
+    if prot & PROT_READ != 0 {
+        permission |= MapPermission::R;
+    }
+    if prot & PROT_WRITE != 0 {
+        permission |= MapPermission::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        permission |= MapPermission::X;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let region = if flags & MAP_ANONYMOUS != 0 {
+        None
+    } else {
+        let region = match inner.fd_table.get(fd) {
+            Some(Some(file)) => file.mmap_region(),
+            _ => return -1,
+        };
+        // 映射以页为单位, 取整后也不能超出设备内存, 否则会露出其后的物理内存
+        let end = len
+            .checked_add(PAGE_SIZE - 1)
+            .and_then(|len| offset.checked_add(len / PAGE_SIZE * PAGE_SIZE));
+        match region {
+            Some(region) if end.is_some_and(|end| end <= region.size) => Some(region),
+            _ => return -1,
+        }
+    };
+    let start_va = if flags & MAP_FIXED != 0 {
+        if addr % PAGE_SIZE != 0 || addr >= USER_SPACE_END {
+            return -1;
+        }
+        VirtAddr::from(addr)
+    } else {
+        inner.memory_set.find_free_area(len)
+    };
+    // 整段都要落在用户地址空间内, 跳板不在 areas 中, is_free 检查不到
+    let end = match start_va.0.checked_add(len) {
+        Some(end) if end <= USER_SPACE_END => end,
+        _ => return -1,
+    };
+    let end_va = VirtAddr::from(end);
+    if !inner.memory_set.is_free(start_va.floor(), end_va.ceil()) {
+        return -1;
+    }
+    match region {
+        Some(region) => inner.memory_set.insert_linear_area(
+            start_va,
+            end_va,
+            PhysAddr::from(region.pa + offset),
+            permission,
+            region.holder,
+        ),
+        None => {
+            if !inner
+                .memory_set
+                .try_insert_framed_area(start_va, end_va, permission)
+            {
+                return -1;
+            }
+        }
+    }
+    start_va.0 as isize
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    let end = match addr.checked_add(len) {
+        Some(end) if addr % PAGE_SIZE == 0 && end <= USER_SPACE_END => end,
+        _ => return -1,
+    };
+    let start_va = VirtAddr::from(addr);
+    let end_va = VirtAddr::from(end);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner
+        .memory_set
+        .remove_area(start_va.floor(), end_va.ceil())
+    {
+        0
+    } else {
+        -1
+    }
+}
@@ -0,0 +1,210 @@
+use crate::fs::FileDescriptor;
+use crate::mm::{translated_byte_buffer, translated_refmut, UserBuffer};
+use crate::net::{
+    SockAddr, Socket, SocketType, TcpSocket, UdpSocket, UnixSocket, AF_INET, AF_UNIX,
+    SOCKET_BUFFER_SIZE,
+};
+use crate::task::{current_process, current_user_token};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+fn get_socket(fd: usize) -> Option<Arc<dyn Socket>> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    match inner.fd_table.get(fd) {
+        Some(Some(FileDescriptor::Socket(socket))) => Some(socket.clone()),
+        _ => None,
+    }
+}
+
+fn add_socket(socket: Arc<dyn Socket>) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -1,
+    };
+    inner.fd_table[fd] = Some(FileDescriptor::Socket(socket));
+    fd as isize
+}
+
+fn read_sockaddr(addr: *const u8, addrlen: usize) -> Option<SockAddr> {
+    if addr.is_null() {
+        return None;
+    }
+    let token = current_user_token();
+    let bytes: Vec<u8> = translated_byte_buffer(token, addr, addrlen).concat();
+    SockAddr::from_bytes(&bytes)
+}
+
+/// 按 accept/getsockname 的约定写回地址, addrlen 为值-结果参数
+fn write_sockaddr(addr: Option<SockAddr>, buf: *mut u8, addrlen: *mut u32) -> isize {
+    if buf.is_null() || addrlen.is_null() {
+        return 0;
+    }
+    let addr = match addr {
+        Some(addr) => addr,
+        None => return -1,
+    };
+    let token = current_user_token();
+    let bytes = addr.to_bytes();
+    let len = translated_refmut(token, addrlen);
+    let copy_len = (*len as usize).min(bytes.len());
+    UserBuffer::new(translated_byte_buffer(token, buf, copy_len)).write(&bytes[..copy_len]);
+    *len = bytes.len() as u32;
+    0
+}
+
+pub fn sys_socket(domain: usize, socket_type: usize, _protocol: usize) -> isize {
+    let socket_type = match SocketType::from_raw(socket_type) {
+        Some(socket_type) => socket_type,
+        None => return -1,
+    };
+    let socket: Arc<dyn Socket> = match (domain as u16, socket_type) {
+        (AF_INET, SocketType::Stream) => Arc::new(TcpSocket::new()),
+        (AF_INET, SocketType::Datagram) => Arc::new(UdpSocket::new()),
+        (AF_UNIX, SocketType::Stream) => Arc::new(UnixSocket::new()),
+        _ => return -1,
+    };
+    add_socket(socket)
+}
+
+pub fn sys_bind(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    match (get_socket(fd), read_sockaddr(addr, addrlen)) {
+        (Some(socket), Some(addr)) => socket.bind(addr),
+        _ => -1,
+    }
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    match get_socket(fd) {
+        Some(socket) => socket.listen(backlog),
+        None => -1,
+    }
+}
+
+pub fn sys_accept(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    let socket = match get_socket(fd) {
+        Some(socket) => socket,
+        None => return -1,
+    };
+    match socket.accept() {
+        Some((new_socket, peer)) => {
+            write_sockaddr(Some(peer), addr, addrlen);
+            add_socket(new_socket)
+        }
+        None => -1,
+    }
+}
+
+pub fn sys_connect(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    match (get_socket(fd), read_sockaddr(addr, addrlen)) {
+        (Some(socket), Some(addr)) => socket.connect(addr),
+        _ => -1,
+    }
+}
+
+pub fn sys_getsockname(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    match get_socket(fd) {
+        Some(socket) => write_sockaddr(socket.local_addr(), addr, addrlen),
+        None => -1,
+    }
+}
+
+pub fn sys_getpeername(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    match get_socket(fd) {
+        Some(socket) => write_sockaddr(socket.peer_addr(), addr, addrlen),
+        None => -1,
+    }
+}
+
+pub fn sys_sendto(
+    fd: usize,
+    buf: *const u8,
+    len: usize,
+    _flags: usize,
+    addr: *const u8,
+    addrlen: usize,
+) -> isize {
+    let socket = match get_socket(fd) {
+        Some(socket) => socket,
+        None => return -1,
+    };
+    // 一次最多发送一个 socket 缓冲区, 不按用户给出的长度在内核堆上分配
+    let len = len.min(SOCKET_BUFFER_SIZE);
+    let token = current_user_token();
+    let data: Vec<u8> = translated_byte_buffer(token, buf, len).concat();
+    socket.sendto(&data, read_sockaddr(addr, addrlen))
+}
+
+pub fn sys_recvfrom(
+    fd: usize,
+    buf: *mut u8,
+    len: usize,
+    _flags: usize,
+    addr: *mut u8,
+    addrlen: *mut u32,
+) -> isize {
+    let socket = match get_socket(fd) {
+        Some(socket) => socket,
+        None => return -1,
+    };
+    // 先检查用户缓冲区, 再按不超过一个 socket 缓冲区的长度分配
+    let len = len.min(SOCKET_BUFFER_SIZE);
+    let token = current_user_token();
+    let mut user_buf = UserBuffer::new(translated_byte_buffer(token, buf, len));
+    let mut data = vec![0u8; len];
+    let (ret, from) = socket.recvfrom(&mut data);
+    if ret < 0 {
+        return ret;
+    }
+    user_buf.write(&data[..ret as usize]);
+    if from.is_some() {
+        write_sockaddr(from, addr, addrlen);
+    }
+    ret
+}
+
+pub fn sys_setsockopt(fd: usize, level: usize, name: usize, value: *const u8, len: usize) -> isize {
+    let socket = match get_socket(fd) {
+        Some(socket) => socket,
+        None => return -1,
+    };
+    // 目前支持的选项都是 int
+    if value.is_null() || len < 4 {
+        return -1;
+    }
+    let token = current_user_token();
+    let bytes: Vec<u8> = translated_byte_buffer(token, value, 4).concat();
+    let value = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    socket.setsockopt(level, name, value)
+}
+
+pub fn sys_getsockopt(fd: usize, level: usize, name: usize, value: *mut u8, len: *mut u32) -> isize {
+    let socket = match get_socket(fd) {
+        Some(socket) => socket,
+        None => return -1,
+    };
+    let option = match socket.getsockopt(level, name) {
+        Some(option) => option,
+        None => return -1,
+    };
+    if value.is_null() || len.is_null() {
+        return -1;
+    }
+    let token = current_user_token();
+    let len = translated_refmut(token, len);
+    let copy_len = (*len as usize).min(4);
+    UserBuffer::new(translated_byte_buffer(token, value, copy_len))
+        .write(&option.to_ne_bytes()[..copy_len]);
+    *len = 4;
+    0
+}
+
+pub fn sys_shutdown(fd: usize, how: usize) -> isize {
+    match get_socket(fd) {
+        Some(socket) => socket.shutdown(how),
+        None => -1,
+    }
+}
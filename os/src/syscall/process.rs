@@ -1,12 +1,14 @@
-use crate::fs::{open_file, OpenFlags};
+use crate::fs::{absolute_path, read_program};
 use crate::mm::{translated_ref, translated_refmut, translated_str};
+use crate::sbi::shutdown;
 use crate::task::{
     current_process, current_task, current_user_token, exit_current_and_run_next, pid2process,
-    suspend_current_and_run_next, SignalFlags,
+    reap_zombie, suspend_current_and_run_next, SignalFlags,
+};
+use crate::timer::{
+    get_realtime_ns, get_time_ns, set_realtime_ns, TimeSpec, TimeVal, NSEC_PER_SEC,
 };
-use crate::timer::get_time_ms;
 use alloc::string::String;
-use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 pub fn sys_exit(exit_code: i32) -> ! {
@@ -19,8 +21,38 @@ pub fn sys_yield() -> isize {
     0
 }
 
-pub fn sys_get_time() -> isize {
-    get_time_ms() as isize
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+
+pub fn sys_gettimeofday(tv: *mut TimeVal, _tz: usize) -> isize {
+    let ns = get_realtime_ns();
+    *translated_refmut(current_user_token(), tv) = TimeVal {
+        tv_sec: (ns / NSEC_PER_SEC) as usize,
+        tv_usec: (ns % NSEC_PER_SEC / 1000) as usize,
+    };
+    0
+}
+
+pub fn sys_clock_gettime(clock_id: usize, tp: *mut TimeSpec) -> isize {
+    let ns = match clock_id {
+        CLOCK_REALTIME => get_realtime_ns(),
+        CLOCK_MONOTONIC => get_time_ns(),
+        _ => return -1,
+    };
+    *translated_refmut(current_user_token(), tp) = TimeSpec::from_ns(ns);
+    0
+}
+
+pub fn sys_clock_settime(clock_id: usize, tp: *const TimeSpec) -> isize {
+    if clock_id != CLOCK_REALTIME {
+        return -1;
+    }
+    let ts = *translated_ref(current_user_token(), tp);
+    if ts.tv_nsec as u64 >= NSEC_PER_SEC {
+        return -1;
+    }
+    set_realtime_ns(ts.as_ns());
+    0
 }
 
 pub fn sys_getpid() -> isize {
@@ -43,7 +75,8 @@ pub fn sys_fork() -> isize {
 
 pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    let path = absolute_path(&cwd, &translated_str(token, path));
     let mut args_vec: Vec<String> = Vec::new();
     loop {
         let arg_str_ptr = *translated_ref(token, args);
@@ -55,8 +88,7 @@ pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
             args = args.add(1);
         }
     }
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
+    if let Some(all_data) = read_program(path.as_str()) {
         let process = current_process();
         let argc = args_vec.len();
         process.exec(all_data.as_slice(), args_vec);
@@ -67,9 +99,13 @@ pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
     }
 }
 
+/// 与 Linux 相同, 停止的子进程也报告
+const WUNTRACED: u32 = 2;
+
 /// If there is not a child process whose pid is same as given, return -1.
 /// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// 指定 WUNTRACED 时, 收到 SIGTSTP 停止且还没有报告过的子进程返回 -3, 子进程仍留在 children 中
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: u32) -> isize {
     let process = current_process();
     // find a child process
 
@@ -89,17 +125,28 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
     });
     if let Some((idx, _)) = pair {
         let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after being removed from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child PCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ release child PCB
+        let (found_pid, exit_code) = reap_zombie(child);
         *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
-    } else {
-        -2
+        return found_pid as isize;
     }
+    if options & WUNTRACED != 0 {
+        for child in inner
+            .children
+            .iter()
+            .filter(|p| pid == -1 || pid as usize == p.getpid())
+        {
+            let mut child_inner = child.inner_exclusive_access();
+            let signals = child_inner.signals;
+            let stopped = signals.contains(SignalFlags::SIGTSTP)
+                && !signals.contains(SignalFlags::SIGCONT)
+                && signals.check_error().is_none();
+            if stopped && !child_inner.stop_reported {
+                child_inner.stop_reported = true;
+                return -3;
+            }
+        }
+    }
+    -2
     // ---- release current PCB automatically
 }
 
@@ -115,3 +162,21 @@ pub fn sys_kill(pid: usize, signal: u32) -> isize {
         -1
     }
 }
+
+const LINUX_REBOOT_MAGIC1: u32 = 0xfee1dead;
+const LINUX_REBOOT_MAGIC2: u32 = 672274793;
+const LINUX_REBOOT_CMD_POWER_OFF: u32 = 0x4321fedc;
+
+/// 只支持关机, 关机前写回文件系统
+pub fn sys_reboot(magic1: u32, magic2: u32, cmd: u32) -> isize {
+    if magic1 != LINUX_REBOOT_MAGIC1 || magic2 != LINUX_REBOOT_MAGIC2 {
+        return -1;
+    }
+    match cmd {
+        LINUX_REBOOT_CMD_POWER_OFF => {
+            crate::fatfs::fs_unmount();
+            shutdown(false);
+        }
+        _ => -1,
+    }
+}
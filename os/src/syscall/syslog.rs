@@ -0,0 +1,61 @@
+//! syslog: 读取和控制内核日志缓冲区, 操作码与 Linux 相同
+use crate::logging;
+use crate::mm::{translated_byte_buffer, UserBuffer};
+use crate::task::{current_user_token, suspend_current_and_run_next};
+use alloc::format;
+
+const SYSLOG_ACTION_READ: usize = 2;
+const SYSLOG_ACTION_READ_ALL: usize = 3;
+const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+const SYSLOG_ACTION_CLEAR: usize = 5;
+const SYSLOG_ACTION_CONSOLE_OFF: usize = 6;
+const SYSLOG_ACTION_CONSOLE_ON: usize = 7;
+const SYSLOG_ACTION_CONSOLE_LEVEL: usize = 8;
+const SYSLOG_ACTION_SIZE_UNREAD: usize = 9;
+const SYSLOG_ACTION_SIZE_BUFFER: usize = 10;
+
+fn copy_to_user(buf: *mut u8, data: &[u8]) -> isize {
+    let token = current_user_token();
+    UserBuffer::new(translated_byte_buffer(token, buf, data.len())).write(data) as isize
+}
+
+pub fn sys_syslog(action: usize, buf: *mut u8, len: usize) -> isize {
+    match action {
+        SYSLOG_ACTION_READ => {
+            if len == 0 {
+                return 0;
+            }
+            // 没有新日志时阻塞
+            while logging::unread_len() == 0 {
+                suspend_current_and_run_next();
+            }
+            copy_to_user(buf, &logging::read_unread(len))
+        }
+        SYSLOG_ACTION_READ_ALL => copy_to_user(buf, &logging::read_all(len)),
+        SYSLOG_ACTION_READ_CLEAR => {
+            let ret = copy_to_user(buf, &logging::read_all(len));
+            logging::clear();
+            ret
+        }
+        SYSLOG_ACTION_CLEAR => {
+            logging::clear();
+            0
+        }
+        SYSLOG_ACTION_CONSOLE_OFF => {
+            logging::set_console_enabled(false);
+            0
+        }
+        SYSLOG_ACTION_CONSOLE_ON => {
+            logging::set_console_enabled(true);
+            0
+        }
+        // 级别放在 len 参数中, 1 到 8
+        SYSLOG_ACTION_CONSOLE_LEVEL => match len {
+            1..=8 if logging::set_default_level(&format!("{}", len)) => 0,
+            _ => -1,
+        },
+        SYSLOG_ACTION_SIZE_UNREAD => logging::unread_len() as isize,
+        SYSLOG_ACTION_SIZE_BUFFER => logging::LOG_BUF_LEN as isize,
+        _ => -1,
+    }
+}
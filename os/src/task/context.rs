@@ -1,3 +1,4 @@
+use super::kthread::kthread_start;
 use crate::trap::trap_return;
 
 #[repr(C)]
@@ -22,4 +23,11 @@ impl TaskContext {
             s: [0; 12],
         }
     }
+    pub fn goto_kthread_start(kstack_ptr: usize) -> Self {
+        Self {
+            ra: kthread_start as usize,
+            sp: kstack_ptr,
+            s: [0; 12],
+        }
+    }
 }
@@ -0,0 +1,41 @@
+//! 内核线程: 只在内核中运行, 没有用户地址空间, 与用户任务一起由 `TaskManager` 调度
+use super::{
+    add_task, block_current_and_run_next, current_task, schedule, take_current_task, TaskContext,
+    TaskControlBlock,
+};
+use crate::timer::{add_timer, get_time_ms};
+use alloc::sync::Arc;
+use riscv::register::sstatus;
+
+/// 创建内核线程并加入就绪队列, entry 返回后线程退出
+pub fn kthread_spawn(entry: fn()) -> Arc<TaskControlBlock> {
+    let task = Arc::new(TaskControlBlock::new_kthread(entry));
+    add_task(Arc::clone(&task));
+    task
+}
+
+/// 内核线程第一次被调度时从这里开始
+pub fn kthread_start() -> ! {
+    // 与系统调用一样打开中断, 可以被抢占
+    unsafe { sstatus::set_sie() };
+    let entry = current_task().unwrap().kthread_entry.unwrap();
+    entry();
+    kthread_exit();
+}
+
+/// 结束当前内核线程
+pub fn kthread_exit() -> ! {
+    // 空闲循环中还持有一份引用, 切换回去之后才会释放内核栈
+    let task = take_current_task().unwrap();
+    assert!(task.is_kthread());
+    drop(task);
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
+    unreachable!();
+}
+
+/// 阻塞当前内核线程至少 ms 毫秒
+pub fn kthread_sleep_ms(ms: usize) {
+    add_timer(get_time_ms() + ms, current_task().unwrap());
+    block_current_and_run_next();
+}
@@ -1,8 +1,19 @@
 use super::{ProcessControlBlock, TaskControlBlock, TaskStatus};
-use crate::sync::UPSafeCell;
+use crate::cmdline;
+use crate::sync::SpinNoIrq;
 use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::Arc;
 use lazy_static::*;
+use log::warn;
+
+/// 调度策略, 由启动参数 `sched=` 选择. 两种策略共用同一个就绪队列
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SchedPolicy {
+    /// `rr`, 时钟中断时把当前任务放回队尾
+    RoundRobin,
+    /// `fifo`, 任务一直运行到阻塞、让出或退出
+    Fifo,
+}
 
 pub struct TaskManager {
     ready_queue: VecDeque<Arc<TaskControlBlock>>,
@@ -34,14 +45,30 @@ impl TaskManager {
 }
 
 lazy_static! {
-    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
-        unsafe { UPSafeCell::new(TaskManager::new()) };
-    pub static ref PID2PCB: UPSafeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
-        unsafe { UPSafeCell::new(BTreeMap::new()) };
+    pub static ref TASK_MANAGER: SpinNoIrq<TaskManager> =
+        SpinNoIrq::new(TaskManager::new());
+    pub static ref PID2PCB: SpinNoIrq<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+        SpinNoIrq::new(BTreeMap::new());
+}
+
+lazy_static! {
+    pub static ref SCHED_POLICY: SchedPolicy = match cmdline::param("sched") {
+        None | Some("rr") => SchedPolicy::RoundRobin,
+        Some("fifo") => SchedPolicy::Fifo,
+        Some(other) => {
+            warn!("unknown sched={}, using rr", other);
+            SchedPolicy::RoundRobin
+        }
+    };
+}
+
+/// 时钟中断时是否切换任务
+pub fn preempt_on_tick() -> bool {
+    *SCHED_POLICY == SchedPolicy::RoundRobin
 }
 
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().add(task);
+    TASK_MANAGER.lock().add(task);
 }
 
 pub fn wakeup_task(task: Arc<TaskControlBlock>) {
@@ -52,24 +79,24 @@ pub fn wakeup_task(task: Arc<TaskControlBlock>) {
 }
 
 pub fn remove_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().remove(task);
+    TASK_MANAGER.lock().remove(task);
 }
 
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    TASK_MANAGER.exclusive_access().fetch()
+    TASK_MANAGER.lock().fetch()
 }
 
 pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
-    let map = PID2PCB.exclusive_access();
+    let map = PID2PCB.lock();
     map.get(&pid).map(Arc::clone)
 }
 
 pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
-    PID2PCB.exclusive_access().insert(pid, process);
+    PID2PCB.lock().insert(pid, process);
 }
 
 pub fn remove_from_pid2process(pid: usize) {
-    let mut map = PID2PCB.exclusive_access();
+    let mut map = PID2PCB.lock();
     if map.remove(&pid).is_none() {
         panic!("cannot find pid {} in pid2task!", pid);
     }
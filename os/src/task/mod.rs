@@ -1,5 +1,6 @@
 mod context;
 mod id;
+mod kthread;
 mod manager;
 mod process;
 mod processor;
@@ -9,21 +10,26 @@ mod switch;
 mod task;
 
 use self::id::TaskUserRes;
-use crate::fs::{open_file, OpenFlags};
+use crate::cmdline;
+use crate::fs::read_program;
 use crate::sbi::shutdown;
 use crate::timer::remove_timer;
 use alloc::{sync::Arc, vec::Vec};
 use lazy_static::*;
+use log::{debug, info};
 use manager::fetch_task;
 use process::ProcessControlBlock;
 use switch::__switch;
 
 pub use context::TaskContext;
 pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle, IDLE_PID};
-pub use manager::{add_task, pid2process, remove_from_pid2process, remove_task, wakeup_task};
+pub use kthread::{kthread_exit, kthread_sleep_ms, kthread_spawn};
+pub use manager::{
+    add_task, pid2process, preempt_on_tick, remove_from_pid2process, remove_task, wakeup_task,
+};
 pub use processor::{
     current_kstack_top, current_process, current_task, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, run_tasks, schedule, take_current_task,
+    current_user_token, run_tasks, schedule, take_current_task, try_current_task,
 };
 pub use signal::SignalFlags;
 pub use task::{TaskControlBlock, TaskStatus};
@@ -77,6 +83,7 @@ pub fn exit_current_and_run_next(exit_code: i32) {
                 "[kernel] Idle process exit with exit_code {} ...",
                 exit_code
             );
+            crate::fatfs::fs_unmount();
             if exit_code != 0 {
                 //crate::sbi::shutdown(255); //255 == -1 for err hint
                 shutdown(true);
@@ -96,7 +103,9 @@ pub fn exit_current_and_run_next(exit_code: i32) {
             // move all child processes under init process
             let mut initproc_inner = INITPROC.inner_exclusive_access();
             for child in process_inner.children.iter() {
-                child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+                let mut child_inner = child.inner_exclusive_access();
+                child_inner.parent = Some(Arc::downgrade(&INITPROC));
+                child_inner.orphaned = true;
                 initproc_inner.children.push(child.clone());
             }
         }
@@ -145,17 +154,70 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     schedule(&mut _unused as *mut _);
 }
 
+/// 启动参数中没有 `init=` 时运行的程序
+const DEFAULT_INIT: &str = "/bin/initproc";
+
 lazy_static! {
     pub static ref INITPROC: Arc<ProcessControlBlock> = {
-        // let inode = open_file("initproc", OpenFlags::RDONLY).unwrap();
-        // let v = inode.read_all();
-        let v = include_bytes!("../initproc");
-        ProcessControlBlock::new(v.as_slice())
+        let path = cmdline::param("init").unwrap_or(DEFAULT_INIT);
+        let elf = read_program(path).unwrap_or_else(|| panic!("init program {} not found", path));
+        info!("running {} as init", path);
+        ProcessControlBlock::new(elf.as_slice())
     };
 }
 
 pub fn add_initproc() {
     let _initproc = INITPROC.clone();
+    kthread_spawn(orphan_reaper);
+}
+
+const REAP_INTERVAL_MS: usize = 1000;
+
+/// 内核线程, 回收过继给 INITPROC 的孤儿进程中已经退出的.
+/// INITPROC 自己创建的子进程仍然由它自己 wait
+fn orphan_reaper() {
+    loop {
+        kthread_sleep_ms(REAP_INTERVAL_MS);
+        let mut reaped = Vec::new();
+        let mut initproc_inner = INITPROC.inner_exclusive_access();
+        // 判断与移除都在 INITPROC 的借用中完成, 不会与 INITPROC 的 waitpid 同时取走一个子进程.
+        // 退出的进程会先借用自己再借用 INITPROC, 这里只能尝试借用子进程, 借不到的下次再看
+        let mut i = 0;
+        while i < initproc_inner.children.len() {
+            // 退出的进程在调度走之前还持有自己的 Arc, 等它放手后再回收
+            let zombie = Arc::strong_count(&initproc_inner.children[i]) == 1
+                && initproc_inner.children[i]
+                    .try_inner_exclusive_access()
+                    .is_some_and(|inner| inner.orphaned && inner.is_zombie);
+            if zombie {
+                reaped.push(initproc_inner.children.swap_remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        drop(initproc_inner);
+        for zombie in reaped {
+            let (pid, exit_code) = reap_zombie(zombie);
+            debug!("reaped orphan process {} (exit code {})", pid, exit_code);
+        }
+    }
+}
+
+/// 回收已经从父进程 children 中移除的僵尸进程, 返回 pid 和退出码. waitpid 与 orphan_reaper 共用.
+/// 退出时已经关闭了文件并释放了数据页, 这里再清理一次, 最后一个 Arc 释放时
+/// 主线程的内核栈, 页表和 pid 随之回收
+pub fn reap_zombie(zombie: Arc<ProcessControlBlock>) -> (usize, i32) {
+    // 确认没有其他引用, 否则进程的资源不会在这里释放
+    assert_eq!(Arc::strong_count(&zombie), 1);
+    let pid = zombie.getpid();
+    let mut inner = zombie.inner_exclusive_access();
+    let exit_code = inner.exit_code;
+    inner.fd_table.clear();
+    inner.memory_set.recycle_data_pages();
+    inner.tasks.clear();
+    drop(inner);
+    drop(zombie);
+    (pid, exit_code)
 }
 
 pub fn check_signals_of_current() -> Option<(i32, &'static str)> {
@@ -164,6 +226,28 @@ pub fn check_signals_of_current() -> Option<(i32, &'static str)> {
     process_inner.signals.check_error()
 }
 
+/// 收到 SIGTSTP 后让出 CPU, 直到 SIGCONT 或致命信号到来
+pub fn wait_while_stopped() {
+    loop {
+        let process = current_process();
+        let mut process_inner = process.inner_exclusive_access();
+        let signals = process_inner.signals;
+        if !signals.contains(SignalFlags::SIGTSTP) || signals.check_error().is_some() {
+            return;
+        }
+        if signals.contains(SignalFlags::SIGCONT) {
+            process_inner
+                .signals
+                .remove(SignalFlags::SIGTSTP | SignalFlags::SIGCONT);
+            process_inner.stop_reported = false;
+            return;
+        }
+        drop(process_inner);
+        drop(process);
+        suspend_current_and_run_next();
+    }
+}
+
 pub fn current_add_signal(signal: SignalFlags) {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
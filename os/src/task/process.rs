@@ -3,15 +3,15 @@ use super::manager::insert_into_pid2process;
 use super::TaskControlBlock;
 use super::{add_task, SignalFlags};
 use super::{pid_alloc, PidHandle};
-use crate::fs::{File, FileDescriptor, root, Stdin, Stdout};
+use crate::config::MAX_FD;
+use crate::fs::{File, FileDescriptor, Stdin, Stdout};
 use crate::mm::{translated_refmut, MemorySet, KERNEL_SPACE};
-use crate::sync::{Condvar, Mutex, Semaphore, UPSafeCell};
+use crate::sync::{Condvar, Mutex, Semaphore, UPRefMut, UPSafeCell};
 use crate::trap::{trap_handler, TrapContext};
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use alloc::vec::Vec;
-use core::cell::RefMut;
 
 pub struct ProcessControlBlock {
     // immutable
@@ -22,6 +22,10 @@ pub struct ProcessControlBlock {
 
 pub struct ProcessControlBlockInner {
     pub is_zombie: bool,
+    /// 父进程退出后被过继给 INITPROC, 退出后由 `orphan_reaper` 回收
+    pub orphaned: bool,
+    /// 停止后已经由 WUNTRACED 的 waitpid 报告过, 继续运行时清除
+    pub stop_reported: bool,
     pub memory_set: MemorySet,
     pub parent: Option<Weak<ProcessControlBlock>>,
     pub children: Vec<Arc<ProcessControlBlock>>,
@@ -33,7 +37,8 @@ pub struct ProcessControlBlockInner {
     pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
     pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
     pub condvar_list: Vec<Option<Arc<Condvar>>>,
-    pub work_dir: Arc<FileDescriptor>,
+    /// 当前目录的绝对路径, 相对路径以它为起点
+    pub cwd: String,
 }
 
 impl ProcessControlBlockInner {
@@ -42,12 +47,15 @@ impl ProcessControlBlockInner {
         self.memory_set.token()
     }
 
-    pub fn alloc_fd(&mut self) -> usize {
+    /// 最小的空闲描述符, 已经打开 MAX_FD 个文件时返回 None
+    pub fn alloc_fd(&mut self) -> Option<usize> {
         if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
+            Some(fd)
+        } else if self.fd_table.len() < MAX_FD {
             self.fd_table.push(None);
-            self.fd_table.len() - 1
+            Some(self.fd_table.len() - 1)
+        } else {
+            None
         }
     }
 
@@ -69,10 +77,15 @@ impl ProcessControlBlockInner {
 }
 
 impl ProcessControlBlock {
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, ProcessControlBlockInner> {
+    pub fn inner_exclusive_access(&self) -> UPRefMut<'_, ProcessControlBlockInner> {
         self.inner.exclusive_access()
     }
 
+    /// 已被借用 (无论在哪个 hart 上) 时返回 None
+    pub fn try_inner_exclusive_access(&self) -> Option<UPRefMut<'_, ProcessControlBlockInner>> {
+        self.inner.try_exclusive_access()
+    }
+
     pub fn new(elf_data: &[u8]) -> Arc<Self> {
         // memory_set with elf program headers/trampoline/trap context/user stack
         let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
@@ -83,6 +96,8 @@ impl ProcessControlBlock {
             inner: unsafe {
                 UPSafeCell::new(ProcessControlBlockInner {
                     is_zombie: false,
+                    orphaned: false,
+                    stop_reported: false,
                     memory_set,
                     parent: None,
                     children: Vec::new(),
@@ -101,7 +116,7 @@ impl ProcessControlBlock {
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
-                    work_dir:Arc::new(FileDescriptor::File(root()))
+                    cwd: String::from("/"),
                 })
             },
         });
@@ -210,6 +225,8 @@ impl ProcessControlBlock {
             inner: unsafe {
                 UPSafeCell::new(ProcessControlBlockInner {
                     is_zombie: false,
+                    orphaned: false,
+                    stop_reported: false,
                     memory_set,
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
@@ -221,7 +238,7 @@ impl ProcessControlBlock {
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
-                    work_dir: parent.work_dir.clone()
+                    cwd: parent.cwd.clone(),
                 })
             },
         });
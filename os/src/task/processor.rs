@@ -1,10 +1,17 @@
 use super::__switch;
 use super::{fetch_task, TaskStatus};
 use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
+use crate::config::MAX_HARTS;
+use crate::smp::hart_id;
+#[cfg(feature = "lockdep")]
+use crate::sync::irq_off_depth;
 use crate::sync::UPSafeCell;
-use crate::trap::TrapContext;
+use crate::trap::{wait_for_interrupt, TrapContext};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
 use lazy_static::*;
+use riscv::register::sstatus;
 
 pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
@@ -30,13 +37,25 @@ impl Processor {
 }
 
 lazy_static! {
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+    /// 每个 hart 一个, 只由该 hart 自己访问
+    static ref PROCESSORS: Vec<UPSafeCell<Processor>> = (0..MAX_HARTS)
+        .map(|_| unsafe { UPSafeCell::new(Processor::new()) })
+        .collect();
+}
+
+fn local_processor() -> &'static UPSafeCell<Processor> {
+    &PROCESSORS[hart_id()]
 }
 
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        let mut processor = local_processor().exclusive_access();
         if let Some(task) = fetch_task() {
+            // 任务可能刚在另一个 hart 上让出, 等它的上下文保存完毕
+            while task.on_cpu.load(Ordering::Acquire) {
+                core::hint::spin_loop();
+            }
+            task.on_cpu.store(true, Ordering::Relaxed);
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
             let mut task_inner = task.inner_exclusive_access();
@@ -44,24 +63,33 @@ pub fn run_tasks() {
             task_inner.task_status = TaskStatus::Running;
             drop(task_inner);
             // release coming task TCB manually
-            processor.current = Some(task);
+            processor.current = Some(task.clone());
             // release processor manually
             drop(processor);
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+            // 回到这里时任务的上下文已经保存好, 其他 hart 可以运行它了
+            task.on_cpu.store(false, Ordering::Release);
         } else {
-            println!("no tasks available in run_tasks");
+            // 没有就绪任务时等待中断, 由设备中断或定时器唤醒阻塞的任务
+            drop(processor);
+            wait_for_interrupt();
         }
     }
 }
 
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    local_processor().exclusive_access().take_current()
 }
 
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    local_processor().exclusive_access().current()
+}
+
+/// 不会因为 Processor 已被借用而 panic, 供日志等可能在任何地方调用的代码使用
+pub fn try_current_task() -> Option<Arc<TaskControlBlock>> {
+    local_processor().try_exclusive_access()?.current()
 }
 
 pub fn current_process() -> Arc<ProcessControlBlock> {
@@ -95,10 +123,18 @@ pub fn current_kstack_top() -> usize {
 }
 
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+    #[cfg(feature = "lockdep")]
+    assert_eq!(irq_off_depth(), 0, "schedule while holding a lock");
+    // 切换期间不能被抢占, 回到本任务时恢复原来的中断状态
+    let irq_enabled = sstatus::read().sie();
+    unsafe { sstatus::clear_sie() };
+    let mut processor = local_processor().exclusive_access();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
     drop(processor);
     unsafe {
         __switch(switched_task_cx_ptr, idle_task_cx_ptr);
     }
+    if irq_enabled {
+        unsafe { sstatus::set_sie() };
+    }
 }
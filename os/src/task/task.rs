@@ -1,23 +1,41 @@
 use super::id::TaskUserRes;
 use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext};
 use crate::trap::TrapContext;
-use crate::{mm::PhysPageNum, sync::UPSafeCell};
+use crate::{
+    mm::PhysPageNum,
+    sync::{UPRefMut, UPSafeCell},
+};
 use alloc::sync::{Arc, Weak};
-use core::cell::RefMut;
+use core::sync::atomic::AtomicBool;
 
 pub struct TaskControlBlock {
     // immutable
+    /// 内核线程不属于任何进程, 为空
     pub process: Weak<ProcessControlBlock>,
+    /// 内核线程的入口, 用户线程为 None
+    pub kthread_entry: Option<fn()>,
     pub kstack: KernelStack,
+    /// 正在某个 hart 上运行, 或者刚让出但上下文还没保存完
+    pub on_cpu: AtomicBool,
     // mutable
     inner: UPSafeCell<TaskControlBlockInner>,
 }
 
 impl TaskControlBlock {
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+    pub fn inner_exclusive_access(&self) -> UPRefMut<'_, TaskControlBlockInner> {
         self.inner.exclusive_access()
     }
 
+    /// 内部状态正被借用时返回 None
+    pub fn try_tid(&self) -> Option<usize> {
+        let inner = self.inner.try_exclusive_access()?;
+        inner.res.as_ref().map(|res| res.tid)
+    }
+
+    pub fn is_kthread(&self) -> bool {
+        self.kthread_entry.is_some()
+    }
+
     pub fn get_user_token(&self) -> usize {
         let process = self.process.upgrade().unwrap();
         let inner = process.inner_exclusive_access();
@@ -56,7 +74,9 @@ impl TaskControlBlock {
         let kstack_top = kstack.get_top();
         Self {
             process: Arc::downgrade(&process),
+            kthread_entry: None,
             kstack,
+            on_cpu: AtomicBool::new(false),
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
                     res: Some(res),
@@ -68,6 +88,27 @@ impl TaskControlBlock {
             },
         }
     }
+
+    /// 没有用户地址空间和 trap 上下文, 第一次被调度时从 `kthread_start` 开始运行
+    pub fn new_kthread(entry: fn()) -> Self {
+        let kstack = kstack_alloc();
+        let kstack_top = kstack.get_top();
+        Self {
+            process: Weak::new(),
+            kthread_entry: Some(entry),
+            kstack,
+            on_cpu: AtomicBool::new(false),
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    res: None,
+                    trap_cx_ppn: PhysPageNum(0),
+                    task_cx: TaskContext::goto_kthread_start(kstack_top),
+                    task_status: TaskStatus::Ready,
+                    exit_code: None,
+                })
+            },
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq)]
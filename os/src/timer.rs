@@ -1,8 +1,9 @@
 use core::cmp::Ordering;
 
-use crate::config::CLOCK_FREQ;
+use crate::fdt::MACHINE;
+use crate::drivers::RTC_DEVICE;
 use crate::sbi::set_timer;
-use crate::sync::UPSafeCell;
+use crate::sync::SpinNoIrq;
 use crate::task::{wakeup_task, TaskControlBlock};
 use alloc::collections::BinaryHeap;
 use alloc::sync::Arc;
@@ -11,20 +12,64 @@ use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
 const MSEC_PER_SEC: usize = 1000;
+pub const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+impl TimeSpec {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / NSEC_PER_SEC) as usize,
+            tv_nsec: (ns % NSEC_PER_SEC) as usize,
+        }
+    }
+
+    pub fn as_ns(&self) -> u64 {
+        self.tv_sec as u64 * NSEC_PER_SEC + self.tv_nsec as u64
+    }
+}
 
 pub fn get_time() -> usize {
     time::read()
 }
 
 pub fn get_time_ms() -> usize {
-    time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
+    time::read() / (MACHINE.timebase_freq / MSEC_PER_SEC)
 }
 pub fn get_time_sec() -> usize {
-    time::read() / CLOCK_FREQ
+    time::read() / MACHINE.timebase_freq
+}
+
+/// 自开机起的纳秒数
+pub fn get_time_ns() -> u64 {
+    let ticks = time::read() as u64;
+    let freq = MACHINE.timebase_freq as u64;
+    ticks / freq * NSEC_PER_SEC + ticks % freq * NSEC_PER_SEC / freq
+}
+
+/// 墙上时间, 自 1970-01-01 00:00:00 UTC 起的纳秒数
+pub fn get_realtime_ns() -> u64 {
+    RTC_DEVICE.get_time()
+}
+
+pub fn set_realtime_ns(ns: u64) {
+    RTC_DEVICE.set_time(ns);
 }
 
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    set_timer(get_time() + MACHINE.timebase_freq / TICKS_PER_SEC);
 }
 
 pub struct TimerCondVar {
@@ -53,17 +98,17 @@ impl Ord for TimerCondVar {
 }
 
 lazy_static! {
-    static ref TIMERS: UPSafeCell<BinaryHeap<TimerCondVar>> =
-        unsafe { UPSafeCell::new(BinaryHeap::<TimerCondVar>::new()) };
+    static ref TIMERS: SpinNoIrq<BinaryHeap<TimerCondVar>> =
+        SpinNoIrq::new(BinaryHeap::<TimerCondVar>::new());
 }
 
 pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
-    let mut timers = TIMERS.exclusive_access();
+    let mut timers = TIMERS.lock();
     timers.push(TimerCondVar { expire_ms, task });
 }
 
 pub fn remove_timer(task: Arc<TaskControlBlock>) {
-    let mut timers = TIMERS.exclusive_access();
+    let mut timers = TIMERS.lock();
     let mut temp = BinaryHeap::<TimerCondVar>::new();
     for condvar in timers.drain() {
         if Arc::as_ptr(&task) != Arc::as_ptr(&condvar.task) {
@@ -76,7 +121,7 @@ pub fn remove_timer(task: Arc<TaskControlBlock>) {
 
 pub fn check_timer() {
     let current_ms = get_time_ms();
-    let mut timers = TIMERS.exclusive_access();
+    let mut timers = TIMERS.lock();
     while let Some(timer) = timers.peek() {
         if timer.expire_ms <= current_ms {
             wakeup_task(Arc::clone(&timer.task));
@@ -1,17 +1,22 @@
 mod context;
 
 use crate::config::TRAMPOLINE;
+use crate::drivers::irq::handle_external_interrupt;
+use crate::net::poll_interfaces;
 use crate::syscall::syscall;
+use crate::sync::irq_off_depth;
 use crate::task::{
     check_signals_of_current, current_add_signal, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, exit_current_and_run_next, suspend_current_and_run_next, SignalFlags,
+    current_user_token, exit_current_and_run_next, preempt_on_tick, suspend_current_and_run_next,
+    try_current_task, wait_while_stopped, SignalFlags,
 };
 use crate::timer::{check_timer, set_next_trigger};
 use core::arch::{asm, global_asm};
+use log::debug;
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, stval, stvec,
+    sie, sscratch, sstatus, stval, stvec,
 };
 
 global_asm!(include_str!("trap.S"));
@@ -21,8 +26,12 @@ pub fn init() {
 }
 
 fn set_kernel_trap_entry() {
+    extern "C" {
+        fn __alltraps_k();
+    }
     unsafe {
-        stvec::write(trap_from_kernel as usize, TrapMode::Direct);
+        stvec::write(__alltraps_k as usize, TrapMode::Direct);
+        sscratch::write(trap_from_kernel as usize);
     }
 }
 
@@ -38,6 +47,21 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+pub fn enable_external_interrupt() {
+    unsafe {
+        sie::set_sext();
+    }
+}
+
+/// 在内核中短暂打开中断并等待, 中断由 `trap_from_kernel` 处理
+pub fn wait_for_interrupt() {
+    unsafe {
+        sstatus::set_sie();
+        asm!("wfi");
+        sstatus::clear_sie();
+    }
+}
+
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
@@ -48,8 +72,13 @@ pub fn trap_handler() -> ! {
             // jump to next instruction anyway
             let mut cx = current_trap_cx();
             cx.sepc += 4;
+            // 系统调用期间打开中断, 没有持有锁的地方都可以被抢占
+            unsafe { sstatus::set_sie() };
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
@@ -60,14 +89,12 @@ pub fn trap_handler() -> ! {
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            /*
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
+            debug!(
+                "{:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
                 scause.cause(),
                 stval,
                 current_trap_cx().sepc,
             );
-            */
             current_add_signal(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
@@ -76,7 +103,15 @@ pub fn trap_handler() -> ! {
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
             check_timer();
-            suspend_current_and_run_next();
+            poll_interfaces();
+            #[cfg(feature = "graphics")]
+            crate::drivers::gpu::refresh_framebuffer();
+            if preempt_on_tick() {
+                suspend_current_and_run_next();
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            handle_external_interrupt();
         }
         _ => {
             panic!(
@@ -86,6 +121,7 @@ pub fn trap_handler() -> ! {
             );
         }
     }
+    wait_while_stopped();
     // check signals
     if let Some((errno, msg)) = check_signals_of_current() {
         println!("[kernel] {}", msg);
@@ -96,6 +132,8 @@ pub fn trap_handler() -> ! {
 
 #[no_mangle]
 pub fn trap_return() -> ! {
+    // stvec 改为跳板之后不能再响应中断
+    unsafe { sstatus::clear_sie() };
     set_user_trap_entry();
     let trap_cx_user_va = current_trap_cx_user_va();
     let user_satp = current_user_token();
@@ -116,11 +154,37 @@ pub fn trap_return() -> ! {
     }
 }
 
+/// 被打断的内核代码是否处在可以切换任务的位置
+///
+/// 持有 `UPSafeCell` 或 `SpinNoIrq` 时中断是关闭的, 能进入这里说明没有持有它们;
+/// 空闲循环中没有当前任务, 不能切换
+fn preemptible() -> bool {
+    preempt_on_tick() && irq_off_depth() == 0 && try_current_task().is_some()
+}
+
+/// 由 `__alltraps_k` 调用, 返回后经 `__restore_k` 回到被打断的内核代码,
+/// 被抢占时先切换到其他任务, 本任务再次被调度时才返回
 #[no_mangle]
-pub fn trap_from_kernel() -> ! {
-    use riscv::register::sepc;
-    println!("stval = {:#x}, sepc = {:#x}", stval::read(), sepc::read());
-    panic!("a trap {:?} from kernel!", scause::read().cause());
+pub fn trap_from_kernel(_trap_cx: &TrapContext) {
+    let scause = scause::read();
+    let stval = stval::read();
+    match scause.cause() {
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            handle_external_interrupt();
+        }
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            set_next_trigger();
+            check_timer();
+            if preemptible() {
+                suspend_current_and_run_next();
+            }
+        }
+        _ => {
+            use riscv::register::sepc;
+            println!("stval = {:#x}, sepc = {:#x}", stval, sepc::read());
+            panic!("a trap {:?} from kernel!", scause.cause());
+        }
+    }
 }
 
 pub use context::TrapContext;
@@ -0,0 +1,203 @@
+//! 在 QEMU 中启动内核, 向串口输入并检查输出
+//!
+//! 内核与 FAT 镜像由 os/Makefile 的 qemu_dump 构建, QEMU 参数取自 `make qemu-args`,
+//! 与 `make run` 相同, 环境变量 SMP、LOG 的含义也与 make 相同.
+//! run 会转发宿主机的网络端口, 所以同一时刻只运行一个 QEMU, 并行的测试在这里排队.
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 最近一次构建使用的 features, 持有它的测试独占 QEMU
+static QEMU_LOCK: Mutex<Option<String>> = Mutex::new(None);
+
+/// 失败时在 panic 信息中附带的输出长度
+const OUTPUT_TAIL: usize = 4000;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub fn os_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../os")
+}
+
+fn make(args: &[&str]) -> String {
+    let output = Command::new("make")
+        .arg("-s")
+        .args(args)
+        .current_dir(os_dir())
+        .output()
+        .unwrap_or_else(|err| panic!("cannot run make: {}", err));
+    if !output.status.success() {
+        panic!(
+            "make {} failed with {}\n{}{}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// QEMU 的 stdout 与 stderr 合在一起
+#[derive(Default)]
+struct Output {
+    inner: Mutex<OutputInner>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct OutputInner {
+    data: Vec<u8>,
+    /// stdout 已关闭, 即 QEMU 已退出
+    closed: bool,
+}
+
+impl OutputInner {
+    fn tail(&self) -> String {
+        let start = self.data.len().saturating_sub(OUTPUT_TAIL);
+        String::from_utf8_lossy(&self.data[start..]).into_owned()
+    }
+}
+
+fn spawn_reader(mut pipe: impl Read + Send + 'static, output: Arc<Output>, closes: bool) {
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            let len = pipe.read(&mut buf).unwrap_or(0);
+            let mut inner = output.inner.lock().unwrap_or_else(PoisonError::into_inner);
+            if len == 0 {
+                inner.closed |= closes;
+                output.changed.notify_all();
+                return;
+            }
+            inner.data.extend_from_slice(&buf[..len]);
+            output.changed.notify_all();
+        }
+    });
+}
+
+pub struct Qemu {
+    child: Child,
+    stdin: ChildStdin,
+    output: Arc<Output>,
+    /// expect 从这里开始查找
+    cursor: usize,
+    _lock: MutexGuard<'static, Option<String>>,
+}
+
+impl Qemu {
+    /// 与 `make run BOOTARGS=...` 相同
+    pub fn boot(bootargs: &str) -> Self {
+        Self::boot_with_features("", bootargs)
+    }
+
+    /// 与 `make run FEATURES=... BOOTARGS=...` 相同, features 变化时重新构建
+    pub fn boot_with_features(features: &str, bootargs: &str) -> Self {
+        let mut lock = QEMU_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        if lock.as_deref() != Some(features) {
+            *lock = None;
+            make(&["qemu_dump", &format!("FEATURES={}", features)]);
+            *lock = Some(features.to_string());
+        }
+        let args = make(&["qemu-args"]);
+        let mut child = Command::new("qemu-system-riscv64")
+            .args(args.split_whitespace())
+            .arg("-append")
+            .arg(bootargs)
+            .current_dir(os_dir())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|err| panic!("cannot start qemu-system-riscv64: {}", err));
+        let output = Arc::new(Output::default());
+        spawn_reader(child.stdout.take().unwrap(), output.clone(), true);
+        spawn_reader(child.stderr.take().unwrap(), output.clone(), false);
+        Self {
+            stdin: child.stdin.take().unwrap(),
+            child,
+            output,
+            cursor: 0,
+            _lock: lock,
+        }
+    }
+
+    /// 等待上次匹配之后的输出中出现 pattern, 返回两者之间的输出.
+    /// 超时或 QEMU 先退出时 panic, 并附上最后的输出
+    pub fn expect(&mut self, pattern: &str, timeout: Duration) -> String {
+        assert!(!pattern.is_empty());
+        let deadline = Instant::now() + timeout;
+        let mut inner = self.output.inner.lock().unwrap();
+        loop {
+            let unread = &inner.data[self.cursor..];
+            if let Some(pos) = unread
+                .windows(pattern.len())
+                .position(|window| window == pattern.as_bytes())
+            {
+                let before = String::from_utf8_lossy(&unread[..pos]).into_owned();
+                self.cursor += pos + pattern.len();
+                return before;
+            }
+            let now = Instant::now();
+            let reason = if inner.closed {
+                "QEMU exited"
+            } else if now >= deadline {
+                "timed out"
+            } else {
+                inner = self
+                    .output
+                    .changed
+                    .wait_timeout(inner, deadline - now)
+                    .unwrap()
+                    .0;
+                continue;
+            };
+            let tail = inner.tail();
+            drop(inner);
+            panic!(
+                "{} while waiting for {:?}, output:\n{}",
+                reason, pattern, tail
+            );
+        }
+    }
+
+    /// 写入串口, 换行需要调用者给出
+    pub fn send(&mut self, input: &str) {
+        self.stdin.write_all(input.as_bytes()).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    /// 等待内核关机. init 正常退出或调用 shutdown 时成功,
+    /// init 返回非零或内核 panic 时失败. 超时则杀死 QEMU 并 panic
+    pub fn wait_exit(&mut self, timeout: Duration) -> ExitStatus {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            if Instant::now() >= deadline {
+                let _ = self.child.kill();
+                let tail = self.output.inner.lock().unwrap().tail();
+                panic!(
+                    "kernel did not shut down in {:?}, output:\n{}",
+                    timeout, tail
+                );
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// 到目前为止的全部输出
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output.inner.lock().unwrap().data).into_owned()
+    }
+}
+
+impl Drop for Qemu {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
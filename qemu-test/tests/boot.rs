@@ -0,0 +1,72 @@
+//! 以默认配置构建, 与 `make run` 相同
+use qemu_test::Qemu;
+use std::time::Duration;
+
+const BOOT_TIMEOUT: Duration = Duration::from_secs(60);
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// user_shell 在根目录时的提示符
+const PROMPT: &str = ":/$ ";
+
+#[test]
+fn shell_runs_program() {
+    let mut qemu = Qemu::boot("");
+    qemu.expect(PROMPT, BOOT_TIMEOUT);
+    qemu.send("hello_world\n");
+    qemu.expect("Hello world from user mode program!", TIMEOUT);
+    qemu.expect(PROMPT, TIMEOUT);
+    qemu.send("echo status=$?\n");
+    qemu.expect("status=0", TIMEOUT);
+    qemu.expect(PROMPT, TIMEOUT);
+    qemu.send("shutdown\n");
+    let status = qemu.wait_exit(TIMEOUT);
+    assert!(status.success(), "kernel exited with {}", status);
+}
+
+#[test]
+fn shell_pipes_and_redirection() {
+    let mut qemu = Qemu::boot("");
+    qemu.expect(PROMPT, BOOT_TIMEOUT);
+    qemu.send("echo 'one  two' \"$HOME\" | cat | cat > /shell.out\n");
+    qemu.expect(PROMPT, TIMEOUT);
+    qemu.send("echo three >> /shell.out\n");
+    qemu.expect(PROMPT, TIMEOUT);
+    qemu.send("cat < /shell.out\n");
+    qemu.expect("one  two /\r\nthree", TIMEOUT);
+    qemu.expect(PROMPT, TIMEOUT);
+    qemu.send("cd bin\n");
+    qemu.expect(":/bin$ ", TIMEOUT);
+    qemu.send("no_such_program\n");
+    qemu.expect("no_such_program: command not found", TIMEOUT);
+    qemu.send("echo $?\n");
+    qemu.expect("127", TIMEOUT);
+    qemu.send("exit 3\n");
+    // init 返回 shell 的退出码, 不为 0 时内核以失败状态关机
+    let status = qemu.wait_exit(TIMEOUT);
+    assert!(!status.success(), "kernel exited with {}", status);
+}
+
+#[test]
+fn init_success_shuts_down() {
+    let mut qemu = Qemu::boot("init=/bin/exit");
+    qemu.expect("exit pass.", BOOT_TIMEOUT);
+    let status = qemu.wait_exit(TIMEOUT);
+    assert!(status.success(), "kernel exited with {}", status);
+}
+
+#[test]
+fn init_failure_fails_qemu() {
+    let mut qemu = Qemu::boot("init=/bin/stack_overflow");
+    qemu.expect("It should trigger segmentation fault!", BOOT_TIMEOUT);
+    let status = qemu.wait_exit(TIMEOUT);
+    assert!(!status.success(), "kernel exited with {}", status);
+}
+
+#[test]
+fn testsuite_reports_summary() {
+    let mut qemu = Qemu::boot("testsuite=auto testsuite_timeout=5");
+    qemu.expect("TESTSUITE BEGIN", BOOT_TIMEOUT);
+    let summary = qemu.expect("TESTSUITE END", Duration::from_secs(600));
+    assert!(summary.contains("TESTSUITE SUMMARY"), "{}", qemu.output());
+    // 测试程序是否全部通过由 CI 解析 TEST 行判断, 这里只要求正常关机
+    qemu.wait_exit(TIMEOUT);
+}
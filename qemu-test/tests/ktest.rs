@@ -0,0 +1,12 @@
+//! 以 test feature 构建, 与 `make test` 相同. 单独放在一个测试程序中, 避免与 boot.rs 交替重新构建
+use qemu_test::Qemu;
+use std::time::Duration;
+
+#[test]
+fn kernel_tests_pass() {
+    let mut qemu = Qemu::boot_with_features("test", "");
+    qemu.expect("[ktest] running", Duration::from_secs(60));
+    qemu.expect("[ktest] test result: ok.", Duration::from_secs(60));
+    let status = qemu.wait_exit(Duration::from_secs(10));
+    assert!(status.success(), "kernel exited with {}", status);
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{args, close, open, read, write, OpenFlags};
+
+/// 依次输出各个文件, 没有参数时输出标准输入
+#[no_mangle]
+pub fn main() -> i32 {
+    let args = args();
+    if args.len() < 2 {
+        copy(0);
+        return 0;
+    }
+    let mut status = 0;
+    for path in args[1..].iter() {
+        let fd = open(&format!("{}\0", path), OpenFlags::RDONLY);
+        if fd < 0 {
+            println!("cat: {}: No such file", path);
+            status = 1;
+            continue;
+        }
+        copy(fd as usize);
+        close(fd as usize);
+    }
+    status
+}
+
+fn copy(fd: usize) {
+    let mut buf = [0u8; 512];
+    loop {
+        let len = read(fd, &mut buf);
+        if len <= 0 {
+            return;
+        }
+        write(1, &buf[..len as usize]);
+    }
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{syslog, write, SYSLOG_ACTION_READ_ALL};
+
+// 与内核日志缓冲区一样大, 用户堆放不下
+const LOG_BUF_LEN: usize = 64 * 1024;
+static mut BUF: [u8; LOG_BUF_LEN] = [0; LOG_BUF_LEN];
+
+/// 打印内核日志缓冲区中的全部内容
+#[no_mangle]
+pub fn main() -> i32 {
+    let buf = unsafe { &mut *core::ptr::addr_of_mut!(BUF) };
+    let len = syslog(SYSLOG_ACTION_READ_ALL, buf);
+    if len < 0 {
+        println!("dmesg: read kernel buffer failed");
+        return -1;
+    }
+    write(1, &buf[..len as usize]);
+    0
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    accept, bind, close, listen, read, setsockopt, socket, write, SockAddrIn, AF_INET,
+    SOCK_STREAM, SOL_SOCKET, SO_REUSEADDR,
+};
+
+// 与 Makefile 中 QEMU 的端口转发一致, 宿主机上 `nc localhost 5555` 即可连上
+const PORT: u16 = 5555;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let server = socket(AF_INET, SOCK_STREAM);
+    assert!(server >= 0);
+    let server = server as usize;
+    setsockopt(server, SOL_SOCKET, SO_REUSEADDR, 1);
+    assert_eq!(bind(server, &SockAddrIn::new([0, 0, 0, 0], PORT)), 0);
+    assert_eq!(listen(server, 8), 0);
+    println!("echo server listening on port {}", PORT);
+    loop {
+        let client = accept(server);
+        if client < 0 {
+            println!("accept failed");
+            break;
+        }
+        let client = client as usize;
+        println!("client connected, fd = {}", client);
+        let mut buf = [0u8; 512];
+        loop {
+            let len = read(client, &mut buf);
+            if len <= 0 {
+                break;
+            }
+            write(client, &buf[..len as usize]);
+        }
+        close(client);
+        println!("client disconnected");
+    }
+    close(server);
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, ioctl, mmap, munmap, open, OpenFlags, MAP_SHARED, PROT_READ, PROT_WRITE};
+
+const FBIOGET_VSCREENINFO: usize = 0x4600;
+
+/// 把 /dev/fb0 映射进来, 画一个渐变色的背景和一个白色方块
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("/dev/fb0\0", OpenFlags::RDWR);
+    if fd < 0 {
+        println!("fb_test: no framebuffer");
+        return -1;
+    }
+    let fd = fd as usize;
+    // struct fb_var_screeninfo 共 160 字节, 前两项是宽和高
+    let mut info = [0u32; 40];
+    if ioctl(fd, FBIOGET_VSCREENINFO, info.as_mut_ptr() as usize) < 0 {
+        println!("fb_test: FBIOGET_VSCREENINFO failed");
+        return -1;
+    }
+    let (width, height) = (info[0] as usize, info[1] as usize);
+    let len = width * height * 4;
+    let addr = mmap(len, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    if addr < 0 {
+        println!("fb_test: mmap failed");
+        return -1;
+    }
+    let fb = unsafe { core::slice::from_raw_parts_mut(addr as *mut u32, width * height) };
+    for y in 0..height {
+        for x in 0..width {
+            let r = (x * 255 / width) as u32;
+            let g = (y * 255 / height) as u32;
+            fb[y * width + x] = 0xff00_0000 | (r << 16) | (g << 8) | 0x80;
+        }
+    }
+    for y in height / 4..height / 2 {
+        for x in width / 4..width / 2 {
+            fb[y * width + x] = 0xffff_ffff;
+        }
+    }
+    println!("fb_test: drew {}x{}", width, height);
+    munmap(addr as usize, len);
+    close(fd);
+    0
+}
@@ -5,25 +5,282 @@
 extern crate user_lib;
 extern crate alloc;
 
-use user_lib::{exec, fork, wait, _yield};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    _yield, close, dup2, exec, execv, exit, fork, get_time, kill, open, read, read_dir,
+    try_waitpid, wait, write, OpenFlags, DT_REG, SIGKILL,
+};
 
+const SHELL: &str = "/bin/user_shell\0";
+/// 没有 testsuite_timeout= 时每个测试程序的时限
+const DEFAULT_TIMEOUT_SEC: usize = 10;
+/// 被杀死的进程如果阻塞在系统调用中, 收不到信号, 等这么久之后放弃
+const KILL_GRACE_MS: isize = 1000;
+/// 期望输出与测试程序在同一目录, 例如 brk 的期望输出是 brk.out
+const EXPECTED_SUFFIX: &str = ".out";
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// 测试程序的标准输出先写到这个文件, 结束后再打印出来.
+/// 杀不死的测试程序还占着原来的文件, 之后的测试换用下一个
+fn stdout_file(generation: usize) -> String {
+    if generation == 0 {
+        String::from("/testsuite.stdout\0")
+    } else {
+        format!("/testsuite.{}.stdout\0", generation)
+    }
+}
+
+/// 内核命令行中有 testsuite= 时运行测试, 否则启动 shell.
+/// 作为 init 返回后内核关机, 返回值不为 0 时以失败状态关机
 #[no_mangle]
 fn main() -> i32 {
-    let file: [&str; 5] =["fantastic_text\0","hello_world\0","matrix\0","sleep\0","exit\0"];
+    let cmdline = read_cmdline();
+    match param(&cmdline, "testsuite") {
+        Some(dir) => {
+            let dir = if dir == "auto" { "/" } else { dir };
+            let timeout = param(&cmdline, "testsuite_timeout")
+                .and_then(|sec| sec.parse().ok())
+                .unwrap_or(DEFAULT_TIMEOUT_SEC);
+            run_testsuite(dir, timeout * 1000)
+        }
+        None => run_shell(),
+    }
+}
 
-    for f in &file{
-        fe(f);
+fn read_cmdline() -> String {
+    let fd = open("/proc/cmdline\0", OpenFlags::RDONLY);
+    if fd < 0 {
+        return String::new();
     }
+    let mut buf = [0u8; 512];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let len = len.max(0) as usize;
+    String::from(core::str::from_utf8(&buf[..len]).unwrap_or(""))
+}
 
-    0
+/// 与内核一样, 同一个参数出现多次时以最后一次为准
+fn param<'a>(cmdline: &'a str, key: &str) -> Option<&'a str> {
+    cmdline
+        .split_whitespace()
+        .filter_map(|arg| arg.split_once('='))
+        .filter(|(name, _)| *name == key)
+        .map(|(_, value)| value)
+        .last()
 }
 
-fn fe(file: &str){
-    if fork()==0{
-        exec(file);
-    }else {
+/// 启动 shell 并回收所有子进程, shell 退出时返回它的退出码
+fn run_shell() -> i32 {
+    let shell = fork();
+    if shell == 0 {
+        exec(SHELL);
+        println!("[initproc] failed to run {}", SHELL.trim_end_matches('\0'));
+        exit(-1);
+    }
+    loop {
         let mut exit_code: i32 = 0;
         let pid = wait(&mut exit_code);
-        return;
+        if pid == shell {
+            return exit_code;
+        }
+        if pid < 0 {
+            return 0;
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Fail,
+    Timeout,
+}
+
+impl Status {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pass => "PASS",
+            Status::Fail => "FAIL",
+            Status::Timeout => "TIMEOUT",
+        }
+    }
+}
+
+/// 按文件名顺序运行 dir 中的每个 ELF, 输出的 TEST 与 TESTSUITE 行供 CI 解析
+fn run_testsuite(dir: &str, timeout_ms: usize) -> i32 {
+    println!("TESTSUITE BEGIN dir={} timeout={}ms", dir, timeout_ms);
+    let fd = open(&format!("{}\0", dir), OpenFlags::DIRECTORY);
+    let entries = if fd >= 0 {
+        let entries = read_dir(fd as usize);
+        close(fd as usize);
+        entries
+    } else {
+        None
+    };
+    let entries = match entries {
+        Some(entries) => entries,
+        None => {
+            println!("TESTSUITE ERROR cannot read {}", dir);
+            return 1;
+        }
+    };
+    let mut names: Vec<String> = entries
+        .into_iter()
+        .filter(|(name, d_type)| *d_type == DT_REG && is_elf(&join(dir, name)))
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+
+    let (mut passed, mut failed, mut timed_out) = (0, 0, 0);
+    let mut generation = 0;
+    for name in names.iter() {
+        let start = get_time();
+        let out = stdout_file(generation);
+        let (status, exit_code, alive) =
+            run_test(&join(dir, name), name, timeout_ms as isize, &out);
+        if alive {
+            generation += 1;
+        }
+        match status {
+            Status::Pass => passed += 1,
+            Status::Fail => failed += 1,
+            Status::Timeout => timed_out += 1,
+        }
+        println!(
+            "TEST {} {} exit={} time={}ms",
+            name,
+            status.as_str(),
+            exit_code,
+            get_time() - start
+        );
+    }
+    println!(
+        "TESTSUITE SUMMARY total={} pass={} fail={} timeout={}",
+        names.len(),
+        passed,
+        failed,
+        timed_out
+    );
+    println!("TESTSUITE END");
+    if passed == names.len() {
+        0
+    } else {
+        1
     }
-}
\ No newline at end of file
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+fn is_elf(path: &str) -> bool {
+    let fd = open(&format!("{}\0", path), OpenFlags::RDONLY);
+    if fd < 0 {
+        return false;
+    }
+    let mut magic = [0u8; 4];
+    let len = read(fd as usize, &mut magic);
+    close(fd as usize);
+    len == 4 && magic == ELF_MAGIC
+}
+
+/// 返回的第三项为 true 时测试程序没有退出, 仍然打开着 out_file
+fn run_test(path: &str, name: &str, timeout_ms: isize, out_file: &str) -> (Status, i32, bool) {
+    let out = open(
+        out_file,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    if out < 0 {
+        println!(
+            "[initproc] cannot create {}",
+            out_file.trim_end_matches('\0')
+        );
+        return (Status::Fail, -1, false);
+    }
+    let pid = fork();
+    if pid == 0 {
+        dup2(out as usize, 1);
+        close(out as usize);
+        let path = format!("{}\0", path);
+        let name = format!("{}\0", name);
+        execv(&path, &[name.as_ptr(), core::ptr::null()]);
+        exit(-1);
+    }
+    close(out as usize);
+
+    let start = get_time();
+    let mut killed_at = None;
+    let mut alive = false;
+    let mut exit_code: i32 = 0;
+    loop {
+        let ret = try_waitpid(pid as usize, &mut exit_code);
+        if ret == pid || ret == -1 {
+            break;
+        }
+        let now = get_time();
+        match killed_at {
+            None if now - start > timeout_ms => {
+                kill(pid as usize, SIGKILL);
+                killed_at = Some(now);
+            }
+            Some(killed_at) if now - killed_at > KILL_GRACE_MS => {
+                println!("[initproc] {} does not respond to SIGKILL", name);
+                alive = true;
+                break;
+            }
+            _ => {}
+        }
+        _yield();
+    }
+    let matched = check_output(path, out_file);
+    let status = if killed_at.is_some() {
+        Status::Timeout
+    } else if exit_code == 0 && matched {
+        Status::Pass
+    } else {
+        Status::Fail
+    };
+    (status, exit_code, alive)
+}
+
+/// 打印捕获的输出, 有期望输出文件时同时逐段比较
+fn check_output(path: &str, out_file: &str) -> bool {
+    let out = open(out_file, OpenFlags::RDONLY);
+    if out < 0 {
+        return false;
+    }
+    let expected = open(&format!("{}{}\0", path, EXPECTED_SUFFIX), OpenFlags::RDONLY);
+    let mut matched = true;
+    let mut buf = [0u8; 256];
+    let mut expected_buf = [0u8; 256];
+    loop {
+        let len = read(out as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        let len = len as usize;
+        write(1, &buf[..len]);
+        if expected >= 0 && matched {
+            let mut got = 0;
+            while got < len {
+                let n = read(expected as usize, &mut expected_buf[got..len]);
+                if n <= 0 {
+                    break;
+                }
+                got += n as usize;
+            }
+            matched = got == len && expected_buf[..len] == buf[..len];
+        }
+    }
+    if expected >= 0 {
+        // 期望输出比实际输出长
+        if matched && read(expected as usize, &mut expected_buf[..1]) > 0 {
+            matched = false;
+        }
+        close(expected as usize);
+    }
+    close(out as usize);
+    matched
+}
@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{_yield, close, ioctl, open, read, OpenFlags};
+
+const EVIOCGRAB: usize = 0x4004_4590;
+const EV_KEY: u16 = 1;
+const EV_REL: u16 = 2;
+const KEY_ESC: u16 = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct InputEvent {
+    tv_sec: i64,
+    tv_usec: i64,
+    event_type: u16,
+    code: u16,
+    value: i32,
+}
+
+/// 独占键盘并打印键盘和鼠标的事件, 按 Esc 退出
+#[no_mangle]
+pub fn main() -> i32 {
+    let keyboard = open("/dev/input/event0\0", OpenFlags::RDONLY | OpenFlags::NONBLOCK);
+    if keyboard < 0 {
+        println!("input_test: no input device");
+        return -1;
+    }
+    let keyboard = keyboard as usize;
+    let mouse = open("/dev/input/event1\0", OpenFlags::RDONLY | OpenFlags::NONBLOCK);
+    // 独占期间按键不会进入终端
+    ioctl(keyboard, EVIOCGRAB, 1);
+    println!("input_test: press Esc to quit");
+    let mut events = [InputEvent::default(); 8];
+    let buf = unsafe {
+        core::slice::from_raw_parts_mut(
+            events.as_mut_ptr() as *mut u8,
+            core::mem::size_of_val(&events),
+        )
+    };
+    let mut fds = [keyboard, 0];
+    let mut count = 1;
+    if mouse >= 0 {
+        fds[1] = mouse as usize;
+        count = 2;
+    }
+    loop {
+        for &fd in fds[..count].iter() {
+            let len = read(fd, buf);
+            if len <= 0 {
+                continue;
+            }
+            let n = len as usize / core::mem::size_of::<InputEvent>();
+            for event in events[..n].iter() {
+                match event.event_type {
+                    EV_KEY => {
+                        println!("key {} value {}", event.code, event.value);
+                        if event.code == KEY_ESC && event.value == 0 {
+                            ioctl(keyboard, EVIOCGRAB, 0);
+                            for &fd in fds[..count].iter() {
+                                close(fd);
+                            }
+                            return 0;
+                        }
+                    }
+                    EV_REL => println!("rel axis {} delta {}", event.code, event.value),
+                    _ => {}
+                }
+            }
+        }
+        _yield();
+    }
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::vec::Vec;
+use user_lib::{args, close, open, read_dir, OpenFlags, DT_DIR};
+
+/// 列出目录中的各项, 目录名后加 '/'. 没有参数时列出当前目录
+#[no_mangle]
+pub fn main() -> i32 {
+    let args = args();
+    let dirs = if args.len() < 2 {
+        &["."][..]
+    } else {
+        &args[1..]
+    };
+    let mut status = 0;
+    for dir in dirs.iter() {
+        if dirs.len() > 1 {
+            println!("{}:", dir);
+        }
+        let fd = open(&format!("{}\0", dir), OpenFlags::DIRECTORY);
+        let entries = if fd >= 0 {
+            let entries = read_dir(fd as usize);
+            close(fd as usize);
+            entries
+        } else {
+            None
+        };
+        let mut entries = match entries {
+            Some(entries) => entries,
+            None => {
+                println!("ls: cannot access {}", dir);
+                status = 1;
+                continue;
+            }
+        };
+        entries.sort();
+        let names: Vec<_> = entries
+            .iter()
+            .map(|(name, d_type)| {
+                if *d_type == DT_DIR {
+                    format!("{}/", name)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect();
+        println!("{}", names.join("  "));
+    }
+    status
+}
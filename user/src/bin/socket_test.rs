@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    accept, bind, close, connect, exit, fork, listen, read, recvfrom, sendto, shutdown_socket,
+    socket, waitpid, write, SockAddrIn, SockAddrUn, AF_INET, AF_UNIX, SHUT_WR, SOCK_DGRAM,
+    SOCK_STREAM,
+};
+
+const LOOPBACK: [u8; 4] = [127, 0, 0, 1];
+const MESSAGE: &[u8] = b"hello, socket!";
+
+/// 客户端发送 MESSAGE 并关闭写方向, 服务端原样写回
+fn echo_once(server: usize) {
+    let client = accept(server);
+    assert!(client >= 0);
+    let client = client as usize;
+    let mut buf = [0u8; 64];
+    loop {
+        let len = read(client, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        write(client, &buf[..len as usize]);
+    }
+    close(client);
+}
+
+fn check_echo(client: usize) {
+    assert_eq!(write(client, MESSAGE), MESSAGE.len() as isize);
+    shutdown_socket(client, SHUT_WR);
+    let mut buf = [0u8; 64];
+    let mut received = 0;
+    loop {
+        let len = read(client, &mut buf[received..]);
+        if len <= 0 {
+            break;
+        }
+        received += len as usize;
+    }
+    assert_eq!(&buf[..received], MESSAGE);
+}
+
+fn run_server_and_client(server: usize, client: impl FnOnce()) {
+    let pid = fork();
+    if pid == 0 {
+        client();
+        exit(0);
+    }
+    echo_once(server);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+fn tcp_test() {
+    let server = socket(AF_INET, SOCK_STREAM) as usize;
+    assert_eq!(bind(server, &SockAddrIn::new(LOOPBACK, 6000)), 0);
+    assert_eq!(listen(server, 1), 0);
+    run_server_and_client(server, || {
+        let client = socket(AF_INET, SOCK_STREAM) as usize;
+        assert_eq!(connect(client, &SockAddrIn::new(LOOPBACK, 6000)), 0);
+        check_echo(client);
+        close(client);
+    });
+    close(server);
+    println!("tcp loopback test passed!");
+}
+
+fn udp_test() {
+    let server = socket(AF_INET, SOCK_DGRAM) as usize;
+    assert_eq!(bind(server, &SockAddrIn::new(LOOPBACK, 6001)), 0);
+    let client = socket(AF_INET, SOCK_DGRAM) as usize;
+    assert_eq!(sendto(client, MESSAGE, &SockAddrIn::new(LOOPBACK, 6001)), MESSAGE.len() as isize);
+    let mut buf = [0u8; 64];
+    let mut from = SockAddrIn::default();
+    let len = recvfrom(server, &mut buf, &mut from);
+    assert_eq!(&buf[..len as usize], MESSAGE);
+    // 回复给发送方的临时端口
+    assert_eq!(sendto(server, &buf[..len as usize], &from), len);
+    let len = recvfrom(client, &mut buf, &mut from);
+    assert_eq!(&buf[..len as usize], MESSAGE);
+    assert_eq!(from.port(), 6001);
+    close(client);
+    close(server);
+    println!("udp loopback test passed!");
+}
+
+fn unix_test() {
+    let server = socket(AF_UNIX, SOCK_STREAM) as usize;
+    assert_eq!(bind(server, &SockAddrUn::new("/tmp/echo.sock")), 0);
+    assert_eq!(listen(server, 1), 0);
+    run_server_and_client(server, || {
+        let client = socket(AF_UNIX, SOCK_STREAM) as usize;
+        assert_eq!(connect(client, &SockAddrUn::new("/tmp/echo.sock")), 0);
+        check_echo(client);
+        close(client);
+    });
+    close(server);
+    println!("unix socket test passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    tcp_test();
+    udp_test();
+    unix_test();
+    0
+}
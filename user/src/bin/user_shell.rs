@@ -1,22 +1,45 @@
 #![no_std]
 #![no_main]
-#![allow(clippy::println_empty_string)]
 
 extern crate alloc;
 
 #[macro_use]
 extern crate user_lib;
 
-const LF: u8 = 0x0au8;
-const CR: u8 = 0x0du8;
-const DL: u8 = 0x7fu8;
-const BS: u8 = 0x08u8;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::iter::Peekable;
+use core::str::Chars;
+use user_lib::{
+    chdir, close, dup, dup2, execv, exit, fork, getcwd, getpid, kill, open, pipe, read, read_dir,
+    shutdown, tcgetattr, tcsetattr, tcsetpgrp, waitpid, waitpid_untraced, write, OpenFlags,
+    Termios, DT_DIR, ECHO, ICANON, ISIG, SIGCONT, SIGKILL,
+};
+
+const STDIN: usize = 0;
+const STDOUT: usize = 1;
+/// 历史记录最多保存的条数, 用户程序的堆只有 16KiB
+const HISTORY_LEN: usize = 32;
+/// 没有 export PATH 时查找程序的目录, 根目录中的测试程序也能直接运行
+const DEFAULT_PATH: &str = "/bin:.";
+const BUILTINS: &[&str] = &[
+    "cd", "echo", "exit", "export", "fg", "help", "history", "pwd", "shutdown",
+];
+
+const CTRL_A: u8 = 0x01;
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_E: u8 = 0x05;
+const CTRL_U: u8 = 0x15;
+const BACKSPACE: u8 = 0x08;
+const TAB: u8 = 0x09;
+const ESC: u8 = 0x1b;
+const DEL: u8 = 0x7f;
+
+/// 被 ^Z 停止的命令的退出码, 与 bash 相同为 128 + SIGTSTP
+const STOPPED_STATUS: i32 = 148;
 
-use alloc::string::String;
-use user_lib::console::getchar;
-use user_lib::{exec, fork, print_apps, shutdown, waitpid};
-const ENTER: u8 = 13;
-const BACKSPACE: u8 = 127;
 const MYOS_ASCII_ART: &str = r#"
 MMMMMMMM               MMMMMMMMYYYYYYY       YYYYYYY        OOOOOOOOO     SSSSSSSSSSSSSSS
 M:::::::M             M:::::::MY:::::Y       Y:::::Y      OO:::::::::OO SSS:::::::::::::S
@@ -37,81 +60,825 @@ MMMMMMMM               MMMMMMMM      YYYYYYYYYYYYY          OOOOOOOOO
 "#;
 
 #[no_mangle]
-pub fn main()->! {
-    logo!("{}",MYOS_ASCII_ART);
-    println!("");
-    print!("knifefire@knifefire-Legion-Y9000P-IAH7H:~ ");
-
-    let mut buffer = String::new();
+pub fn main() -> i32 {
+    logo!("{}", MYOS_ASCII_ART);
+    let mut shell = Shell::new();
     loop {
-        let mut line = read_line(&mut buffer);
-        println!("");
-        match line{
-            ENTER => {
-                process_command(buffer.as_ref());
-                println!("");
-                buffer.clear();
-                print!("knifefire@knifefire-Legion-Y9000P-IAH7H:~ ");
-            }
-            _ => {}
-        }
-    }
-}
-
-fn process_command(command: &str){
-    let trimmed_command = command.trim();
-    match trimmed_command {
-        "help" | "?" | "h" => {
-            println!("");
-            println!("available commands:");
-            println!("  list      dispaly apps");
-            println!("  run       run the built in app");
-            println!("  help      print this help message  (alias: h, ?)");
-            println!("  shutdown  shutdown the machine     (alias: sd, exit)");
-        }
-        "list" =>{
-            print_apps();
-        }
-        "shutdown" | "sd" => shutdown(),
-        "" => {}
-        _ => {
-            if !command.is_empty() {
-                let pid = fork();
-                if pid == 0{
-                    if exec(trimmed_command) == -1{
-                        println!("Error when executing!");
-                        return;
+        let prompt = format!("knifefire@knifefire-Legion-Y9000P-IAH7H:{}$ ", cwd());
+        match shell.read_line(&prompt) {
+            Some(line) => shell.run_line(&line),
+            None => {
+                println!("exit");
+                return shell.status;
+            }
+        }
+    }
+}
+
+fn cwd() -> String {
+    getcwd().unwrap_or_else(|| String::from("/"))
+}
+
+fn read_byte() -> Option<u8> {
+    let mut c = [0u8; 1];
+    if read(STDIN, &mut c) <= 0 {
+        None
+    } else {
+        Some(c[0])
+    }
+}
+
+/// 目录中的各项, 打不开时为空
+fn list_dir(path: &str) -> Vec<(String, u8)> {
+    let fd = open(&format!("{}\0", path), OpenFlags::DIRECTORY);
+    if fd < 0 {
+        return Vec::new();
+    }
+    let entries = read_dir(fd as usize).unwrap_or_default();
+    close(fd as usize);
+    entries
+}
+
+/// 别名换成内建命令的名字, 不是内建命令时返回 None
+fn builtin(name: &str) -> Option<&'static str> {
+    match name {
+        "?" | "h" => Some("help"),
+        "sd" => Some("shutdown"),
+        _ => BUILTINS.iter().find(|builtin| **builtin == name).copied(),
+    }
+}
+
+struct Shell {
+    /// export 设置的变量. 内核的 exec 还不能传递环境变量, 它们只用于 $NAME 展开与查找程序
+    vars: Vec<(String, String)>,
+    /// 上一条命令的退出码, 即 $?
+    status: i32,
+    history: Vec<String>,
+    /// 被 ^Z 停止的命令行与它的各个进程, fg 继续最后一个
+    jobs: Vec<(String, Vec<usize>)>,
+}
+
+impl Shell {
+    fn new() -> Self {
+        Self {
+            vars: Vec::from([
+                (String::from("PATH"), String::from(DEFAULT_PATH)),
+                (String::from("HOME"), String::from("/")),
+            ]),
+            status: 0,
+            history: Vec::new(),
+            jobs: Vec::new(),
+        }
+    }
+
+    fn var(&self, name: &str) -> &str {
+        self.vars
+            .iter()
+            .find(|(var, _)| var == name)
+            .map_or("", |(_, value)| value.as_str())
+    }
+
+    fn set_var(&mut self, name: &str, value: &str) {
+        match self.vars.iter_mut().find(|(var, _)| var == name) {
+            Some((_, old)) => *old = String::from(value),
+            None => self.vars.push((String::from(name), String::from(value))),
+        }
+    }
+
+    fn add_history(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() || self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        if self.history.len() == HISTORY_LEN {
+            self.history.remove(0);
+        }
+        self.history.push(String::from(line));
+    }
+}
+
+/// 终端的原始模式: 不回显、不按行缓冲、^C 不产生信号. 析构时恢复原来的设置
+struct RawMode(Termios);
+
+impl RawMode {
+    /// 标准输入不是终端时返回 None
+    fn enter() -> Option<Self> {
+        let mut termios = Termios::default();
+        if tcgetattr(STDIN, &mut termios) < 0 {
+            return None;
+        }
+        let mut raw = termios;
+        raw.c_lflag &= !(ICANON | ECHO | ISIG);
+        tcsetattr(STDIN, &raw);
+        Some(Self(termios))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        tcsetattr(STDIN, &self.0);
+    }
+}
+
+/// 正在编辑的一行, 只接受可打印的 ASCII 字符
+struct Editor<'a> {
+    prompt: &'a str,
+    line: Vec<u8>,
+    cursor: usize,
+}
+
+impl Editor<'_> {
+    /// 回到行首重画整行, 再把光标移回编辑位置
+    fn redraw(&self) {
+        let mut out = format!("\r{}", self.prompt).into_bytes();
+        out.extend_from_slice(&self.line);
+        out.extend_from_slice(b"\x1b[K");
+        let back = self.line.len() - self.cursor;
+        if back > 0 {
+            out.extend_from_slice(format!("\x1b[{}D", back).as_bytes());
+        }
+        write(STDOUT, &out);
+    }
+
+    fn insert(&mut self, text: &[u8]) {
+        for &c in text {
+            self.line.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+        self.redraw();
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.line.remove(self.cursor);
+            self.redraw();
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.line.len() {
+            self.line.remove(self.cursor);
+            self.redraw();
+        }
+    }
+
+    fn move_to(&mut self, cursor: usize) {
+        self.cursor = cursor;
+        self.redraw();
+    }
+
+    fn set(&mut self, line: &[u8]) {
+        self.line = line.to_vec();
+        self.cursor = line.len();
+        self.redraw();
+    }
+
+    fn text(&self) -> String {
+        self.line.iter().map(|&c| c as char).collect()
+    }
+}
+
+/// 方向键等功能键
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Delete,
+    Unknown,
+}
+
+/// 读 ESC 之后的 `[A`、`OH`、`3~` 等
+fn read_escape() -> Key {
+    match read_byte() {
+        Some(b'[') | Some(b'O') => {}
+        _ => return Key::Unknown,
+    }
+    match read_byte() {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(digit @ b'0'..=b'9') => {
+            // 读到 '~' 为止
+            while !matches!(read_byte(), Some(b'~') | None) {}
+            match digit {
+                b'1' | b'7' => Key::Home,
+                b'4' | b'8' => Key::End,
+                b'3' => Key::Delete,
+                _ => Key::Unknown,
+            }
+        }
+        _ => Key::Unknown,
+    }
+}
+
+impl Shell {
+    /// 读一行命令, 标准输入结束或在空行上按 ^D 时返回 None
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        let raw = match RawMode::enter() {
+            Some(raw) => raw,
+            None => {
+                print!("{}", prompt);
+                return read_plain_line();
+            }
+        };
+        let mut editor = Editor {
+            prompt,
+            line: Vec::new(),
+            cursor: 0,
+        };
+        editor.redraw();
+        // 浏览历史时保存正在编辑的内容
+        let mut history_pos = self.history.len();
+        let mut draft = Vec::new();
+        loop {
+            let c = match read_byte() {
+                Some(c) => c,
+                None if editor.line.is_empty() => return None,
+                None => break,
+            };
+            match c {
+                b'\r' | b'\n' => break,
+                CTRL_C => {
+                    print!("^C\n");
+                    return Some(String::new());
+                }
+                CTRL_D if editor.line.is_empty() => {
+                    print!("\n");
+                    return None;
+                }
+                CTRL_D => editor.delete(),
+                CTRL_A => editor.move_to(0),
+                CTRL_E => editor.move_to(editor.line.len()),
+                CTRL_U => {
+                    editor.line.drain(..editor.cursor);
+                    editor.move_to(0);
+                }
+                BACKSPACE | DEL => editor.backspace(),
+                TAB => self.complete(&mut editor),
+                ESC => match read_escape() {
+                    Key::Up if history_pos > 0 => {
+                        if history_pos == self.history.len() {
+                            draft = editor.line.clone();
+                        }
+                        history_pos -= 1;
+                        editor.set(self.history[history_pos].as_bytes());
                     }
-                    unreachable!();
-                }else {
-                    let mut exit_code: i32 = 0;
-                    let exit_pid = waitpid(pid as usize, &mut exit_code);
-                    assert_eq!(pid, exit_pid);
-                    println!("Shell: Process {} exited with code {}", pid, exit_code);
+                    Key::Down if history_pos < self.history.len() => {
+                        history_pos += 1;
+                        match self.history.get(history_pos) {
+                            Some(line) => editor.set(line.as_bytes()),
+                            None => editor.set(&draft),
+                        }
+                    }
+                    Key::Left if editor.cursor > 0 => editor.move_to(editor.cursor - 1),
+                    Key::Right if editor.cursor < editor.line.len() => {
+                        editor.move_to(editor.cursor + 1)
+                    }
+                    Key::Home => editor.move_to(0),
+                    Key::End => editor.move_to(editor.line.len()),
+                    Key::Delete => editor.delete(),
+                    _ => {}
+                },
+                0x20..=0x7e => editor.insert(&[c]),
+                _ => {}
+            }
+        }
+        print!("\n");
+        drop(raw);
+        let line = editor.text();
+        self.add_history(&line);
+        Some(line)
+    }
+
+    /// 补全光标前的词. 命令位置上同时补全内建命令与 /bin 中的程序,
+    /// 其他位置只补全文件名. 有多个候选且不能再补时列出它们
+    fn complete(&self, editor: &mut Editor) {
+        let is_separator = |c: &u8| matches!(c, b' ' | b'|' | b'<' | b'>');
+        let before = &editor.line[..editor.cursor];
+        let start = before.iter().rposition(is_separator).map_or(0, |i| i + 1);
+        let is_command = matches!(
+            before[..start].iter().rev().find(|&&c| c != b' '),
+            None | Some(b'|')
+        );
+        let word: String = before[start..].iter().map(|&c| c as char).collect();
+        let (dir, prefix) = match word.rfind('/') {
+            Some(i) => (&word[..=i], &word[i + 1..]),
+            None => ("", word.as_str()),
+        };
 
+        let mut candidates: Vec<String> = Vec::new();
+        for (name, d_type) in list_dir(if dir.is_empty() { "." } else { dir }) {
+            if name.starts_with(prefix) {
+                let suffix = if d_type == DT_DIR { '/' } else { ' ' };
+                candidates.push(format!("{}{}", name, suffix));
+            }
+        }
+        if is_command && dir.is_empty() {
+            let programs = list_dir("/bin").into_iter().map(|(name, _)| name);
+            let builtins = BUILTINS.iter().map(|name| name.to_string());
+            for name in builtins.chain(programs) {
+                if name.starts_with(prefix) {
+                    candidates.push(format!("{} ", name));
                 }
             }
         }
+        candidates.sort();
+        candidates.dedup();
+
+        let first = match candidates.first() {
+            Some(first) => first.as_bytes(),
+            None => return,
+        };
+        let common = candidates.iter().fold(first.len(), |len, candidate| {
+            first[..len]
+                .iter()
+                .zip(candidate.as_bytes())
+                .take_while(|(a, b)| a == b)
+                .count()
+        });
+        if common > prefix.len() {
+            let rest = first[prefix.len()..common].to_vec();
+            editor.insert(&rest);
+        } else if candidates.len() > 1 {
+            let names: Vec<&str> = candidates.iter().map(|name| name.trim_end()).collect();
+            print!("\n{}\n", names.join("  "));
+            editor.redraw();
+        }
     }
 }
-// Manage the cursor manually
-fn read_line(buffer: &mut String) -> u8 {
-    let mut cursor_position = buffer.len();
 
+/// 标准输入不是终端时逐字节读到换行为止
+fn read_plain_line() -> Option<String> {
+    let mut line = String::new();
     loop {
-        let ch = getchar() as u8;
-        if ch == ENTER {
-            return ENTER;
-        } else if ch == BACKSPACE {
-            if buffer.len() > 0 && cursor_position > 0 {
-                buffer.remove(cursor_position - 1);
-                cursor_position -= 1;
-                print!("\x08 \x08");
+        match read_byte() {
+            Some(b'\n') => return Some(line),
+            Some(c) => line.push(c as char),
+            None if line.is_empty() => return None,
+            None => return Some(line),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Token {
+    Word(String),
+    Pipe,
+    Input,
+    Output,
+    Append,
+}
+
+#[derive(Default)]
+struct Command {
+    argv: Vec<String>,
+    input: Option<String>,
+    /// (文件名, 是否追加)
+    output: Option<(String, bool)>,
+}
+
+impl Shell {
+    /// 分词并展开变量. 单引号中的内容原样保留, 双引号中只展开 $ 并处理 \" \\ \$
+    fn tokenize(&self, line: &str) -> Result<Vec<Token>, &'static str> {
+        let mut tokens = Vec::new();
+        let mut chars = line.chars().peekable();
+        let mut word = String::new();
+        // 引号中的空字符串也是一个参数
+        let mut in_word = false;
+        while let Some(c) = chars.next() {
+            match c {
+                ' ' | '\t' | '|' | '<' | '>' => {
+                    if in_word {
+                        tokens.push(Token::Word(core::mem::take(&mut word)));
+                        in_word = false;
+                    }
+                    match c {
+                        '|' => tokens.push(Token::Pipe),
+                        '<' => tokens.push(Token::Input),
+                        '>' if chars.peek() == Some(&'>') => {
+                            chars.next();
+                            tokens.push(Token::Append);
+                        }
+                        '>' => tokens.push(Token::Output),
+                        _ => {}
+                    }
+                }
+                '\'' => {
+                    in_word = true;
+                    loop {
+                        match chars.next() {
+                            Some('\'') => break,
+                            Some(c) => word.push(c),
+                            None => return Err("unterminated quote"),
+                        }
+                    }
+                }
+                '"' => {
+                    in_word = true;
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => match chars.peek() {
+                                Some(&c) if matches!(c, '"' | '\\' | '$') => {
+                                    chars.next();
+                                    word.push(c);
+                                }
+                                _ => word.push('\\'),
+                            },
+                            Some('$') => self.expand(&mut chars, &mut word),
+                            Some(c) => word.push(c),
+                            None => return Err("unterminated quote"),
+                        }
+                    }
+                }
+                '\\' => {
+                    in_word = true;
+                    if let Some(c) = chars.next() {
+                        word.push(c);
+                    }
+                }
+                '$' => {
+                    // 不在引号中的空变量不产生参数
+                    let len = word.len();
+                    self.expand(&mut chars, &mut word);
+                    in_word |= word.len() > len;
+                }
+                c => {
+                    in_word = true;
+                    word.push(c);
+                }
+            }
+        }
+        if in_word {
+            tokens.push(Token::Word(word));
+        }
+        Ok(tokens)
+    }
+
+    /// 展开 `$` 之后的 `?`、`NAME` 或 `{NAME}`, 其他情况保留 `$`
+    fn expand(&self, chars: &mut Peekable<Chars>, word: &mut String) {
+        let name = match chars.peek() {
+            Some('?') => {
+                chars.next();
+                word.push_str(&self.status.to_string());
+                return;
+            }
+            Some('{') => {
+                chars.next();
+                let mut name = String::new();
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                    name.push(c);
+                }
+                name
+            }
+            Some(&c) if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                name
+            }
+            _ => {
+                word.push('$');
+                return;
+            }
+        };
+        word.push_str(self.var(&name));
+    }
+}
+
+/// 按 `|` 分成多条命令, 并取出每条命令的重定向
+fn parse(tokens: Vec<Token>) -> Result<Vec<Command>, &'static str> {
+    let mut pipeline = Vec::new();
+    let mut command = Command::default();
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => command.argv.push(word),
+            Token::Pipe => {
+                if command.argv.is_empty() {
+                    return Err("syntax error near `|'");
+                }
+                pipeline.push(core::mem::take(&mut command));
+            }
+            Token::Input => match tokens.next() {
+                Some(Token::Word(file)) => command.input = Some(file),
+                _ => return Err("missing file name after `<'"),
+            },
+            Token::Output | Token::Append => {
+                let append = token == Token::Append;
+                match tokens.next() {
+                    Some(Token::Word(file)) => command.output = Some((file, append)),
+                    _ => return Err("missing file name after `>'"),
+                }
+            }
+        }
+    }
+    if command.argv.is_empty() {
+        if pipeline.is_empty() && command.input.is_none() && command.output.is_none() {
+            return Ok(pipeline);
+        }
+        return Err("missing command");
+    }
+    pipeline.push(command);
+    Ok(pipeline)
+}
+
+/// 在当前进程中打开重定向的文件, 失败时打印错误并返回 false
+fn redirect(command: &Command) -> bool {
+    if let Some(file) = &command.input {
+        let fd = open(&format!("{}\0", file), OpenFlags::RDONLY);
+        if fd < 0 {
+            println!("shell: {}: No such file", file);
+            return false;
+        }
+        dup2(fd as usize, STDIN);
+        close(fd as usize);
+    }
+    if let Some((file, append)) = &command.output {
+        let mode = if *append {
+            OpenFlags::APPEND
+        } else {
+            OpenFlags::TRUNC
+        };
+        let fd = open(
+            &format!("{}\0", file),
+            OpenFlags::CREATE | OpenFlags::WRONLY | mode,
+        );
+        if fd < 0 {
+            println!("shell: {}: cannot create", file);
+            return false;
+        }
+        dup2(fd as usize, STDOUT);
+        close(fd as usize);
+    }
+    true
+}
+
+impl Shell {
+    fn run_line(&mut self, line: &str) {
+        let pipeline = match self.tokenize(line).and_then(parse) {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                println!("shell: {}", err);
+                self.status = 2;
+                return;
             }
+        };
+        if pipeline.is_empty() {
+            return;
+        }
+        // 单独的内建命令在 shell 中运行, cd 与 export 才能生效
+        self.status = if pipeline.len() == 1 && builtin(&pipeline[0].argv[0]).is_some() {
+            let saved = (dup(STDIN), dup(STDOUT));
+            let status = if redirect(&pipeline[0]) {
+                self.run_builtin(&pipeline[0].argv)
+            } else {
+                1
+            };
+            dup2(saved.0 as usize, STDIN);
+            dup2(saved.1 as usize, STDOUT);
+            close(saved.0 as usize);
+            close(saved.1 as usize);
+            status
         } else {
-            buffer.insert(cursor_position, ch as char);
-            cursor_position += 1;
-            print!("{}", ch as char);
+            let pids = self.spawn_pipeline(&pipeline);
+            self.wait_job(line.trim(), pids)
+        };
+    }
+
+    /// 等待前台运行的命令, 期间由最后一条命令接收 ^C/^Z. 返回最后一条命令的退出码,
+    /// 它被 ^Z 停止时记下这条命令, 由 shell 重新接管终端
+    fn wait_job(&mut self, line: &str, pids: Vec<usize>) -> i32 {
+        let last = match pids.last() {
+            Some(&last) => last,
+            None => return 1,
+        };
+        tcsetpgrp(STDIN, last);
+        // 先等最后一条命令: 它停止时前面的命令可能正阻塞在管道上
+        let mut status: i32 = 0;
+        let stopped = waitpid_untraced(last, &mut status) == -3;
+        if !stopped {
+            for &pid in pids[..pids.len() - 1].iter() {
+                let mut exit_code: i32 = 0;
+                waitpid(pid, &mut exit_code);
+            }
+        }
+        tcsetpgrp(STDIN, getpid() as usize);
+        if stopped {
+            self.jobs.push((String::from(line), pids));
+            println!("[{}]+  Stopped                 {}", self.jobs.len(), line);
+            return STOPPED_STATUS;
         }
+        status
     }
-}
\ No newline at end of file
+
+    /// 每条命令一个子进程, 相邻的用管道连接. 返回各个子进程的 pid, fork 失败时返回空
+    fn spawn_pipeline(&mut self, pipeline: &[Command]) -> Vec<usize> {
+        let mut pids = Vec::new();
+        let mut prev_read: Option<usize> = None;
+        for (i, command) in pipeline.iter().enumerate() {
+            let next = if i + 1 < pipeline.len() {
+                let mut fds = [0u32; 2];
+                if pipe(&mut fds) < 0 {
+                    println!("shell: cannot create pipe");
+                    break;
+                }
+                Some((fds[0] as usize, fds[1] as usize))
+            } else {
+                None
+            };
+            let pid = fork();
+            if pid < 0 {
+                // 放弃整条管道, 已经启动的命令也一并结束
+                println!("shell: cannot fork");
+                if let Some((read_fd, write_fd)) = next {
+                    close(read_fd);
+                    close(write_fd);
+                }
+                if let Some(read_fd) = prev_read.take() {
+                    close(read_fd);
+                }
+                for pid in pids.drain(..) {
+                    let mut exit_code: i32 = 0;
+                    kill(pid, SIGKILL);
+                    waitpid(pid, &mut exit_code);
+                }
+                break;
+            }
+            if pid == 0 {
+                if let Some(read_fd) = prev_read {
+                    dup2(read_fd, STDIN);
+                    close(read_fd);
+                }
+                if let Some((read_fd, write_fd)) = next {
+                    dup2(write_fd, STDOUT);
+                    close(read_fd);
+                    close(write_fd);
+                }
+                self.run_child(command);
+            }
+            if let Some(read_fd) = prev_read.take() {
+                close(read_fd);
+            }
+            if let Some((read_fd, write_fd)) = next {
+                close(write_fd);
+                prev_read = Some(read_fd);
+            }
+            pids.push(pid as usize);
+        }
+        if let Some(read_fd) = prev_read {
+            close(read_fd);
+        }
+        pids
+    }
+
+    fn run_child(&mut self, command: &Command) -> ! {
+        let status = if !redirect(command) {
+            1
+        } else if builtin(&command.argv[0]).is_some() {
+            self.run_builtin(&command.argv)
+        } else {
+            self.exec(&command.argv)
+        };
+        exit(status);
+        unreachable!()
+    }
+
+    /// 名字中没有 '/' 时依次在 PATH 的各个目录中查找. 只在失败时返回
+    fn exec(&self, argv: &[String]) -> i32 {
+        let args: Vec<String> = argv.iter().map(|arg| format!("{}\0", arg)).collect();
+        let mut arg_ptrs: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+        arg_ptrs.push(core::ptr::null());
+        let name = &argv[0];
+        if name.contains('/') {
+            execv(&args[0], &arg_ptrs);
+        } else {
+            for dir in self.var("PATH").split(':').filter(|dir| !dir.is_empty()) {
+                execv(&format!("{}/{}\0", dir, name), &arg_ptrs);
+            }
+        }
+        println!("{}: command not found", name);
+        127
+    }
+
+    fn run_builtin(&mut self, argv: &[String]) -> i32 {
+        match builtin(&argv[0]).unwrap() {
+            "cd" => {
+                let dir = match argv.get(1) {
+                    Some(dir) => dir.clone(),
+                    None => String::from(self.var("HOME")),
+                };
+                if chdir(&format!("{}\0", dir)) < 0 {
+                    println!("cd: {}: No such directory", dir);
+                    return 1;
+                }
+                0
+            }
+            "pwd" => {
+                println!("{}", cwd());
+                0
+            }
+            "echo" => {
+                println!("{}", argv[1..].join(" "));
+                0
+            }
+            "export" => {
+                if argv.len() == 1 {
+                    for (name, value) in self.vars.iter() {
+                        println!("export {}={}", name, value);
+                    }
+                    return 0;
+                }
+                for arg in argv[1..].iter() {
+                    let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+                    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                    if !valid {
+                        println!("export: `{}': not a valid identifier", arg);
+                        return 1;
+                    }
+                    // 只写名字时保留原来的值
+                    if arg.contains('=') || self.var(name).is_empty() {
+                        self.set_var(name, value);
+                    }
+                }
+                0
+            }
+            "exit" => {
+                let code = match argv.get(1).map(|code| code.parse()) {
+                    None => self.status,
+                    Some(Ok(code)) => code,
+                    Some(Err(_)) => {
+                        println!("exit: {}: numeric argument required", argv[1]);
+                        2
+                    }
+                };
+                exit(code);
+                unreachable!()
+            }
+            "fg" => {
+                let (line, pids) = match self.jobs.pop() {
+                    Some(job) => job,
+                    None => {
+                        println!("fg: no current job");
+                        return 1;
+                    }
+                };
+                println!("{}", line);
+                kill(*pids.last().unwrap(), SIGCONT);
+                self.wait_job(&line, pids)
+            }
+            "history" => {
+                for (i, line) in self.history.iter().enumerate() {
+                    println!("{:>4}  {}", i + 1, line);
+                }
+                0
+            }
+            "shutdown" => {
+                shutdown();
+                0
+            }
+            _ => {
+                help();
+                0
+            }
+        }
+    }
+}
+
+fn help() {
+    println!("builtins:");
+    println!("  cd [dir]          change the current directory (default $HOME)");
+    println!("  pwd               print the current directory");
+    println!("  echo [args]       print the arguments");
+    println!("  export [NAME=VAL] set a variable, or list all of them");
+    println!("  fg                continue the last command stopped by ^Z");
+    println!("  history           list the command history");
+    println!("  exit [code]       leave the shell (default $?)");
+    println!("  shutdown          shutdown the machine     (alias: sd)");
+    println!("  help              print this help message  (alias: h, ?)");
+    println!("syntax:");
+    println!("  cmd | cmd   < in   > out   >> out   'literal'   \"$NAME $?\"   \\c");
+    println!("keys:");
+    println!("  up/down history, left/right/home/end move, tab complete");
+    println!("  ^U clear the line, ^C cancel, ^D exit, ^Z stop the running command");
+    let mut programs: Vec<String> = list_dir("/bin").into_iter().map(|(name, _)| name).collect();
+    programs.sort();
+    println!("programs in /bin:");
+    println!("  {}", programs.join("  "));
+}
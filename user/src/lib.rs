@@ -3,10 +3,14 @@
 #![feature(alloc_error_handler)]
 #![feature(panic_info_message)]
 
+extern crate alloc;
+
 mod lang_items;
 mod syscall;
 pub mod console;
 
+use alloc::string::String;
+use alloc::vec::Vec;
 use bitflags::bitflags;
 use buddy_system_allocator::LockedHeap;
 use syscall::*;
@@ -24,17 +28,38 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
 }
 
 
+static mut ARGC: usize = 0;
+static mut ARGV: usize = 0;
+
+/// 内核在 a0、a1 中传入 argc 与 argv
 #[no_mangle]
 #[link_section=".text.entry"]
-pub extern "C" fn start_main(){
+pub extern "C" fn start_main(argc: usize, argv: usize){
     clear_bss();
     unsafe {
+        ARGC = argc;
+        ARGV = argv;
         HEAP.lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
     }
     exit(main());
 }
 
+/// 命令行参数, 第一项是程序名
+pub fn args() -> Vec<&'static str> {
+    let (argc, argv) = unsafe { (ARGC, ARGV) };
+    (0..argc)
+        .map(|i| unsafe {
+            let arg = *(argv as *const *const u8).add(i);
+            let mut len = 0;
+            while *arg.add(len) != 0 {
+                len += 1;
+            }
+            core::str::from_utf8(core::slice::from_raw_parts(arg, len)).unwrap_or("")
+        })
+        .collect()
+}
+
 fn clear_bss() {
     extern "C" {
         fn start_bss();
@@ -52,7 +77,7 @@ fn main() -> i32 {
     panic!("Cannot find main!");
 }
 
-use crate::syscall::{sys_exec, sys_exit, sys_fork, sys_get_time, sys_getpid, sys_read, sys_sbrk, sys_waitpid, sys_write, sys_yield};
+use crate::syscall::{sys_exec, sys_exit, sys_fork, sys_get_time, sys_getpid, sys_kill, sys_read, sys_sbrk, sys_waitpid, sys_write, sys_yield};
 
 pub fn exit(exit_code:i32)->isize{
     sys_exit(exit_code)
@@ -60,8 +85,18 @@ pub fn exit(exit_code:i32)->isize{
 pub fn _yield() -> isize {
     sys_yield()
 }
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+/// 开机以来的毫秒数, 修改系统时间不会影响计时
 pub fn get_time() -> isize {
-    sys_get_time()
+    let mut ts = TimeSpec::default();
+    sys_get_time(&mut ts);
+    (ts.tv_sec * 1000 + ts.tv_nsec / 1_000_000) as isize
 }
 
 pub fn sbrk(size: i32) -> isize {
@@ -73,13 +108,24 @@ pub fn getpid() -> isize {
 pub fn fork() -> isize {
     sys_fork()
 }
+/// path 需要以 '\0' 结尾, 程序的 argv 只有 path 自己.
+/// 与 execvp 类似, 相对路径找不到时再到 /bin 中找
 pub fn exec(path: &str) -> isize {
-    sys_exec(path)
+    let ret = sys_exec(path, &[path.as_ptr(), core::ptr::null()]);
+    if ret != -1 || path.starts_with('/') {
+        return ret;
+    }
+    let in_bin = alloc::format!("/bin/{}", path);
+    sys_exec(&in_bin, &[in_bin.as_ptr(), core::ptr::null()])
+}
+/// args 中的字符串以 '\0' 结尾, 最后一项是空指针
+pub fn execv(path: &str, args: &[*const u8]) -> isize {
+    sys_exec(path, args)
 }
 
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
+        match sys_waitpid(-1, exit_code as *mut _, 0) {
             -2 => {
                 _yield();
             }
@@ -91,7 +137,7 @@ pub fn wait(exit_code: &mut i32) -> isize {
 
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
+        match sys_waitpid(pid as isize, exit_code as *mut _, 0) {
             -2 => {
                 _yield();
             }
@@ -100,39 +146,296 @@ pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
         }
     }
 }
+/// 子进程还在运行时返回 -2, 不等待
+pub fn try_waitpid(pid: usize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid as isize, exit_code as *mut _, 0)
+}
+
+const WUNTRACED: u32 = 2;
+
+/// 与 waitpid 相同, 但子进程收到 SIGTSTP 停止时返回 -3, 之后用 SIGCONT 让它继续
+pub fn waitpid_untraced(pid: usize, exit_code: &mut i32) -> isize {
+    loop {
+        match sys_waitpid(pid as isize, exit_code as *mut _, WUNTRACED) {
+            -2 => {
+                _yield();
+            }
+            // -1, -3 or a real pid
+            exit_pid => return exit_pid,
+        }
+    }
+}
+/// 内核中的信号以位表示, 与 Linux 的信号编号不同
+pub const SIGINT: u32 = 1 << 2;
+pub const SIGKILL: u32 = 1 << 9;
+pub const SIGCONT: u32 = 1 << 18;
+pub const SIGTSTP: u32 = 1 << 20;
+
+pub fn kill(pid: usize, signal: u32) -> isize {
+    sys_kill(pid, signal)
+}
 pub fn sleep(period_ms: usize) {
-    let start = sys_get_time();
-    while sys_get_time() < start + period_ms as isize {
+    let start = get_time();
+    while get_time() < start + period_ms as isize {
         sys_yield();
     }
 }
 pub fn shutdown(){
     sys_shutdown();
 }
-pub fn print_apps(){
-    sys_print_apps();
+/// fds[0] 为读端, fds[1] 为写端
+pub fn pipe(fds: &mut [u32; 2]) -> isize {
+    sys_pipe(fds)
+}
+/// 把内核块缓存中的脏块写回磁盘
+pub fn sync() -> isize {
+    sys_sync()
+}
+pub fn fsync(fd: usize) -> isize {
+    sys_fsync(fd)
+}
+/// path 需要以 '\0' 结尾
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path)
+}
+/// 当前目录的绝对路径
+pub fn getcwd() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if sys_getcwd(&mut buf) < 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    core::str::from_utf8(&buf[..len]).ok().map(String::from)
 }
 bitflags! {
     pub struct OpenFlags: u32 {
         const RDONLY = 0;
         const WRONLY = 1 << 0;
         const RDWR = 1 << 1;
-        const CREATE = 1 << 9;
-        const TRUNC = 1 << 10;
+        const CREATE = 0x40;
+        const EXCL = 0x80;
+        const TRUNC = 0x200;
+        const APPEND = 0x400;
+        const NONBLOCK = 0x800;
+        const DIRECTORY = 0x0200000;
     }
 }
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_open(path, flags.bits)
 }
+pub fn ftruncate(fd: usize, length: usize) -> isize {
+    sys_ftruncate(fd, length)
+}
 pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
+pub fn dup(fd: usize) -> isize {
+    sys_dup(fd)
+}
+/// new_fd 已经打开时先关闭它. 两者相同时与 POSIX 一样, old_fd 有效就返回它
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    if old_fd == new_fd {
+        let fd = sys_dup(old_fd);
+        if fd < 0 {
+            return -1;
+        }
+        sys_close(fd as usize);
+        return old_fd as isize;
+    }
+    sys_dup3(old_fd, new_fd, 0)
+}
+
+/// getdents64 返回的目录项类型
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+
+/// 读出目录中的各项 (文件名, 类型), 不包括 . 和 ..
+pub fn read_dir(fd: usize) -> Option<Vec<(String, u8)>> {
+    let mut entries = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = sys_getdents64(fd, &mut buf);
+        if len < 0 {
+            return None;
+        }
+        if len == 0 {
+            return Some(entries);
+        }
+        // linux_dirent64: d_ino u64, d_off i64, d_reclen u16, d_type u8, d_name
+        let mut pos = 0;
+        while pos < len as usize {
+            let reclen = u16::from_le_bytes([buf[pos + 16], buf[pos + 17]]) as usize;
+            let d_type = buf[pos + 18];
+            let name = &buf[pos + 19..pos + reclen];
+            let name_len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            let name = core::str::from_utf8(&name[..name_len]).unwrap_or("");
+            if name != "." && name != ".." {
+                entries.push((String::from(name), d_type));
+            }
+            pos += reclen;
+        }
+    }
+}
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
-pub fn get_char(fd: usize, buf: &mut [u8])->isize{
-    sys_getchar(fd, buf)
-}
\ No newline at end of file
+
+pub const TIOCGPGRP: usize = 0x540F;
+pub const TIOCSPGRP: usize = 0x5410;
+
+pub fn ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    sys_ioctl(fd, request, arg)
+}
+/// 设置终端的前台进程, ^C/^Z 产生的信号会发给它
+pub fn tcsetpgrp(fd: usize, pid: usize) -> isize {
+    let pgrp = pid as i32;
+    sys_ioctl(fd, TIOCSPGRP, &pgrp as *const i32 as usize)
+}
+
+pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+/// c_lflag 中的位
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+
+/// 与内核的 struct termios 布局相同
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_line: u8,
+    pub c_cc: [u8; 19],
+}
+
+pub fn tcgetattr(fd: usize, termios: &mut Termios) -> isize {
+    sys_ioctl(fd, TCGETS, termios as *mut Termios as usize)
+}
+pub fn tcsetattr(fd: usize, termios: &Termios) -> isize {
+    sys_ioctl(fd, TCSETS, termios as *const Termios as usize)
+}
+
+pub const AF_UNIX: usize = 1;
+pub const AF_INET: usize = 2;
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+pub const SOL_SOCKET: usize = 1;
+pub const SO_REUSEADDR: usize = 2;
+pub const SHUT_WR: usize = 1;
+
+/// 可以传给 bind/connect 的地址结构
+pub trait SockAddr: Sized {
+    fn addr_ptr(&self) -> *const u8 {
+        self as *const Self as *const u8
+    }
+    fn addr_len(&self) -> usize {
+        core::mem::size_of::<Self>()
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SockAddrIn {
+    pub family: u16,
+    /// 网络字节序
+    pub port: u16,
+    pub addr: [u8; 4],
+    pub zero: [u8; 8],
+}
+
+impl SockAddrIn {
+    pub fn new(addr: [u8; 4], port: u16) -> Self {
+        Self {
+            family: AF_INET as u16,
+            port: port.to_be(),
+            addr,
+            zero: [0; 8],
+        }
+    }
+    pub fn port(&self) -> u16 {
+        u16::from_be(self.port)
+    }
+}
+
+impl SockAddr for SockAddrIn {}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SockAddrUn {
+    pub family: u16,
+    pub path: [u8; 108],
+}
+
+impl SockAddrUn {
+    pub fn new(path: &str) -> Self {
+        let mut addr = Self {
+            family: AF_UNIX as u16,
+            path: [0; 108],
+        };
+        let len = path.len().min(107);
+        addr.path[..len].copy_from_slice(&path.as_bytes()[..len]);
+        addr
+    }
+}
+
+impl SockAddr for SockAddrUn {}
+
+pub fn socket(domain: usize, socket_type: usize) -> isize {
+    sys_socket(domain, socket_type, 0)
+}
+pub fn bind<T: SockAddr>(fd: usize, addr: &T) -> isize {
+    sys_bind(fd, addr.addr_ptr(), addr.addr_len())
+}
+pub fn listen(fd: usize, backlog: usize) -> isize {
+    sys_listen(fd, backlog)
+}
+/// 不关心对端地址时使用
+pub fn accept(fd: usize) -> isize {
+    sys_accept(fd, core::ptr::null_mut(), core::ptr::null_mut())
+}
+pub fn connect<T: SockAddr>(fd: usize, addr: &T) -> isize {
+    sys_connect(fd, addr.addr_ptr(), addr.addr_len())
+}
+pub fn sendto(fd: usize, buf: &[u8], addr: &SockAddrIn) -> isize {
+    sys_sendto(fd, buf, 0, addr.addr_ptr(), addr.addr_len())
+}
+pub fn recvfrom(fd: usize, buf: &mut [u8], addr: &mut SockAddrIn) -> isize {
+    let mut len = core::mem::size_of::<SockAddrIn>() as u32;
+    sys_recvfrom(fd, buf, 0, addr as *mut SockAddrIn as *mut u8, &mut len)
+}
+pub fn setsockopt(fd: usize, level: usize, name: usize, value: i32) -> isize {
+    sys_setsockopt(fd, level, name, &value as *const i32 as *const u8, 4)
+}
+pub fn shutdown_socket(fd: usize, how: usize) -> isize {
+    sys_socket_shutdown(fd, how)
+}
+
+pub const PROT_READ: usize = 1;
+pub const PROT_WRITE: usize = 2;
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// 成功时返回映射的起始地址
+pub fn mmap(len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    sys_mmap(0, len, prot, flags, fd, offset)
+}
+pub fn munmap(addr: usize, len: usize) -> isize {
+    sys_munmap(addr, len)
+}
+
+pub const SYSLOG_ACTION_READ_ALL: usize = 3;
+pub const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+pub const SYSLOG_ACTION_CLEAR: usize = 5;
+pub const SYSLOG_ACTION_CONSOLE_LEVEL: usize = 8;
+
+/// 读取内核日志缓冲区, 返回读到的字节数
+pub fn syslog(action: usize, buf: &mut [u8]) -> isize {
+    sys_syslog(action, buf.as_mut_ptr(), buf.len())
+}
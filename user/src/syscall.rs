@@ -3,18 +3,39 @@ use core::arch::asm;
 const SYS_WRITE: usize = 64;
 const SYS_EXIT: usize = 93;
 const SYS_YIELD: usize = 124;
-const SYSCALL_GET_TIME: usize = 169;
+const SYS_SYSLOG: usize = 116;
+const SYS_CLOCK_GETTIME: usize = 113;
 const SYS_SBRK: usize = 214;
 const SYS_GETPID: usize = 172;
 const SYS_FORK: usize = 220;
 const SYS_EXEC: usize = 221;
 const SYS_WAITPID: usize = 260;
-const SYT_SHUTDOWN: usize = 48;
-const SYS_PRINT_APPS: usize = 100;
+const SYS_REBOOT: usize = 142;
+const SYS_KILL: usize = 129;
+const SYS_DUP: usize = 23;
+const SYS_DUP3: usize = 24;
+const SYS_GETDENTS64: usize = 61;
+const SYS_GETCWD: usize = 17;
+const SYS_CHDIR: usize = 49;
+const SYS_PIPE: usize = 59;
+const SYS_SYNC: usize = 81;
+const SYS_FSYNC: usize = 82;
+const SYS_FTRUNCATE: usize = 46;
 const SYS_OPEN: usize = 56;
 const SYS_CLOSE: usize = 57;
 const SYS_READ: usize = 63;
-const SYS_GETCHAR: usize = 520;
+const SYS_IOCTL: usize = 29;
+const SYS_SOCKET: usize = 198;
+const SYS_BIND: usize = 200;
+const SYS_LISTEN: usize = 201;
+const SYS_ACCEPT: usize = 202;
+const SYS_CONNECT: usize = 203;
+const SYS_SENDTO: usize = 206;
+const SYS_RECVFROM: usize = 207;
+const SYS_SETSOCKOPT: usize = 208;
+const SYS_SHUTDOWN: usize = 210;
+const SYS_MUNMAP: usize = 215;
+const SYS_MMAP: usize = 222;
 
 
 pub fn syscall(sys_id:usize, arg:[usize;3])->isize{
@@ -29,6 +50,21 @@ pub fn syscall(sys_id:usize, arg:[usize;3])->isize{
     ret
 }
 
+pub fn syscall6(sys_id: usize, arg: [usize; 6]) -> isize {
+    let mut ret;
+    unsafe { asm!(
+        "ecall",
+        inlateout("a0") arg[0] => ret,
+        in("a1") arg[1],
+        in("a2") arg[2],
+        in("a3") arg[3],
+        in("a4") arg[4],
+        in("a5") arg[5],
+        in("a7") sys_id,
+    ) }
+    ret
+}
+
 
 pub fn sys_exit(exit_code: i32) ->isize{
     syscall(SYS_EXIT,[exit_code as usize,0,0])
@@ -37,8 +73,11 @@ pub fn sys_exit(exit_code: i32) ->isize{
 pub fn sys_yield()->isize{
     syscall(SYS_YIELD,[0,0,0])
 }
-pub fn sys_get_time() -> isize {
-    syscall(SYSCALL_GET_TIME, [0, 0, 0])
+const CLOCK_MONOTONIC: usize = 1;
+
+/// 开机以来的时间, 不受 settimeofday 影响
+pub fn sys_get_time(ts: &mut crate::TimeSpec) -> isize {
+    syscall(SYS_CLOCK_GETTIME, [CLOCK_MONOTONIC, ts as *mut _ as usize, 0])
 }
 pub fn sys_sbrk(size: i32) -> isize {
     syscall(SYS_SBRK, [size as usize, 0, 0])
@@ -52,18 +91,39 @@ pub fn sys_fork() -> isize {
     syscall(SYS_FORK, [0, 0, 0])
 }
 
-pub fn sys_exec(path: &str) -> isize {
-    syscall(SYS_EXEC, [path.as_ptr() as usize, 0, 0])
+pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
+    syscall(SYS_EXEC, [path.as_ptr() as usize, args.as_ptr() as usize, 0])
 }
 
-pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
-    syscall(SYS_WAITPID, [pid as usize, exit_code as usize, 0])
+pub fn sys_waitpid(pid: isize, exit_code: *mut i32, options: u32) -> isize {
+    syscall(SYS_WAITPID, [pid as usize, exit_code as usize, options as usize])
 }
+const LINUX_REBOOT_MAGIC1: usize = 0xfee1dead;
+const LINUX_REBOOT_MAGIC2: usize = 672274793;
+const LINUX_REBOOT_CMD_POWER_OFF: usize = 0x4321fedc;
+
 pub fn sys_shutdown(){
-    syscall(SYT_SHUTDOWN,[0,0,0]);
+    syscall(SYS_REBOOT,[LINUX_REBOOT_MAGIC1,LINUX_REBOOT_MAGIC2,LINUX_REBOOT_CMD_POWER_OFF]);
+}
+pub fn sys_kill(pid: usize, signal: u32) -> isize {
+    syscall(SYS_KILL, [pid, signal as usize, 0])
 }
-pub fn sys_print_apps(){
-    syscall(SYS_PRINT_APPS,[0,0,0]);
+pub fn sys_getcwd(buf: &mut [u8]) -> isize {
+    syscall(SYS_GETCWD, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
+pub fn sys_chdir(path: &str) -> isize {
+    syscall(SYS_CHDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_pipe(fds: &mut [u32; 2]) -> isize {
+    syscall(SYS_PIPE, [fds.as_mut_ptr() as usize, 0, 0])
+}
+pub fn sys_sync() -> isize {
+    syscall(SYS_SYNC, [0, 0, 0])
+}
+pub fn sys_fsync(fd: usize) -> isize {
+    syscall(SYS_FSYNC, [fd, 0, 0])
 }
 
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
@@ -76,17 +136,86 @@ pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
 pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYS_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
+const AT_FDCWD: isize = -100;
+
 pub fn sys_open(path: &str, flags: u32) -> isize {
-    syscall(SYS_OPEN, [path.as_ptr() as usize, flags as usize, 0])
+    syscall(SYS_OPEN, [AT_FDCWD as usize, path.as_ptr() as usize, flags as usize])
+}
+
+pub fn sys_ftruncate(fd: usize, length: usize) -> isize {
+    syscall(SYS_FTRUNCATE, [fd, length, 0])
 }
 
 pub fn sys_close(fd: usize) -> isize {
     syscall(SYS_CLOSE, [fd, 0, 0])
 }
 
-pub fn sys_getchar(fd: usize, buffer: &mut [u8]) -> isize {
-    syscall(
-        SYS_GETCHAR,
-        [fd, buffer.as_mut_ptr() as usize, buffer.len()],
+pub fn sys_dup(fd: usize) -> isize {
+    syscall(SYS_DUP, [fd, 0, 0])
+}
+
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    syscall(SYS_DUP3, [old_fd, new_fd, flags as usize])
+}
+
+pub fn sys_getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    syscall(SYS_GETDENTS64, [fd, buf.as_mut_ptr() as usize, buf.len()])
+}
+
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    syscall(SYS_IOCTL, [fd, request, arg])
+}
+
+pub fn sys_socket(domain: usize, socket_type: usize, protocol: usize) -> isize {
+    syscall(SYS_SOCKET, [domain, socket_type, protocol])
+}
+
+pub fn sys_bind(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall(SYS_BIND, [fd, addr as usize, addrlen])
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    syscall(SYS_LISTEN, [fd, backlog, 0])
+}
+
+pub fn sys_accept(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    syscall(SYS_ACCEPT, [fd, addr as usize, addrlen as usize])
+}
+
+pub fn sys_connect(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall(SYS_CONNECT, [fd, addr as usize, addrlen])
+}
+
+pub fn sys_sendto(fd: usize, buf: &[u8], flags: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall6(
+        SYS_SENDTO,
+        [fd, buf.as_ptr() as usize, buf.len(), flags, addr as usize, addrlen],
     )
-}
\ No newline at end of file
+}
+
+pub fn sys_recvfrom(fd: usize, buf: &mut [u8], flags: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    syscall6(
+        SYS_RECVFROM,
+        [fd, buf.as_mut_ptr() as usize, buf.len(), flags, addr as usize, addrlen as usize],
+    )
+}
+
+pub fn sys_setsockopt(fd: usize, level: usize, name: usize, value: *const u8, len: usize) -> isize {
+    syscall6(SYS_SETSOCKOPT, [fd, level, name, value as usize, len, 0])
+}
+
+pub fn sys_socket_shutdown(fd: usize, how: usize) -> isize {
+    syscall(SYS_SHUTDOWN, [fd, how, 0])
+}
+
+pub fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    syscall6(SYS_MMAP, [addr, len, prot, flags, fd, offset])
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    syscall(SYS_MUNMAP, [addr, len, 0])
+}
+
+pub fn sys_syslog(action: usize, buf: *mut u8, len: usize) -> isize {
+    syscall(SYS_SYSLOG, [action, buf as usize, len])
+}